@@ -0,0 +1,179 @@
+use backend::{
+    IncrementalBackend, LogosParserState, NomParserState, count_function_calls,
+    parse_incremental_delta, parse_python, parse_python_borrowed, parse_python_with_nom,
+};
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Counts allocations so the borrowed parser's savings can be reported alongside its time
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn count_allocations<T>(f: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    black_box(f());
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+const ENGINES: [&str; 2] = ["nom", "logos"];
+
+fn parse(engine: &str, source: &str) -> usize {
+    match engine {
+        "nom" => parse_python_with_nom(source).map_or(0, |calls| calls.len()),
+        _ => parse_python(source).map_or(0, |calls| calls.len()),
+    }
+}
+
+fn incremental_backend(engine: &str) -> Box<dyn IncrementalBackend> {
+    match engine {
+        "nom" => Box::new(NomParserState::new()),
+        _ => Box::new(LogosParserState::new()),
+    }
+}
+
+fn call(i: usize) -> String {
+    format!(
+        r#"search_hotels(location="City {i}", check_in_date="2024-05-20", budget_max_per_night={i}.5, guest_count=2, amenities=["wifi", "pool"], refundable=True)"#
+    )
+}
+
+fn small_call() -> String {
+    r#"<|python_start|>[get_weather(location="Tokyo", days=7)]<|python_end|>"#.to_string()
+}
+
+fn call_list(n: usize) -> String {
+    let calls: Vec<String> = (0..n).map(call).collect();
+    format!("<|python_start|>[{}]<|python_end|>", calls.join(", "))
+}
+
+// Roughly 50KB of prose full of brackets that are not tool calls, with a single real
+// call at the end. This is the input that exposes quadratic rescanning.
+fn stray_brackets() -> String {
+    let filler = "See [the docs](https://example.com) and note [1], [a, b] or x[0]. ";
+    let mut source = filler.repeat(50 * 1024 / filler.len());
+    source.push_str(&format!("[{}]", call(0)));
+    source
+}
+
+// Split a 50-call block into 200 roughly equal chunks on char boundaries
+fn stream_chunks() -> Vec<String> {
+    let source: Vec<char> = call_list(50).chars().collect();
+    let chunk_len = source.len().div_ceil(200);
+    source
+        .chunks(chunk_len)
+        .map(|chunk| chunk.iter().collect())
+        .collect()
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let inputs = [
+        ("small_call", small_call()),
+        ("list_50_calls", call_list(50)),
+        ("stray_brackets_50kb", stray_brackets()),
+    ];
+
+    for (name, source) in &inputs {
+        let mut group = c.benchmark_group(*name);
+        for engine in ENGINES {
+            group.bench_with_input(BenchmarkId::from_parameter(engine), source, |b, source| {
+                b.iter(|| parse(engine, black_box(source)))
+            });
+        }
+        group.finish();
+    }
+}
+
+fn bench_streaming(c: &mut Criterion) {
+    let chunks = stream_chunks();
+
+    let mut group = c.benchmark_group("streaming_200_chunks");
+    for engine in ENGINES {
+        group.bench_with_input(BenchmarkId::from_parameter(engine), &chunks, |b, chunks| {
+            b.iter(|| {
+                let mut backend = incremental_backend(engine);
+                for chunk in chunks {
+                    let _ = backend.feed(black_box(chunk));
+                }
+                backend.finish().map_or(0, |calls| calls.len())
+            })
+        });
+    }
+    group.finish();
+}
+
+// Regression guard for rescanning the whole buffer on every chunk: a 100-call list fed one
+// character at a time should scale linearly with its length
+fn bench_streaming_single_chars(c: &mut Criterion) {
+    let mut group = c.benchmark_group("streaming_1_char_chunks");
+    for n in [25, 100] {
+        let chunks: Vec<String> = call_list(n).chars().map(String::from).collect();
+        group.bench_with_input(BenchmarkId::new("nom", n), &chunks, |b, chunks| {
+            b.iter(|| {
+                let mut state = NomParserState::new();
+                for chunk in chunks {
+                    let _ = parse_incremental_delta(&mut state, black_box(chunk));
+                }
+                state.parsed_functions.len()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_borrowed(c: &mut Criterion) {
+    let source = call_list(50);
+    println!(
+        "list_50_calls allocations: owned {}, borrowed {}",
+        count_allocations(|| parse_python_with_nom(&source)),
+        count_allocations(|| parse_python_borrowed(&source)),
+    );
+
+    let mut group = c.benchmark_group("borrowed_50_calls");
+    group.bench_function("owned", |b| {
+        b.iter(|| parse_python_with_nom(black_box(&source)).map_or(0, |calls| calls.len()))
+    });
+    group.bench_function("borrowed", |b| {
+        b.iter(|| parse_python_borrowed(black_box(&source)).map_or(0, |calls| calls.len()))
+    });
+    group.finish();
+}
+
+fn bench_count(c: &mut Criterion) {
+    let source = call_list(50);
+
+    let mut group = c.benchmark_group("count_50_calls");
+    group.bench_function("count_function_calls", |b| {
+        b.iter(|| count_function_calls(black_box(&source)))
+    });
+    group.bench_function("parse_python_len", |b| {
+        b.iter(|| parse_python(black_box(&source)).map_or(0, |calls| calls.len()))
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_parse,
+    bench_streaming,
+    bench_streaming_single_chars,
+    bench_borrowed,
+    bench_count
+);
+criterion_main!(benches);
@@ -0,0 +1,91 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::{FunctionCall, Value};
+
+/// A value that borrows from the source it was parsed from: identifiers, and strings and
+/// bytes without escapes, are slices of it, so parsing them allocates nothing. The nom
+/// engine parses into these, so only the values it produces appear.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueRef<'a> {
+    Bool(bool),
+    Number(f64),
+    /// Owned only when escapes had to be resolved
+    String(Cow<'a, str>),
+    Bytes(Cow<'a, [u8]>),
+    Identifier(&'a str),
+    Null,
+    Ellipsis,
+    List(Vec<ValueRef<'a>>),
+    Tuple(Vec<ValueRef<'a>>),
+    Set(Vec<ValueRef<'a>>),
+    DictSplat(&'a str),
+    ListSplat(&'a str),
+}
+
+impl ValueRef<'_> {
+    /// Copy the value out of the source into a `Value`.
+    pub fn to_owned(&self) -> Value {
+        self.clone().into_owned()
+    }
+
+    /// Like `to_owned`, but reuses the strings that already had to be allocated.
+    pub fn into_owned(self) -> Value {
+        fn all(items: Vec<ValueRef<'_>>) -> Vec<Value> {
+            items.into_iter().map(ValueRef::into_owned).collect()
+        }
+
+        match self {
+            ValueRef::Bool(b) => Value::Bool(b),
+            ValueRef::Number(n) => Value::Number(n),
+            ValueRef::String(s) => Value::String(s.into_owned()),
+            ValueRef::Bytes(bytes) => Value::Bytes(bytes.into_owned()),
+            ValueRef::Identifier(name) => Value::Identifier(name.to_string()),
+            ValueRef::Null => Value::Null,
+            ValueRef::Ellipsis => Value::Ellipsis,
+            ValueRef::List(items) => Value::List(all(items)),
+            ValueRef::Tuple(items) => Value::Tuple(all(items)),
+            ValueRef::Set(items) => Value::Set(all(items)),
+            ValueRef::DictSplat(name) => Value::DictSplat(name.to_string()),
+            ValueRef::ListSplat(name) => Value::ListSplat(name.to_string()),
+        }
+    }
+}
+
+/// A function call that borrows from the source, see `ValueRef`. The kwargs are in the
+/// order they were written, so a key given twice appears twice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionCallRef<'a> {
+    pub name: &'a str,
+    pub kwargs: Vec<(&'a str, ValueRef<'a>)>,
+}
+
+impl<'a> FunctionCallRef<'a> {
+    /// The value of a kwarg, the last one if it was given more than once, as the owned
+    /// call keeps it.
+    pub fn get(&self, key: &str) -> Option<&ValueRef<'a>> {
+        self.kwargs
+            .iter()
+            .rev()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Copy the call out of the source into a `FunctionCall`.
+    pub fn to_owned(&self) -> FunctionCall {
+        self.clone().into_owned()
+    }
+
+    /// Like `to_owned`, but reuses the strings that already had to be allocated.
+    pub fn into_owned(self) -> FunctionCall {
+        let mut kwargs = HashMap::with_capacity(self.kwargs.len());
+        for (key, value) in self.kwargs {
+            kwargs.insert(key.to_string(), value.into_owned());
+        }
+        FunctionCall {
+            name: self.name.to_string(),
+            kwargs,
+            span: None,
+        }
+    }
+}
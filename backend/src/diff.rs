@@ -0,0 +1,107 @@
+use std::fmt;
+
+use crate::{FunctionCall, Value};
+
+/// A single difference between two function calls
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffItem {
+    NameMismatch {
+        left: String,
+        right: String,
+    },
+    /// Kwarg present on the right-hand call only
+    MissingKwarg {
+        key: String,
+        value: Value,
+    },
+    /// Kwarg present on the left-hand call only
+    ExtraKwarg {
+        key: String,
+        value: Value,
+    },
+    ValueMismatch {
+        key: String,
+        left_value: Value,
+        right_value: Value,
+    },
+}
+
+/// All differences between two function calls, ordered by kwarg name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FunctionCallDiff {
+    pub items: Vec<DiffItem>,
+}
+
+impl FunctionCallDiff {
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+/// Compare two function calls, treating `a` as the actual and `b` as the expected call
+pub fn diff_function_calls(a: &FunctionCall, b: &FunctionCall) -> FunctionCallDiff {
+    let mut items = Vec::new();
+
+    if a.name != b.name {
+        items.push(DiffItem::NameMismatch {
+            left: a.name.clone(),
+            right: b.name.clone(),
+        });
+    }
+
+    // Walk the keys in sorted order so the diff output is stable
+    let mut keys: Vec<&String> = a.kwargs.keys().chain(b.kwargs.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    for key in keys {
+        match (a.kwargs.get(key), b.kwargs.get(key)) {
+            (Some(left), Some(right)) if left != right => items.push(DiffItem::ValueMismatch {
+                key: key.clone(),
+                left_value: left.clone(),
+                right_value: right.clone(),
+            }),
+            (Some(left), None) => items.push(DiffItem::ExtraKwarg {
+                key: key.clone(),
+                value: left.clone(),
+            }),
+            (None, Some(right)) => items.push(DiffItem::MissingKwarg {
+                key: key.clone(),
+                value: right.clone(),
+            }),
+            _ => {}
+        }
+    }
+
+    FunctionCallDiff { items }
+}
+
+impl fmt::Display for DiffItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffItem::NameMismatch { left, right } => {
+                write!(f, "name: {:?} != {:?}", left, right)
+            }
+            DiffItem::MissingKwarg { key, value } => {
+                write!(f, "missing kwarg {}: expected {:?}", key, value)
+            }
+            DiffItem::ExtraKwarg { key, value } => {
+                write!(f, "unexpected kwarg {}: {:?}", key, value)
+            }
+            DiffItem::ValueMismatch {
+                key,
+                left_value,
+                right_value,
+            } => write!(f, "kwarg {}: {:?} != {:?}", key, left_value, right_value),
+        }
+    }
+}
+
+impl fmt::Display for FunctionCallDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for item in &self.items {
+            writeln!(f, "  {}", item)?;
+        }
+        Ok(())
+    }
+}
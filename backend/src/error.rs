@@ -0,0 +1,137 @@
+use std::fmt;
+
+use logos::Span;
+
+use crate::Diagnostic;
+
+/// Why a parse failed. Both engines and the incremental parsers return this, and every
+/// variant that can be located in the source carries its byte span.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ParseError {
+    #[error("Unexpected {found} at bytes {}..{}, expected {expected}", .span.start, .span.end)]
+    UnexpectedToken {
+        found: String,
+        expected: String,
+        span: Span,
+    },
+    #[error("Unterminated string at bytes {}..{}", .span.start, .span.end)]
+    UnterminatedString { span: Span },
+    #[error("Unclosed call to {name} at bytes {}..{}", .span.start, .span.end)]
+    UnclosedCall { name: String, span: Span },
+    /// A list element that is not a well-formed `name(key=value, ...)` call
+    #[error("Malformed function call at bytes {}..{}: {snippet}", .span.start, .span.end)]
+    MalformedCall { snippet: String, span: Span },
+    /// Text after the calls that strict parsing, or `parse_kwargs_only`, does not allow
+    #[error("Trailing garbage at {}..{}: {text}", .span.start, .span.end)]
+    TrailingInput { text: String, span: Span },
+    /// A problem the logos engine would have recovered from, in strict parsing
+    #[error("{message} at bytes {}..{}", .span.start, .span.end)]
+    Recovered { message: String, span: Span },
+    /// A kwarg given twice in one call, with `DuplicateKwarg::Error`
+    #[error("Duplicate argument {name} at bytes {}..{}", .span.start, .span.end)]
+    DuplicateKwarg { name: String, span: Span },
+    /// A delimiter found where a different one had to close first, e.g. `]` before a
+    /// call's `)`, in strict parsing
+    #[error(
+        "Mismatched delimiter at bytes {}..{}: expected '{expected}', found '{found}'",
+        .span.start,
+        .span.end
+    )]
+    MismatchedDelimiter {
+        expected: String,
+        found: String,
+        span: Span,
+    },
+    /// A value nested deeper than `ParserConfig::max_nesting_depth`
+    #[error("Nesting deeper than {limit} levels at bytes {}..{}", .span.start, .span.end)]
+    RecursionLimit { limit: usize, span: Span },
+    #[error("No tool calls found")]
+    NoToolCallsFound,
+    /// A call to a function outside `ParserConfig::allowed_names`, in strict mode
+    #[error("Function not allowed: {name}")]
+    FunctionNotAllowed { name: String },
+    /// More calls than `ParserConfig::max_calls`, in strict mode
+    #[error("Too many calls: {count} (limit {limit})")]
+    TooManyCalls { count: usize, limit: usize },
+    /// The incremental parser's `max_buffer_size` was exceeded
+    #[error("Buffer overflow: {buffered} bytes buffered without completing a call (limit {limit})")]
+    BufferOverflow { buffered: usize, limit: usize },
+    /// Reading the input failed at byte `offset`
+    #[error("Read error at byte {offset}: {message}")]
+    Read { message: String, offset: usize },
+    /// A snapshot passed to `NomParserState::from_bytes` could not be restored
+    #[error("Invalid parser state: {0}")]
+    InvalidState(String),
+    /// Neither the lenient nom parse's scan nor its fallback found any calls. `error` is
+    /// whichever of their errors got further into the input, from `strategy`.
+    #[error("{error} (in the {strategy})")]
+    StrategyFailed {
+        strategy: ParseStrategy,
+        error: Box<ParseError>,
+    },
+    /// JSON that `FunctionCall::from_json_object` or `from_openai_json` could not read as a
+    /// tool call, or kwargs that `deserialize_args` could not read as the caller's type
+    #[error("Invalid tool call JSON: {0}")]
+    InvalidJson(String),
+}
+
+impl ParseError {
+    /// The bytes of the source the error points at, if it can be located.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ParseError::UnexpectedToken { span, .. }
+            | ParseError::UnterminatedString { span }
+            | ParseError::UnclosedCall { span, .. }
+            | ParseError::MalformedCall { span, .. }
+            | ParseError::TrailingInput { span, .. }
+            | ParseError::Recovered { span, .. }
+            | ParseError::DuplicateKwarg { span, .. }
+            | ParseError::MismatchedDelimiter { span, .. }
+            | ParseError::RecursionLimit { span, .. } => Some(span.clone()),
+            ParseError::Read { offset, .. } => Some(*offset..*offset),
+            ParseError::StrategyFailed { error, .. } => error.span(),
+            ParseError::NoToolCallsFound
+            | ParseError::FunctionNotAllowed { .. }
+            | ParseError::TooManyCalls { .. }
+            | ParseError::BufferOverflow { .. }
+            | ParseError::InvalidState(_)
+            | ParseError::InvalidJson(_) => None,
+        }
+    }
+
+    /// Line and column where the error starts in `source`, both counted from 1, as by
+    /// `offset_to_line_col`.
+    pub fn line_col(&self, source: &str) -> Option<(usize, usize)> {
+        self.span()
+            .map(|span| crate::offset_to_line_col(source, span.start))
+    }
+}
+
+impl From<Diagnostic> for ParseError {
+    fn from(diagnostic: Diagnostic) -> Self {
+        ParseError::Recovered {
+            message: diagnostic.message,
+            span: diagnostic.span,
+        }
+    }
+}
+
+/// How the lenient nom parse looked for calls, see `ParseError::StrategyFailed`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseStrategy {
+    /// Scanning past surrounding text for lists and python blocks
+    Scan,
+    /// Parsing the whole input as calls, e.g. a bare `get_weather(city="NYC")`
+    Fallback,
+}
+
+impl fmt::Display for ParseStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseStrategy::Scan => write!(f, "scan for lists and python blocks"),
+            ParseStrategy::Fallback => write!(f, "fallback parse of the whole input"),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ParseError>;
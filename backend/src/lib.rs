@@ -1,34 +1,1545 @@
 #![allow(unused)]
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
+#[cfg(feature = "python")]
 use pythonize::pythonize;
+use serde::de::DeserializeOwned;
+use serde::ser::{SerializeMap, Serializer};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, Lines};
+use std::ops::Range;
+use std::time::Instant;
 
 // Import the parsers
+mod borrowed;
+pub mod diff;
+mod error;
 mod logos_parser;
 pub mod nom_parser;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod validation;
 
 // Re-export the parsers
-pub use logos_parser::parse_python;
-pub use nom_parser::{NomParserState, parse_incremental, parse_python_with_nom};
+pub use logos_parser::{
+    LogosParserState, count_function_calls, parse_python, parse_python_strict,
+    parse_python_with_config, parse_python_with_diagnostics,
+};
+pub use nom_parser::{
+    NomParserState, PartialFunction, ToolCallIter, count_tool_calls, parse_incremental,
+    parse_incremental_delta, parse_incremental_events, parse_incremental_with,
+    parse_python_borrowed, parse_python_with_nom, parse_python_with_nom_config,
+    parse_python_with_nom_filtered, parse_python_with_nom_raw, parse_python_with_nom_remainder,
+    parse_python_with_nom_with_diagnostics,
+};
 
-// Re-export the Error and Result types from logos parser
-pub use logos_parser::{Error, Result};
+pub use borrowed::{FunctionCallRef, ValueRef};
+pub use error::{ParseError, ParseStrategy, Result};
+
+/// How both engines parse: the markers delimiting a block of tool calls, which default
+/// to the Llama `<|python_start|>`/`<|python_end|>` tokens as other model families use
+/// their own, and how much malformed or unexpected input is tolerated.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParserConfig {
+    pub start_marker: String,
+    pub end_marker: String,
+    /// Fail on the first problem instead of recovering from it: text around the calls,
+    /// input the logos lexer can't read or that it skipped, a disallowed name and calls
+    /// beyond `max_calls` are all errors. With the nom engine the input must also be
+    /// exactly one function list, run of blocks or bare call.
+    pub strict: bool,
+    /// Scan past text before and after the calls, such as the model's prose. When off,
+    /// the input must be only calls and whitespace.
+    pub allow_surrounding_text: bool,
+    /// Only keep calls to these functions, or with `strict` reject any other call.
+    /// `None` allows every name.
+    pub allowed_names: Option<Vec<String>>,
+    /// Keep at most this many calls, or with `strict` reject input with more
+    pub max_calls: Option<usize>,
+    /// What to do with a kwarg given twice in one call
+    pub on_duplicate_kwarg: DuplicateKwarg,
+    /// When the scan finds no function list, parse the whole input once more as a bare
+    /// call such as `get_weather(city="NYC")`. The logos engine never parses bare calls.
+    pub recovery: bool,
+    /// Return no calls instead of `ParseError::NoToolCallsFound` when the input has none,
+    /// e.g. plain prose. Empty and whitespace-only input never has an error either way.
+    #[serde(default)]
+    pub allow_no_calls: bool,
+    /// How deeply values may nest, e.g. `[[1]]` is nested twice, before the parse fails
+    /// with `ParseError::RecursionLimit` rather than risk overflowing the stack. Values
+    /// never nest deeper than `MAX_NESTING_DEPTH_LIMIT`, however large this is.
+    #[serde(default = "default_max_nesting_depth")]
+    pub max_nesting_depth: usize,
+    /// Measure each parse and return a `ParseStats` alongside the calls. Off by default,
+    /// since it adds a timer and a second lexer pass.
+    pub collect_stats: bool,
+}
+
+pub(crate) fn default_max_nesting_depth() -> usize {
+    64
+}
+
+/// The largest `ParserConfig::max_nesting_depth` that takes effect. Both engines parse
+/// nested values recursively, and this deep a parse stays well within a 2 MiB thread
+/// stack in an optimised build, or 8 MiB in a debug one.
+pub const MAX_NESTING_DEPTH_LIMIT: usize = 256;
+
+/// What to do with a kwarg given twice in one call, as in `f(x=1, x=2)`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DuplicateKwarg {
+    /// Keep the last value, as a dict literal would
+    #[default]
+    KeepLast,
+    KeepFirst,
+    /// Fail with `ParseError::DuplicateKwarg`
+    Error,
+}
+
+impl ParserConfig {
+    pub fn new(start_marker: impl Into<String>, end_marker: impl Into<String>) -> Self {
+        Self {
+            start_marker: start_marker.into(),
+            end_marker: end_marker.into(),
+            strict: false,
+            allow_surrounding_text: true,
+            allowed_names: None,
+            max_calls: None,
+            on_duplicate_kwarg: DuplicateKwarg::default(),
+            recovery: true,
+            allow_no_calls: false,
+            max_nesting_depth: default_max_nesting_depth(),
+            collect_stats: false,
+        }
+    }
+
+    /// Mistral's format: a `[TOOL_CALLS]` sentinel directly before the function list, with
+    /// no end marker. The sentinel is matched as a marker, never parsed as a list.
+    pub fn mistral() -> Self {
+        Self::new("[TOOL_CALLS]", "")
+    }
+
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn with_allow_surrounding_text(mut self, allow_surrounding_text: bool) -> Self {
+        self.allow_surrounding_text = allow_surrounding_text;
+        self
+    }
+
+    pub fn with_allowed_names(mut self, allowed_names: Vec<String>) -> Self {
+        self.allowed_names = Some(allowed_names);
+        self
+    }
+
+    pub fn with_max_calls(mut self, max_calls: usize) -> Self {
+        self.max_calls = Some(max_calls);
+        self
+    }
+
+    pub fn with_on_duplicate_kwarg(mut self, on_duplicate_kwarg: DuplicateKwarg) -> Self {
+        self.on_duplicate_kwarg = on_duplicate_kwarg;
+        self
+    }
+
+    pub fn with_recovery(mut self, recovery: bool) -> Self {
+        self.recovery = recovery;
+        self
+    }
+
+    pub fn with_allow_no_calls(mut self, allow_no_calls: bool) -> Self {
+        self.allow_no_calls = allow_no_calls;
+        self
+    }
+
+    pub fn with_max_nesting_depth(mut self, max_nesting_depth: usize) -> Self {
+        self.max_nesting_depth = max_nesting_depth;
+        self
+    }
+
+    pub fn with_collect_stats(mut self, collect_stats: bool) -> Self {
+        self.collect_stats = collect_stats;
+        self
+    }
+
+    /// Trim whitespace around call names and apply `allowed_names`, dropping disallowed
+    /// calls or, in strict mode, failing on the first one. Then applies `max_calls`.
+    pub(crate) fn filter_names(
+        &self,
+        function_calls: Vec<FunctionCall>,
+    ) -> Result<Vec<FunctionCall>> {
+        let mut filtered = Vec::with_capacity(function_calls.len());
+        for mut function_call in function_calls {
+            if self.admit_name(&mut function_call)? {
+                filtered.push(function_call);
+            }
+        }
+        self.limit_calls(filtered)
+    }
+
+    /// Drop the calls beyond `max_calls` or, in strict mode, fail if there are any.
+    pub(crate) fn limit_calls<T>(&self, mut function_calls: Vec<T>) -> Result<Vec<T>> {
+        match self.max_calls {
+            Some(limit) if function_calls.len() > limit => {
+                if self.strict {
+                    return Err(ParseError::TooManyCalls {
+                        count: function_calls.len(),
+                        limit,
+                    });
+                }
+                function_calls.truncate(limit);
+                Ok(function_calls)
+            }
+            _ => Ok(function_calls),
+        }
+    }
+
+    /// Trim whitespace around the call's name and check it against `allowed_names`.
+    /// A disallowed name is an error in strict mode and `false` otherwise.
+    pub(crate) fn admit_name(&self, function_call: &mut FunctionCall) -> Result<bool> {
+        let name = function_call.name.trim();
+        if name.len() != function_call.name.len() {
+            function_call.name = name.to_string();
+        }
+
+        let allowed = match &self.allowed_names {
+            Some(names) => names.iter().any(|allowed| allowed == &function_call.name),
+            None => true,
+        };
+        if !allowed && self.strict {
+            return Err(ParseError::FunctionNotAllowed {
+                name: function_call.name.clone(),
+            });
+        }
+        Ok(allowed)
+    }
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        Self::new("<|python_start|>", "<|python_end|>")
+    }
+}
+
+/// Measurements from a single parse, for profiling engines on real model output.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParseStats {
+    /// Lexical tokens in the source. Counted with the logos lexer for both engines, so
+    /// the numbers compare across them.
+    pub token_count: usize,
+    pub function_count: usize,
+    pub parse_duration_us: u64,
+    /// `"nom"` or `"logos"`
+    pub engine: &'static str,
+}
+
+impl ParseStats {
+    /// Run `parse` over `source`, measuring it when `config.collect_stats` is set.
+    pub fn collect<E>(
+        engine: &'static str,
+        source: &str,
+        config: &ParserConfig,
+        parse: impl FnOnce() -> std::result::Result<Vec<FunctionCall>, E>,
+    ) -> std::result::Result<(Vec<FunctionCall>, Option<ParseStats>), E> {
+        if !config.collect_stats {
+            return parse().map(|function_calls| (function_calls, None));
+        }
+
+        let start = Instant::now();
+        let function_calls = parse()?;
+        let parse_duration_us = start.elapsed().as_micros() as u64;
+        let stats = ParseStats {
+            token_count: logos_parser::count_tokens(source),
+            function_count: function_calls.len(),
+            parse_duration_us,
+            engine,
+        };
+        Ok((function_calls, Some(stats)))
+    }
+}
+
+/// Parse with the logos engine and report how the parse went.
+pub fn parse_python_with_stats(source: &str) -> Result<(Vec<FunctionCall>, ParseStats)> {
+    let config = ParserConfig::default().with_collect_stats(true);
+    let (function_calls, stats) =
+        ParseStats::collect("logos", source, &config, || parse_python(source))?;
+    Ok((function_calls, stats.expect("stats were requested")))
+}
+
+/// Parse with the nom engine and report how the parse went.
+pub fn parse_python_with_nom_with_stats(source: &str) -> Result<(Vec<FunctionCall>, ParseStats)> {
+    let config = ParserConfig::default().with_collect_stats(true);
+    let (function_calls, stats) =
+        ParseStats::collect("nom", source, &config, || parse_python_with_nom(source))?;
+    Ok((function_calls, stats.expect("stats were requested")))
+}
+
+/// Common interface over the streaming parsers, so callers can switch engines without
+/// changing how they feed chunks.
+pub trait IncrementalBackend {
+    /// Append a chunk of model output and return only the calls it completed.
+    fn feed(&mut self, chunk: &str) -> Result<Vec<FunctionCall>>;
+
+    /// Signal the end of the stream and return the final list of all calls.
+    fn finish(&mut self) -> Result<Vec<FunctionCall>>;
+
+    /// Calls completed so far.
+    fn parsed(&self) -> &[FunctionCall];
+
+    /// Whether every bracket, paren and string in the buffered input has been closed.
+    fn is_complete(&self) -> bool;
+
+    /// Discard all buffered input and parsed calls.
+    fn reset(&mut self);
+
+    /// Whether `finish` had to complete a call cut off by the end of the stream.
+    fn is_truncated(&self) -> bool {
+        false
+    }
+
+    /// Whether a block start marker has been seen without its end marker.
+    fn is_inside_python_block(&self) -> bool;
+
+    /// Name of the call whose arguments are still arriving, if any.
+    fn current_call_name(&self) -> Option<&str>;
+
+    /// Whether some buffered input has not yet produced a call or been ruled out as prose.
+    fn has_pending_input(&self) -> bool;
+
+    /// Take the events recorded since the last call, if events were enabled.
+    fn drain_events(&mut self) -> Vec<ToolCallEvent>;
+
+    /// Append a chunk like `feed`, calling `events` as the chunk reveals each part of a call.
+    fn feed_with(
+        &mut self,
+        chunk: &str,
+        events: &mut dyn ParserEvents,
+    ) -> Result<Vec<FunctionCall>>;
+
+    /// Append a chunk and return the `ParseEvent`s it produced.
+    fn feed_events(&mut self, chunk: &str) -> Result<Vec<ParseEvent>> {
+        let mut events = Vec::new();
+        self.feed_with(chunk, &mut events)?;
+        Ok(events)
+    }
+
+    /// The call whose arguments are still arriving, with the kwargs completed so far.
+    /// Engines that only see calls once they complete return `None`.
+    fn current_partial(&self) -> Option<&PartialFunction> {
+        None
+    }
+
+    /// Append a chunk like `feed`, returning the calls it completed with their indices.
+    fn feed_tracked(&mut self, chunk: &str) -> Result<Vec<TrackedFunctionCall>> {
+        let reported = self.parsed().len();
+        self.feed(chunk)?;
+        Ok(self.tracked_functions().split_off(reported))
+    }
+
+    /// Calls completed so far, with their indices.
+    fn tracked_functions(&self) -> Vec<TrackedFunctionCall> {
+        self.parsed()
+            .iter()
+            .enumerate()
+            .map(|(index, function_call)| TrackedFunctionCall::new(index, function_call.clone()))
+            .collect()
+    }
+}
+
+/// A call from a stream together with its `index`, the order in which the stream completed
+/// it, and an `id` derived from it, e.g. for OpenAI `tool_calls` deltas. Completed calls are
+/// only ever appended, so neither changes until the parser is reset. The index is the one
+/// `ParseEvent::FunctionCompleted` reported for the call.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct TrackedFunctionCall {
+    pub index: usize,
+    pub id: String,
+    #[serde(flatten)]
+    pub function_call: FunctionCall,
+}
+
+impl TrackedFunctionCall {
+    pub fn new(index: usize, function_call: FunctionCall) -> Self {
+        Self {
+            index,
+            id: format!("call_{}", index),
+            function_call,
+        }
+    }
+}
+
+/// Calls accumulated from several sources, such as the assistant turns of a conversation.
+/// Each call is indexed, and given an id, in the order it was added across all of them;
+/// its span is still relative to the source it was parsed from.
+#[derive(Debug, Clone, Default)]
+pub struct FunctionCallSet {
+    calls: Vec<TrackedFunctionCall>,
+    // Calls already in the set, if equal ones are to be skipped
+    seen: Option<HashSet<FunctionCall>>,
+}
+
+impl FunctionCallSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Skip calls equal, by name and kwargs, to one already in the set, e.g. a call a
+    /// model repeated in a later turn.
+    pub fn with_dedup(mut self, dedup: bool) -> Self {
+        self.seen = dedup.then(|| self.calls.iter().map(|c| c.function_call.clone()).collect());
+        self
+    }
+
+    /// Parse `source` with `parse`, e.g. `parse_python_with_nom`, and append its calls,
+    /// returning the ones added. Nothing is added if the parse fails.
+    pub fn extend_from_source<F>(
+        &mut self,
+        source: &str,
+        parse: F,
+    ) -> Result<&[TrackedFunctionCall]>
+    where
+        F: FnOnce(&str) -> Result<Vec<FunctionCall>>,
+    {
+        let function_calls = parse(source)?;
+        Ok(self.extend(function_calls))
+    }
+
+    /// Append calls, returning the ones added.
+    pub fn extend<I: IntoIterator<Item = FunctionCall>>(
+        &mut self,
+        function_calls: I,
+    ) -> &[TrackedFunctionCall] {
+        let first = self.calls.len();
+        for function_call in function_calls {
+            if let Some(seen) = &mut self.seen
+                && !seen.insert(function_call.clone())
+            {
+                continue;
+            }
+            let tracked = TrackedFunctionCall::new(self.calls.len(), function_call);
+            self.calls.push(tracked);
+        }
+        &self.calls[first..]
+    }
+
+    pub fn calls(&self) -> &[TrackedFunctionCall] {
+        &self.calls
+    }
+
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+}
+
+impl IncrementalBackend for NomParserState {
+    fn feed(&mut self, chunk: &str) -> Result<Vec<FunctionCall>> {
+        parse_incremental_delta(self, chunk)
+    }
+
+    fn finish(&mut self) -> Result<Vec<FunctionCall>> {
+        NomParserState::finish(self)
+    }
+
+    fn parsed(&self) -> &[FunctionCall] {
+        &self.parsed_functions
+    }
+
+    fn is_complete(&self) -> bool {
+        NomParserState::is_complete(self)
+    }
+
+    fn reset(&mut self) {
+        NomParserState::reset(self)
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    fn is_inside_python_block(&self) -> bool {
+        NomParserState::is_inside_python_block(self)
+    }
+
+    fn current_call_name(&self) -> Option<&str> {
+        NomParserState::current_call_name(self)
+    }
+
+    fn has_pending_input(&self) -> bool {
+        NomParserState::has_pending_input(self)
+    }
+
+    fn drain_events(&mut self) -> Vec<ToolCallEvent> {
+        NomParserState::drain_events(self)
+    }
+
+    fn current_partial(&self) -> Option<&PartialFunction> {
+        NomParserState::current_partial(self)
+    }
+
+    fn feed_with(
+        &mut self,
+        chunk: &str,
+        events: &mut dyn ParserEvents,
+    ) -> Result<Vec<FunctionCall>> {
+        parse_incremental_with(self, chunk, events)
+    }
+}
+
+/// Parse every function call in the source, returning one result per attempted call.
+/// Unlike the other entry points, a malformed call produces an `Err` with its span in
+/// place instead of failing the whole parse or being dropped silently.
+pub fn parse_python_all(source: &str) -> Vec<Result<FunctionCall>> {
+    iter_tool_calls(source).collect()
+}
+
+/// Like `parse_python_all`, but lazily: the source is only scanned as far as needed for
+/// the next call, so a large log can be processed without collecting every call first.
+pub fn iter_tool_calls(source: &str) -> ToolCallIter<'_> {
+    ToolCallIter::new(source)
+}
+
+/// Call `f` with each function call in the source as soon as it is parsed, in order,
+/// instead of collecting them. Stops at the first malformed call and returns its error, by
+/// which time `f` has seen every call before it.
+pub fn parse_python_for_each<F: FnMut(FunctionCall)>(source: &str, mut f: F) -> Result<()> {
+    for function_call in iter_tool_calls(source) {
+        f(function_call?);
+    }
+    Ok(())
+}
+
+/// Parse calls from a reader line by line, without loading the whole input: each call is
+/// yielded once its list closes. A read error is yielded once and ends the stream, unless
+/// `fuse_on_error(false)` lets it carry on with the next line.
+pub fn parse_python_stream<R: BufRead>(reader: R) -> FunctionCallStream<R> {
+    FunctionCallStream {
+        lines: reader.lines(),
+        state: NomParserState::new(),
+        ready: VecDeque::new(),
+        offset: 0,
+        fuse_on_error: true,
+        done: false,
+    }
+}
+
+/// Iterator returned by `parse_python_stream`
+pub struct FunctionCallStream<R> {
+    lines: Lines<R>,
+    state: NomParserState,
+    // Calls completed by the last line and not yet yielded
+    ready: VecDeque<FunctionCall>,
+    // Bytes read so far, for error spans
+    offset: usize,
+    fuse_on_error: bool,
+    done: bool,
+}
+
+impl<R: BufRead> FunctionCallStream<R> {
+    pub fn fuse_on_error(mut self, fuse_on_error: bool) -> Self {
+        self.fuse_on_error = fuse_on_error;
+        self
+    }
+}
+
+impl<R: BufRead> Iterator for FunctionCallStream<R> {
+    type Item = Result<FunctionCall>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(function_call) = self.ready.pop_front() {
+                return Some(Ok(function_call));
+            }
+            if self.done {
+                return None;
+            }
+            let error = match self.lines.next()? {
+                Ok(mut line) => {
+                    // `lines` strips the line ending, which may be separating two tokens
+                    line.push('\n');
+                    let start = self.offset;
+                    self.offset += line.len();
+                    match parse_incremental_delta(&mut self.state, &line) {
+                        Ok(function_calls) => {
+                            self.ready.extend(function_calls);
+                            continue;
+                        }
+                        Err(error) => error,
+                    }
+                }
+                Err(err) => ParseError::Read {
+                    message: err.to_string(),
+                    offset: self.offset,
+                },
+            };
+            self.done = self.fuse_on_error;
+            return Some(Err(error));
+        }
+    }
+}
+
+/// Line and column of the byte `offset` into `source`, both counted from 1. Columns count
+/// characters, so a multibyte character takes up one; an offset past the end is the end.
+pub fn offset_to_line_col(source: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    let line = before.matches('\n').count() + 1;
+    let column = before[line_start..].chars().count() + 1;
+    (line, column)
+}
+
+/// The rest of `source` after the `import x` and `from x import y` lines it starts with,
+/// which some models write before their calls. Both engines skip them, so they are
+/// allowed even where other text before the calls is not.
+pub fn skip_import_statements(source: &str) -> &str {
+    let mut rest = source;
+    loop {
+        let line = rest.trim_start();
+        let line_len = line.find('\n').map_or(line.len(), |newline| newline + 1);
+        let mut words = line[..line_len].split_whitespace();
+        let is_import = match words.next() {
+            Some("import") => words.next().is_some(),
+            Some("from") => words.nth(1) == Some("import"),
+            _ => false,
+        };
+        if !is_import {
+            return rest;
+        }
+        rest = &line[line_len..];
+    }
+}
+
+/// A problem the parser recovered from, such as a skipped token, an argument with no value
+/// or a candidate list that failed to parse, located by its byte span in the source.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: logos::Span,
+    /// The source text within `span`
+    pub snippet: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}..{}",
+            self.message, self.span.start, self.span.end
+        )
+    }
+}
+
+/// Describe an error from parsing `source`, locating its span by line and column as well
+/// as by bytes, e.g. `Parse error at 2:5: Unclosed call to get_weather at bytes 18..22`
+pub fn describe_error(source: &str, error: &ParseError) -> String {
+    match error.line_col(source) {
+        Some((line, column)) => format!("Parse error at {}:{}: {}", line, column, error),
+        None => format!("Parse error: {}", error),
+    }
+}
+
+/// Parse only the arguments of a call, e.g. `city="Tokyo", days=7`, optionally wrapped in
+/// parentheses. For callers that already have the function name, such as inference
+/// engines that stream the name and the arguments separately.
+pub fn parse_kwargs_only(source: &str) -> Result<HashMap<String, Value>> {
+    let trimmed = source.trim();
+    let wrapped;
+    let input = if trimmed.starts_with('(') && trimmed.ends_with(')') {
+        trimmed
+    } else {
+        wrapped = format!("({})", trimmed);
+        &wrapped
+    };
+
+    match nom_parser::parse_kwargs(input) {
+        Ok((rest, kwargs)) if rest.trim().is_empty() => Ok(kwargs),
+        Ok((rest, _)) => {
+            let text = rest.trim();
+            let offset = source.trim_end().len().saturating_sub(text.len());
+            Err(ParseError::TrailingInput {
+                text: text.to_string(),
+                span: offset..offset + text.len(),
+            })
+        }
+        Err(e) => Err(nom_parser::nom_error(input, e)),
+    }
+}
 
 /// Simplified Python AST nodes
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Value {
     Bool(bool),
     Number(f64),
     String(String),
+    /// Python bytes literal, as in `b"\x00\x01"`
+    #[serde(
+        serialize_with = "serialize_bytes",
+        deserialize_with = "deserialize_bytes"
+    )]
+    Bytes(Vec<u8>),
     Identifier(String),
+    Null,
     Empty,
+    /// Python's `...`, often a placeholder default
+    Ellipsis,
     List(Vec<Value>),
+    /// Python tuple literal, as in `(1, 2)` or `(1,)`
+    Tuple(Vec<Value>),
+    /// Python set literal; serialized as a sorted list since sets have no order
+    #[serde(serialize_with = "serialize_set")]
+    Set(Vec<Value>),
     FunctionCall(FunctionCall),
+    /// `**name` in a dict literal, unpacking another dict into it. It takes the place of a
+    /// key and value in the interleaved list.
+    DictSplat(String),
+    /// `*name` in a list literal, unpacking another list into it
+    ListSplat(String),
+}
+
+fn serialize_set<S: serde::Serializer>(
+    items: &[Value],
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    let mut sorted: Vec<&Value> = items.iter().collect();
+    sorted.sort_by(|a, b| compare_values(a, b));
+    serializer.collect_seq(sorted)
+}
+
+// Bytes serialize as a byte string rather than a list of numbers, so the Python bindings
+// return `bytes` and the CBOR state stays compact
+fn serialize_bytes<S: Serializer>(
+    bytes: &[u8],
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_bytes(bytes)
+}
+
+// Accepts a byte string or, from formats without one such as JSON, a list of numbers
+fn deserialize_bytes<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<u8>, D::Error> {
+    struct BytesVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "a byte string")
+        }
+
+        fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> std::result::Result<Vec<u8>, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E: serde::de::Error>(
+            self,
+            v: Vec<u8>,
+        ) -> std::result::Result<Vec<u8>, E> {
+            Ok(v)
+        }
+
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(
+            self,
+            mut seq: A,
+        ) -> std::result::Result<Vec<u8>, A::Error> {
+            let mut bytes = Vec::new();
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
+
+    deserializer.deserialize_byte_buf(BytesVisitor)
+}
+
+// Derived equality, except that NaN equals itself so that `Value` can be `Eq`
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b || (a.is_nan() && b.is_nan()),
+            (Value::String(a), Value::String(b))
+            | (Value::Identifier(a), Value::Identifier(b))
+            | (Value::DictSplat(a), Value::DictSplat(b))
+            | (Value::ListSplat(a), Value::ListSplat(b)) => a == b,
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
+            (Value::Null, Value::Null)
+            | (Value::Empty, Value::Empty)
+            | (Value::Ellipsis, Value::Ellipsis) => true,
+            (Value::List(a), Value::List(b))
+            | (Value::Tuple(a), Value::Tuple(b))
+            | (Value::Set(a), Value::Set(b)) => a == b,
+            (Value::FunctionCall(a), Value::FunctionCall(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Value::Bool(b) => b.hash(state),
+            // Numbers that compare equal hash alike: every NaN, and both zeros
+            Value::Number(n) if n.is_nan() => f64::NAN.to_bits().hash(state),
+            Value::Number(n) if *n == 0.0 => 0u64.hash(state),
+            Value::Number(n) => n.to_bits().hash(state),
+            Value::String(s) | Value::Identifier(s) | Value::DictSplat(s) | Value::ListSplat(s) => {
+                s.hash(state)
+            }
+            Value::Bytes(bytes) => bytes.hash(state),
+            Value::Null | Value::Empty | Value::Ellipsis => {}
+            Value::List(items) | Value::Tuple(items) | Value::Set(items) => items.hash(state),
+            Value::FunctionCall(call) => call.hash(state),
+        }
+    }
+}
+
+// Total order used to sort set members: by kind first, then by value within a kind
+fn compare_values(a: &Value, b: &Value) -> std::cmp::Ordering {
+    fn rank(value: &Value) -> u8 {
+        match value {
+            Value::Null => 0,
+            Value::Empty => 1,
+            Value::Ellipsis => 2,
+            Value::Bool(_) => 3,
+            Value::Number(_) => 4,
+            Value::String(_) => 5,
+            Value::Bytes(_) => 6,
+            Value::Identifier(_) => 7,
+            Value::List(_) => 8,
+            Value::Tuple(_) => 9,
+            Value::Set(_) => 10,
+            Value::FunctionCall(_) => 11,
+            Value::DictSplat(_) => 12,
+            Value::ListSplat(_) => 13,
+        }
+    }
+
+    match (a, b) {
+        (Value::Bool(x), Value::Bool(y)) => x.cmp(y),
+        (Value::Number(x), Value::Number(y)) => x.total_cmp(y),
+        (Value::String(x), Value::String(y)) | (Value::Identifier(x), Value::Identifier(y)) => {
+            x.cmp(y)
+        }
+        (Value::Bytes(x), Value::Bytes(y)) => x.cmp(y),
+        _ => rank(a)
+            .cmp(&rank(b))
+            .then_with(|| format!("{:?}", a).cmp(&format!("{:?}", b))),
+    }
+}
+
+// Formats values as Python literals, so a parsed call can be printed back as source
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(true) => write!(f, "True"),
+            Value::Bool(false) => write!(f, "False"),
+            // Python has no literal for these, so they print as the call that makes them
+            Value::Number(n) if n.is_nan() => write!(f, "float(\"nan\")"),
+            Value::Number(n) if n.is_infinite() && *n > 0.0 => write!(f, "float(\"inf\")"),
+            Value::Number(n) if n.is_infinite() => write!(f, "float(\"-inf\")"),
+            // Whole numbers print without a trailing `.0`, matching the usual model output
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Value::Number(n) => write!(f, "{}", n),
+            // Backslashes, as in regexes or Windows paths, read better in a raw string
+            Value::String(s) if fits_raw_string(s) => write!(f, "r\"{}\"", s),
+            Value::String(s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\r' => write!(f, "\\r")?,
+                        '\t' => write!(f, "\\t")?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Value::Bytes(bytes) => {
+                write!(f, "b\"")?;
+                for &byte in bytes {
+                    match byte {
+                        b'"' => write!(f, "\\\"")?,
+                        b'\\' => write!(f, "\\\\")?,
+                        b'\n' => write!(f, "\\n")?,
+                        b'\r' => write!(f, "\\r")?,
+                        b'\t' => write!(f, "\\t")?,
+                        b' '..=b'~' => write!(f, "{}", byte as char)?,
+                        byte => write!(f, "\\x{:02x}", byte)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Value::Identifier(name) => write!(f, "{}", name),
+            Value::Null => write!(f, "None"),
+            Value::Ellipsis => write!(f, "..."),
+            // A missing value, as in `key=`
+            Value::Empty => Ok(()),
+            Value::List(items) => {
+                write!(f, "[")?;
+                write_separated(f, items)?;
+                write!(f, "]")
+            }
+            // A single item needs a trailing comma, or the parentheses only group it
+            Value::Tuple(items) if items.len() == 1 => write!(f, "({},)", items[0]),
+            Value::Tuple(items) => {
+                write!(f, "(")?;
+                write_separated(f, items)?;
+                write!(f, ")")
+            }
+            Value::Set(items) if items.is_empty() => write!(f, "set()"),
+            Value::Set(items) => {
+                write!(f, "{{")?;
+                write_separated(f, items)?;
+                write!(f, "}}")
+            }
+            Value::FunctionCall(call) => write!(f, "{}", call),
+            Value::DictSplat(name) => write!(f, "**{}", name),
+            Value::ListSplat(name) => write!(f, "*{}", name),
+        }
+    }
+}
+
+fn write_separated(f: &mut fmt::Formatter<'_>, items: &[Value]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+/// Whether `s` has backslashes and can be written as a raw string literal, which cannot
+/// contain its own quote or end with a backslash
+fn fits_raw_string(s: &str) -> bool {
+    s.contains('\\') && !s.ends_with('\\') && !s.contains(['"', '\n', '\r', '\t'])
+}
+
+impl Value {
+    /// The value as plain JSON, the way tool call `arguments` expect it: strings and
+    /// identifiers become strings, `None`, `...` and missing values `null`, and lists, sets
+    /// and dicts (stored as interleaved keys and values) arrays. A nested call becomes
+    /// `{"__call__": {"name": ..., "kwargs": {...}}}`, bytes `{"__bytes__": "<base64>"}`, tuples
+    /// `{"__tuple__": [...]}`, and `**name` and `*name` `{"__splat__": "name"}` and
+    /// `{"__list_splat__": "name"}`.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        write_json(self, &mut out);
+        out
+    }
+
+    /// Read a value from JSON, the inverse of [`Value::to_json`]: numbers, integer or not,
+    /// become `Value::Number`, arrays lists, and objects lists of alternating keys and
+    /// values, as Python dicts do, unless they are one of the tagged objects `to_json`
+    /// writes. Strings stay strings, since JSON can't tell identifiers apart.
+    pub fn from_json(value: &serde_json::Value) -> Value {
+        match value {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(b) => Value::Bool(*b),
+            serde_json::Value::Number(n) => Value::Number(n.as_f64().unwrap_or(f64::NAN)),
+            serde_json::Value::String(s) => Value::String(s.clone()),
+            serde_json::Value::Array(items) => {
+                Value::List(items.iter().map(Value::from_json).collect())
+            }
+            // The tagged objects that `to_json` writes for bytes, tuples and splats
+            serde_json::Value::Object(entries)
+                if entries.len() == 1
+                    && let Some(serde_json::Value::String(encoded)) = entries.get("__bytes__")
+                    && let Some(bytes) = decode_base64(encoded) =>
+            {
+                Value::Bytes(bytes)
+            }
+            serde_json::Value::Object(entries)
+                if entries.len() == 1
+                    && let Some(serde_json::Value::Array(items)) = entries.get("__tuple__") =>
+            {
+                Value::Tuple(items.iter().map(Value::from_json).collect())
+            }
+            serde_json::Value::Object(entries)
+                if entries.len() == 1
+                    && let Some(serde_json::Value::String(name)) = entries.get("__splat__") =>
+            {
+                Value::DictSplat(name.clone())
+            }
+            serde_json::Value::Object(entries)
+                if entries.len() == 1
+                    && let Some(serde_json::Value::String(name)) =
+                        entries.get("__list_splat__") =>
+            {
+                Value::ListSplat(name.clone())
+            }
+            serde_json::Value::Object(entries)
+                if entries.len() == 1
+                    && let Some(call) = entries.get("__call__")
+                    && let Ok(function_call) = FunctionCall::from_json_object(call) =>
+            {
+                Value::FunctionCall(function_call)
+            }
+            serde_json::Value::Object(entries) => Value::List(
+                entries
+                    .iter()
+                    .flat_map(|(key, value)| [Value::String(key.clone()), Value::from_json(value)])
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Rewrite a value into its canonical form, so equivalent values from different parses
+/// compare equal: the identifiers `True`, `False` and `None` become the booleans and
+/// `Value::Null` they name. Applies to the items of lists, tuples and sets and to the
+/// kwargs of nested calls as well.
+pub fn normalize_value(value: Value) -> Value {
+    match value {
+        Value::Identifier(name) => match name.as_str() {
+            "True" => Value::Bool(true),
+            "False" => Value::Bool(false),
+            "None" => Value::Null,
+            _ => Value::Identifier(name),
+        },
+        Value::List(items) => Value::List(items.into_iter().map(normalize_value).collect()),
+        Value::Tuple(items) => Value::Tuple(items.into_iter().map(normalize_value).collect()),
+        Value::Set(items) => Value::Set(items.into_iter().map(normalize_value).collect()),
+        Value::FunctionCall(call) => Value::FunctionCall(normalize_function_call(call)),
+        value => value,
+    }
+}
+
+/// Apply [`normalize_value`] to every kwarg of the call.
+pub fn normalize_function_call(function_call: FunctionCall) -> FunctionCall {
+    FunctionCall {
+        name: function_call.name,
+        kwargs: function_call
+            .kwargs
+            .into_iter()
+            .map(|(key, value)| (key, normalize_value(value)))
+            .collect(),
+        span: function_call.span,
+    }
+}
+
+fn write_json_array(items: &[Value], out: &mut String) {
+    out.push('[');
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_json(item, out);
+    }
+    out.push(']');
+}
+
+fn write_json(value: &Value, out: &mut String) {
+    match value {
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) if !n.is_finite() => out.push_str("null"),
+        Value::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => {
+            out.push_str(&(*n as i64).to_string())
+        }
+        Value::Number(n) => out.push_str(&n.to_string()),
+        Value::String(s) | Value::Identifier(s) => write_json_string(s, out),
+        Value::Bytes(bytes) => {
+            out.push_str("{\"__bytes__\": ");
+            write_json_string(&encode_base64(bytes), out);
+            out.push('}');
+        }
+        Value::Null | Value::Empty | Value::Ellipsis => out.push_str("null"),
+        Value::List(items) | Value::Set(items) => write_json_array(items, out),
+        Value::Tuple(items) => {
+            out.push_str("{\"__tuple__\": ");
+            write_json_array(items, out);
+            out.push('}');
+        }
+        Value::FunctionCall(call) => {
+            out.push_str("{\"__call__\": {\"name\": ");
+            write_json_string(&call.name, out);
+            out.push_str(", \"kwargs\": ");
+            out.push_str(&call.arguments_json());
+            out.push_str("}}");
+        }
+        Value::DictSplat(name) => {
+            out.push_str("{\"__splat__\": ");
+            write_json_string(name, out);
+            out.push('}');
+        }
+        Value::ListSplat(name) => {
+            out.push_str("{\"__list_splat__\": ");
+            write_json_string(name, out);
+            out.push('}');
+        }
+    }
+}
+
+/// Serializes a `Value` the way `to_json` prints it: untagged, with whole numbers as
+/// integers, identifiers as strings and nested calls as `{"__call__": {"name", "kwargs"}}`. The
+/// derived `Serialize` keeps the variant tags that the bindings return.
+struct JsonValue<'a>(&'a Value);
+
+impl Serialize for JsonValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Bool(b) => serializer.serialize_bool(*b),
+            Value::Number(n) if !n.is_finite() => serializer.serialize_none(),
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => {
+                serializer.serialize_i64(*n as i64)
+            }
+            Value::Number(n) => serializer.serialize_f64(*n),
+            Value::String(s) | Value::Identifier(s) => serializer.serialize_str(s),
+            Value::Bytes(bytes) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("__bytes__", &encode_base64(bytes))?;
+                map.end()
+            }
+            Value::Null | Value::Empty | Value::Ellipsis => serializer.serialize_none(),
+            Value::List(items) | Value::Set(items) => {
+                serializer.collect_seq(items.iter().map(JsonValue))
+            }
+            Value::Tuple(items) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("__tuple__", &JsonItems(items))?;
+                map.end()
+            }
+            Value::FunctionCall(call) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("__call__", &JsonCall(call))?;
+                map.end()
+            }
+            Value::DictSplat(name) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("__splat__", name)?;
+                map.end()
+            }
+            Value::ListSplat(name) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("__list_splat__", name)?;
+                map.end()
+            }
+        }
+    }
+}
+
+struct JsonItems<'a>(&'a [Value]);
+
+impl Serialize for JsonItems<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(JsonValue))
+    }
+}
+
+struct JsonArgs<'a>(&'a HashMap<String, Value>);
+
+impl Serialize for JsonArgs<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(key, value)| (key, JsonValue(value))))
+    }
+}
+
+struct JsonCall<'a>(&'a FunctionCall);
+
+impl Serialize for JsonCall<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("name", &self.0.name)?;
+        map.serialize_entry("kwargs", &JsonArgs(&self.0.kwargs))?;
+        map.end()
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Standard padded base64, as used for bytes in JSON output
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
 }
 
+// Inverse of `encode_base64`; `None` if `encoded` is not padded base64
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    if !encoded.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(encoded.len() / 4 * 3);
+    for chunk in encoded.as_bytes().chunks(4) {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 {
+            return None;
+        }
+        let mut group = 0u32;
+        for &c in &chunk[..4 - padding] {
+            let digit = BASE64_ALPHABET.iter().position(|&a| a == c)?;
+            group = group << 6 | digit as u32;
+        }
+        group <<= 6 * padding;
+        bytes.extend_from_slice(&group.to_be_bytes()[1..4 - padding]);
+    }
+    Some(bytes)
+}
+
+pub(crate) fn write_json_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Progress of a streamed tool call, shaped like OpenAI `tool_calls` deltas: the name
+/// arrives first, then the `arguments` as JSON fragments that concatenate to an object.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum ToolCallEvent {
+    CallStarted { index: usize, name: String },
+    ArgumentsDelta { index: usize, json_fragment: String },
+    CallFinished { index: usize },
+}
+
+/// What a chunk revealed about the calls being streamed, at the granularity of parsed
+/// values: see `parse_incremental_events`. `index` is the call's position in the parsed calls.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum ParseEvent {
+    FunctionStarted {
+        name: String,
+    },
+    ArgumentParsed {
+        name: String,
+        key: String,
+        value: Value,
+    },
+    FunctionCompleted {
+        index: usize,
+    },
+}
+
+/// Hooks the incremental parsers call in source order as a stream reveals each part of a
+/// call, so callers don't have to diff the parsed calls. Every hook defaults to doing nothing.
+pub trait ParserEvents {
+    /// `name(` has arrived.
+    fn on_call_start(&mut self, _name: &str) {}
+
+    /// A kwarg of the call `name` has been terminated.
+    fn on_kwarg(&mut self, _name: &str, _key: &str, _value: &Value) {}
+
+    /// The call at `index` in the parsed calls is complete.
+    fn on_call_complete(&mut self, _index: usize, _function_call: &FunctionCall) {}
+
+    /// A block end marker has been passed.
+    fn on_block_end(&mut self) {}
+}
+
+// Collects the events that `feed_events` returns
+impl ParserEvents for Vec<ParseEvent> {
+    fn on_call_start(&mut self, name: &str) {
+        self.push(ParseEvent::FunctionStarted {
+            name: name.to_string(),
+        });
+    }
+
+    fn on_kwarg(&mut self, name: &str, key: &str, value: &Value) {
+        self.push(ParseEvent::ArgumentParsed {
+            name: name.to_string(),
+            key: key.to_string(),
+            value: value.clone(),
+        });
+    }
+
+    fn on_call_complete(&mut self, index: usize, _function_call: &FunctionCall) {
+        self.push(ParseEvent::FunctionCompleted { index });
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionCall {
     pub name: String,
     pub kwargs: HashMap<String, Value>,
+    /// The bytes of the source the call was parsed from, `name(` through its closing `)`.
+    /// A stream's calls are located in the whole stream rather than the chunk they
+    /// completed in. `None` for calls that weren't parsed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<Range<usize>>,
+}
+
+// Calls are equal by name and kwargs, wherever in the source they were found
+impl PartialEq for FunctionCall {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.kwargs == other.kwargs
+    }
+}
+
+impl Eq for FunctionCall {}
+
+// Kwargs are hashed sorted by name, since equal maps may iterate in different orders
+impl Hash for FunctionCall {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        let mut kwargs: Vec<(&String, &Value)> = self.kwargs.iter().collect();
+        kwargs.sort_by_key(|(key, _)| *key);
+        kwargs.hash(state);
+    }
+}
+
+// Formats as `name(k1=v1, k2=v2)`, with kwargs sorted by name for stable output
+impl fmt::Display for FunctionCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut keys: Vec<&String> = self.kwargs.keys().collect();
+        keys.sort();
+
+        write!(f, "{}(", self.name)?;
+        for (i, key) in keys.into_iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}={}", key, self.kwargs[key])?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl FunctionCall {
+    /// The kwargs as a JSON object with sorted keys, see [`Value::to_json`]
+    pub fn arguments_json(&self) -> String {
+        let mut keys: Vec<&String> = self.kwargs.keys().collect();
+        keys.sort();
+
+        let mut out = String::from("{");
+        for (i, key) in keys.into_iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            write_json_string(key, &mut out);
+            out.push_str(": ");
+            write_json(&self.kwargs[key], &mut out);
+        }
+        out.push('}');
+        out
+    }
+
+    /// Deserialize the kwargs into a caller's own argument struct. Values map as in
+    /// [`FunctionCall::arguments_json`], so whole numbers fit integer fields.
+    pub fn deserialize_args<T: DeserializeOwned>(&self) -> Result<T> {
+        let args = serde_json::to_value(JsonArgs(&self.kwargs)).map_err(|err| {
+            ParseError::InvalidJson(format!("failed to serialize arguments: {}", err))
+        })?;
+        serde_json::from_value(args).map_err(|err| {
+            ParseError::InvalidJson(format!("invalid arguments for {}: {}", self.name, err))
+        })
+    }
+
+    /// The call as an OpenAI `tool_calls` entry, with the arguments JSON-encoded as a string
+    pub fn to_openai_format(&self, id: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "type": "function",
+            "function": {
+                "name": self.name,
+                "arguments": self.arguments_json(),
+            },
+        })
+    }
+
+    /// Read a call from an OpenAI `tool_calls` entry, the inverse of
+    /// [`FunctionCall::to_openai_format`]. The `id` is not kept. JSON objects in the arguments
+    /// become lists of alternating keys and values, as Python dicts do.
+    pub fn from_openai_json(value: &serde_json::Value) -> Result<Self> {
+        if let Some(kind) = value.get("type")
+            && kind != "function"
+        {
+            return Err(ParseError::InvalidJson(format!(
+                "unsupported tool call type: {}",
+                kind
+            )));
+        }
+        let function = value
+            .get("function")
+            .ok_or_else(|| ParseError::InvalidJson("tool call has no function".to_string()))?;
+        let name = function
+            .get("name")
+            .and_then(serde_json::Value::as_str)
+            .ok_or_else(|| ParseError::InvalidJson("tool call has no function name".to_string()))?;
+        let arguments = function
+            .get("arguments")
+            .and_then(serde_json::Value::as_str)
+            .ok_or_else(|| {
+                ParseError::InvalidJson(format!("tool call {} has no arguments string", name))
+            })?;
+
+        let arguments: serde_json::Value = serde_json::from_str(arguments).map_err(|err| {
+            ParseError::InvalidJson(format!("invalid arguments for {}: {}", name, err))
+        })?;
+        let serde_json::Value::Object(arguments) = arguments else {
+            return Err(ParseError::InvalidJson(format!(
+                "arguments for {} are not a JSON object",
+                name
+            )));
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            kwargs: arguments
+                .iter()
+                .map(|(key, value)| (key.clone(), Value::from_json(value)))
+                .collect(),
+            span: None,
+        })
+    }
+
+    /// Read a call from a `{"name": ..., "kwargs": {...}}` object, as [`Value::to_json`]
+    /// writes a nested call inside its `__call__` tag, converting each kwarg with
+    /// [`Value::from_json`]. For callers that already have the JSON, e.g. from an HTTP
+    /// response, rather than the text.
+    pub fn from_json_object(value: &serde_json::Value) -> Result<Self> {
+        let invalid = |message: &str| ParseError::InvalidJson(message.to_string());
+        let object = value
+            .as_object()
+            .ok_or_else(|| invalid("tool call is not an object"))?;
+        let name = object
+            .get("name")
+            .and_then(serde_json::Value::as_str)
+            .ok_or_else(|| invalid("tool call has no name"))?;
+        let kwargs = match object.get("kwargs") {
+            Some(serde_json::Value::Object(kwargs)) => kwargs,
+            Some(_) => return Err(invalid("kwargs are not an object")),
+            None => return Err(invalid("tool call has no kwargs")),
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            kwargs: kwargs
+                .iter()
+                .map(|(key, value)| (key.clone(), Value::from_json(value)))
+                .collect(),
+            span: None,
+        })
+    }
+
+    /// Call with no kwargs, to be filled in with [`FunctionCall::with_kwarg`]
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            kwargs: HashMap::new(),
+            span: None,
+        }
+    }
+
+    /// Set `key` to `value`, replacing any existing value
+    pub fn with_kwarg(mut self, key: &str, value: Value) -> Self {
+        self.kwargs.insert(key.to_string(), value);
+        self
+    }
+
+    /// Remove `key` if present
+    pub fn without_kwarg(mut self, key: &str) -> Self {
+        self.kwargs.remove(key);
+        self
+    }
+
+    /// Copy with the kwargs renamed by `mapping`, e.g. from a fine-tune's `city` to the
+    /// tool's `location`. Keys missing from the mapping are kept as they are.
+    pub fn rename_kwargs(&self, mapping: &HashMap<&str, &str>) -> FunctionCall {
+        self.transform_kwargs(|key, value| match mapping.get(key.as_str()) {
+            Some(renamed) => (renamed.to_string(), value),
+            None => (key, value),
+        })
+    }
+
+    /// Copy with `f` applied to every kwarg. Where two kwargs end up with the same key,
+    /// only one of them is kept.
+    pub fn transform_kwargs<F: Fn(String, Value) -> (String, Value)>(&self, f: F) -> FunctionCall {
+        FunctionCall {
+            name: self.name.clone(),
+            kwargs: self
+                .kwargs
+                .iter()
+                .map(|(key, value)| f(key.clone(), value.clone()))
+                .collect(),
+            span: self.span.clone(),
+        }
+    }
+
+    /// String kwarg, or `None` if absent or not a string
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        match self.kwargs.get(key) {
+            Some(Value::String(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Integer kwarg; numbers with a fractional part or outside the `i64` range are rejected
+    pub fn get_i64(&self, key: &str) -> Option<i64> {
+        match self.kwargs.get(key) {
+            Some(Value::Number(n))
+                if n.fract() == 0.0 && *n >= i64::MIN as f64 && *n < i64::MAX as f64 =>
+            {
+                Some(*n as i64)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get_f64(&self, key: &str) -> Option<f64> {
+        match self.kwargs.get(key) {
+            Some(Value::Number(n)) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.kwargs.get(key) {
+            Some(Value::Bool(b)) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn get_bytes(&self, key: &str) -> Option<&[u8]> {
+        match self.kwargs.get(key) {
+            Some(Value::Bytes(bytes)) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn get_list(&self, key: &str) -> Option<&[Value]> {
+        match self.kwargs.get(key) {
+            Some(Value::List(items)) => Some(items),
+            _ => None,
+        }
+    }
 }
@@ -1,19 +1,66 @@
 use logos::{Lexer, Logos, Span};
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
-use crate::{FunctionCall, Value};
+use crate::error::{ParseError, Result};
+use crate::nom_parser::{
+    balanced_len, expects_value, find_list_candidate, float_from_str, is_balanced,
+    is_identifier_char, parse_number, partial_marker_len, python_block_state, track_significant,
+    unescape_bytes, unescape_string,
+};
+use crate::{
+    Diagnostic, DuplicateKwarg, FunctionCall, IncrementalBackend, MAX_NESTING_DEPTH_LIMIT,
+    ParserConfig, ParserEvents, ToolCallEvent, Value, default_max_nesting_depth,
+    skip_import_statements,
+};
+use std::borrow::Cow;
 
-pub type Error = (String, Span);
-pub type Result<T> = std::result::Result<T, Error>;
+/// Carried in the lexer's extras: the problems the parser recovered from, shared so that
+/// the copies of the lexer used to peek ahead don't each clone the list, and the options
+/// of the `ParserConfig` that change how tokens are parsed.
+#[derive(Debug, Clone)]
+pub struct Extras {
+    diagnostics: Rc<RefCell<Vec<Diagnostic>>>,
+    allow_surrounding_text: bool,
+    on_duplicate_kwarg: DuplicateKwarg,
+    max_nesting_depth: usize,
+    // Fail on a mismatched delimiter instead of recovering from it
+    strict: bool,
+    // How many values enclose the one being parsed
+    depth: usize,
+}
+
+impl Default for Extras {
+    fn default() -> Self {
+        Self {
+            diagnostics: Rc::default(),
+            allow_surrounding_text: true,
+            on_duplicate_kwarg: DuplicateKwarg::default(),
+            max_nesting_depth: default_max_nesting_depth(),
+            strict: false,
+            depth: 0,
+        }
+    }
+}
 
 /// Simplified Python tokens focusing only on list syntax and function calls with kwargs
 #[derive(Debug, Logos, Clone, PartialEq)]
 #[logos(skip r"[ \t\r\n\f]+")]
+// Python comments run to the end of the line; a `#` inside a string is part of its token
+#[logos(skip r"#[^\n]*")]
+#[logos(extras = Extras)]
 pub enum Token {
     #[token("False", |_| false)]
     #[token("True", |_| true)]
     Bool(bool),
 
+    #[token("None")]
+    NoneKeyword,
+
+    #[token("...")]
+    Ellipsis,
+
     #[token("<|python_start|>")]
     PythonStart,
 
@@ -26,6 +73,12 @@ pub enum Token {
     #[token("]")]
     BracketClose,
 
+    #[token("{")]
+    BraceOpen,
+
+    #[token("}")]
+    BraceClose,
+
     #[token("(")]
     ParenOpen,
 
@@ -38,27 +91,113 @@ pub enum Token {
     #[token("=")]
     Equals,
 
-    #[regex(r"-?(?:0|[1-9]\d*)(?:\.\d+)?(?:[eE][+-]?\d+)?", |lex| lex.slice().parse::<f64>().unwrap())]
+    #[token(":")]
+    Colon,
+
+    #[token("*")]
+    Star,
+
+    #[token("**")]
+    DoubleStar,
+
+    // Only the start of a number; `number` lexes the rest
+    #[regex(r"(?:[+-][ \t\r\n\f]*)?\.?[0-9]", number)]
     Number(f64),
 
-    #[regex(r#"(?:"(?:[^"\\\n]|\\.)*"|'(?:[^'\\\n]|\\.)*')"#, |lex| {
+    #[token("\"", quoted_string)]
+    #[token("'", quoted_string)]
+    #[regex(r#"[rR](?:"(?:[^"\\\n]|\\.)*"|'(?:[^'\\\n]|\\.)*')"#, |lex| {
+        let s = lex.slice();
+        // Raw strings keep their backslashes, so only the prefix and quotes are removed
+        s[2..s.len()-1].to_string()
+    })]
+    #[regex(r#"[fF](?:"(?:[^"\\\n]|\\.)*"|'(?:[^'\\\n]|\\.)*')"#, |lex| {
+        let s = lex.slice();
+        // F-strings are plain strings, with their braces kept as written
+        unescape_string(&s[2..s.len()-1])
+    })]
+    #[regex(r#"(?:[rR][fF]|[fF][rR])(?:"(?:[^"\\\n]|\\.)*"|'(?:[^'\\\n]|\\.)*')"#, |lex| {
         let s = lex.slice();
-        // Remove the quotes
-        s[1..s.len()-1].to_owned()
+        s[3..s.len()-1].to_string()
     })]
     String(String),
 
-    #[regex(r"[a-zA-Z_][a-zA-Z0-9_]*", |lex| lex.slice().to_owned())]
+    #[regex(r#"[bB](?:"(?:[^"\\\n]|\\.)*"|'(?:[^'\\\n]|\\.)*')"#, |lex| {
+        let s = lex.slice();
+        unescape_bytes(&s[2..s.len()-1])
+    })]
+    Bytes(Vec<u8>),
+
+    #[regex(r"[\p{XID_Start}_]\p{XID_Continue}*", |lex| lex.slice().to_owned())]
     Identifier(String),
 }
 
+// Lex the number the regex started with the nom engine's grammar, so both engines agree
+// on what a number is. Anything `parse_number` rejects becomes an error token, recovered
+// from like other input the lexer can't read, rather than a panic.
+fn number(lex: &mut Lexer<'_, Token>) -> Option<f64> {
+    let (rest, value) = parse_number(&lex.source()[lex.span().start..]).ok()?;
+    let end = lex.source().len() - rest.len();
+    lex.bump(end - lex.span().end);
+    Some(value)
+}
+
+/// Lex the rest of a string once its opening quote has matched, resolving escapes the same
+/// way the nom engine does. A quote with no closing one on its line, such as an apostrophe
+/// in prose, is an error of just its own length, so lexing resumes right after it.
+fn quoted_string(lex: &mut Lexer<'_, Token>) -> Option<String> {
+    let quote = lex.slice().chars().next()?;
+    let body = lex.remainder();
+    let mut escaped = false;
+    for (i, c) in body.char_indices() {
+        if c == '\n' {
+            return None;
+        } else if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            lex.bump(i + 1);
+            return Some(unescape_string(&body[..i]));
+        }
+    }
+    None
+}
+
 /// Parse the input, specifically formatted for the example text
 pub fn parse_python(source: &str) -> Result<Vec<FunctionCall>> {
+    parse_python_with_diagnostics(source).map(|(function_calls, _)| function_calls)
+}
+
+/// Parse the input like `parse_python`, but fail with the first problem the parser would
+/// otherwise recover from, e.g. input the lexer can't read inside a call or `key=` with
+/// no value.
+pub fn parse_python_strict(source: &str) -> Result<Vec<FunctionCall>> {
+    let extras = Extras {
+        strict: true,
+        ..Extras::default()
+    };
+    let (function_calls, diagnostics) = parse_with_extras(source, extras)?;
+    match diagnostics.into_iter().next() {
+        Some(diagnostic) => Err(diagnostic.into()),
+        None => Ok(function_calls),
+    }
+}
+
+/// Parse the input like `parse_python`, also returning a diagnostic for each problem the
+/// parser recovered from instead of failing, e.g. a skipped token or `key=` with no value.
+pub fn parse_python_with_diagnostics(source: &str) -> Result<(Vec<FunctionCall>, Vec<Diagnostic>)> {
+    parse_with_extras(source, Extras::default())
+}
+
+fn parse_with_extras(source: &str, extras: Extras) -> Result<(Vec<FunctionCall>, Vec<Diagnostic>)> {
     let mut outer_list: Vec<FunctionCall> = Vec::new();
 
     // Use a single approach to find all function calls
     // We'll use the nested function call parser which is more comprehensive
-    let inner_functions = parse_nested_function_calls(source)?;
+    let mut lexer = Token::lexer_with_extras(source, extras);
+    lexer.bump(source.len() - skip_import_statements(source).len());
+    let inner_functions = parse_function_lists(&mut lexer)?;
     tracing::debug!(
         "Results from nested function calls: {} items",
         inner_functions.len()
@@ -74,17 +213,434 @@ pub fn parse_python(source: &str) -> Result<Vec<FunctionCall>> {
     }
 
     tracing::debug!("Final result has {} items", outer_list.len());
-    Ok(outer_list)
+    let diagnostics = lexer.extras.diagnostics.take();
+    Ok((outer_list, diagnostics))
+}
+
+// Record a problem the parser recovered from at `span`
+fn warn(lexer: &Lexer<'_, Token>, message: String, span: Span) {
+    tracing::debug!("Recovered from {} at {:?}", message, span);
+    lexer.extras.diagnostics.borrow_mut().push(Diagnostic {
+        message,
+        snippet: lexer.source()[span.clone()].to_string(),
+        span,
+    });
+}
+
+// Record that the token just read was skipped
+fn warn_skipped(lexer: &Lexer<'_, Token>) {
+    warn(
+        lexer,
+        format!("skipped unexpected token '{}'", lexer.slice()),
+        lexer.span(),
+    );
+}
+
+// Record that the lexer could not read the input just consumed
+fn warn_unrecognised(lexer: &Lexer<'_, Token>) {
+    let slice = lexer.slice();
+    let message = if slice.starts_with(['"', '\'']) {
+        "unterminated string".to_string()
+    } else {
+        format!("unrecognised input '{}'", slice)
+    };
+    warn(lexer, message, lexer.span());
+}
+
+// Record that the token just read was skipped, whether or not the lexer could read it
+fn warn_token(lexer: &Lexer<'_, Token>, token: &Option<std::result::Result<Token, ()>>) {
+    match token {
+        Some(Ok(_)) => warn_skipped(lexer),
+        Some(Err(())) => warn_unrecognised(lexer),
+        None => {}
+    }
+}
+
+// The token just read closes something other than what is open, `expected`. Strict
+// parsing fails with it, or with an earlier problem, and otherwise it is recorded and
+// the caller recovers as before.
+fn mismatched_delimiter(lexer: &Lexer<'_, Token>, expected: &str) -> Result<()> {
+    let found = lexer.slice().to_string();
+    if lexer.extras.strict {
+        if let Some(diagnostic) = lexer.extras.diagnostics.borrow().first() {
+            return Err(diagnostic.clone().into());
+        }
+        return Err(ParseError::MismatchedDelimiter {
+            expected: expected.to_string(),
+            found,
+            span: lexer.span(),
+        });
+    }
+    warn(
+        lexer,
+        format!(
+            "mismatched delimiter: expected '{}', found '{}'",
+            expected, found
+        ),
+        lexer.span(),
+    );
+    Ok(())
+}
+
+/// Count the top-level function calls in the source without building them. Walks the
+/// same token stream as `parse_python`: a call is an identifier followed by `(` inside a
+/// list, and calls nested in another call's arguments are not counted.
+pub fn count_function_calls(source: &str) -> usize {
+    let mut count = 0;
+    let mut bracket_depth = 0usize;
+    let mut paren_depth = 0usize;
+    let mut after_identifier = false;
+
+    for token in Token::lexer(source) {
+        let is_identifier = matches!(token, Ok(Token::Identifier(_)));
+        match token {
+            Ok(Token::ParenOpen) => {
+                if after_identifier && paren_depth == 0 && bracket_depth > 0 {
+                    count += 1;
+                }
+                paren_depth += 1;
+            }
+            Ok(Token::ParenClose) => paren_depth = paren_depth.saturating_sub(1),
+            Ok(Token::BracketOpen) if paren_depth == 0 => bracket_depth += 1,
+            Ok(Token::BracketClose) if paren_depth == 0 => {
+                bracket_depth = bracket_depth.saturating_sub(1)
+            }
+            // A block end closes anything left open by a truncated call
+            Ok(Token::PythonEnd) => {
+                bracket_depth = 0;
+                paren_depth = 0;
+            }
+            _ => {}
+        }
+        after_identifier = is_identifier;
+    }
+
+    count
+}
+
+/// Number of tokens the lexer produces for the source, skipping input it can't lex.
+pub(crate) fn count_tokens(source: &str) -> usize {
+    Token::lexer(source).filter(|token| token.is_ok()).count()
+}
+
+/// Parse the input using custom block markers and the other options of `config`. The
+/// lexer only knows the built-in `<|python_start|>`/`<|python_end|>` tokens, so custom
+/// markers are rewritten to those first. There is no bare call to fall back to, so
+/// `recovery` has no effect.
+pub fn parse_python_with_config(source: &str, config: &ParserConfig) -> Result<Vec<FunctionCall>> {
+    let extras = Extras {
+        allow_surrounding_text: config.allow_surrounding_text && !config.strict,
+        on_duplicate_kwarg: config.on_duplicate_kwarg,
+        max_nesting_depth: config.max_nesting_depth.min(MAX_NESTING_DEPTH_LIMIT),
+        strict: config.strict,
+        ..Extras::default()
+    };
+    let (normalized, shifts) = normalize_markers(source, config);
+    let (mut function_calls, diagnostics) = parse_with_extras(&normalized, extras)?;
+    for function_call in &mut function_calls {
+        if let Some(span) = &mut function_call.span {
+            *span = locate_in_source(span.start, &shifts)..locate_in_source(span.end, &shifts);
+        }
+    }
+    if config.strict
+        && let Some(diagnostic) = diagnostics.into_iter().next()
+    {
+        return Err(diagnostic.into());
+    }
+    config.filter_names(function_calls)
+}
+
+// Rewrite custom markers to the built-in ones. Also returns where each rewritten marker
+// ends in the new text and in `source`, for `locate_in_source`.
+fn normalize_markers<'a>(
+    source: &'a str,
+    config: &ParserConfig,
+) -> (Cow<'a, str>, Vec<(usize, usize)>) {
+    let default = ParserConfig::default();
+    let markers: Vec<(&str, &str)> = [
+        (&config.start_marker, &default.start_marker),
+        (&config.end_marker, &default.end_marker),
+    ]
+    .into_iter()
+    .filter(|(marker, builtin)| !marker.is_empty() && marker != builtin)
+    .map(|(marker, builtin)| (marker.as_str(), builtin.as_str()))
+    .collect();
+    if markers.is_empty() {
+        return (Cow::Borrowed(source), Vec::new());
+    }
+
+    let mut normalized = String::with_capacity(source.len());
+    let mut shifts = Vec::new();
+    let mut pos = 0;
+    loop {
+        let next = markers
+            .iter()
+            .filter_map(|&(marker, builtin)| {
+                let found = source[pos..].find(marker)?;
+                Some((pos + found, marker, builtin))
+            })
+            .min_by_key(|&(found, _, _)| found);
+        let Some((found, marker, builtin)) = next else {
+            break;
+        };
+        normalized.push_str(&source[pos..found]);
+        normalized.push_str(builtin);
+        pos = found + marker.len();
+        shifts.push((normalized.len(), pos));
+    }
+    normalized.push_str(&source[pos..]);
+    (Cow::Owned(normalized), shifts)
+}
+
+// Map an offset outside the markers in the text `normalize_markers` returned back to the
+// same place in its source
+fn locate_in_source(offset: usize, shifts: &[(usize, usize)]) -> usize {
+    match shifts.iter().rev().find(|&&(end, _)| end <= offset) {
+        Some(&(end, source_end)) => source_end + (offset - end),
+        None => offset,
+    }
+}
+
+/// Incremental state for the logos engine. The lexer works on a whole input, so chunks
+/// are buffered and the part of the buffer whose brackets balance is parsed, then dropped;
+/// a call after it may still be growing and is not reported. Prose that cannot start a
+/// function list is dropped as it arrives, so chunks of it cost no more than their length.
+#[derive(Debug, Clone, Default)]
+pub struct LogosParserState {
+    buffer: String,
+    // Bytes of the stream dropped from the front of `buffer`, to locate calls in the stream
+    dropped: usize,
+    // Calls in `buffer` that have already been reported
+    reported: usize,
+    // Whether the text dropped from the buffer left a python block open, and how many
+    // blocks it ended
+    in_python_block: bool,
+    blocks_ended: usize,
+    parsed_functions: Vec<FunctionCall>,
+    emit_events: bool,
+    events: Vec<ToolCallEvent>,
+    // Whether `finish` recovered a call from an unbalanced buffer
+    truncated: bool,
+}
+
+impl LogosParserState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `ToolCallEvent`s for `drain_events`. Calls are only known once they
+    /// complete, so each one produces its start, all of its arguments and its end at once.
+    pub fn set_emit_events(&mut self, emit_events: bool) {
+        self.emit_events = emit_events;
+    }
+
+    // Re-parse the first `len` bytes of the buffer, returning the calls beyond those
+    // already reported
+    fn parse_buffer(&mut self, len: usize) -> Result<Vec<FunctionCall>> {
+        let mut function_calls = parse_python(&self.buffer[..len])?;
+        let reported = self.reported.min(function_calls.len());
+        let mut new_functions = function_calls.split_off(reported);
+        for function_call in &mut new_functions {
+            if let Some(span) = &mut function_call.span {
+                *span = self.dropped + span.start..self.dropped + span.end;
+            }
+        }
+        self.reported = reported + new_functions.len();
+        if self.emit_events {
+            let first_index = self.parsed_functions.len();
+            for (index, function_call) in (first_index..).zip(&new_functions) {
+                self.events.extend([
+                    ToolCallEvent::CallStarted {
+                        index,
+                        name: function_call.name.clone(),
+                    },
+                    ToolCallEvent::ArgumentsDelta {
+                        index,
+                        json_fragment: function_call.arguments_json(),
+                    },
+                    ToolCallEvent::CallFinished { index },
+                ]);
+            }
+        }
+        self.parsed_functions.extend(new_functions.iter().cloned());
+        Ok(new_functions)
+    }
+
+    // Drop the first `len` bytes of the buffer, remembering whether they leave a python
+    // block open
+    fn drop_input(&mut self, len: usize) {
+        let config = ParserConfig::default();
+        let dropped = &self.buffer[..len];
+        self.in_python_block = python_block_state(dropped, &config, self.in_python_block);
+        self.blocks_ended += dropped.matches(config.end_marker.as_str()).count();
+        self.buffer.drain(..len);
+        self.dropped += len;
+    }
+
+    // End markers seen so far, whether or not they have been dropped
+    fn count_blocks_ended(&self) -> usize {
+        let end_marker = ParserConfig::default().end_marker;
+        self.blocks_ended + self.buffer.matches(end_marker.as_str()).count()
+    }
+}
+
+impl IncrementalBackend for LogosParserState {
+    fn feed(&mut self, chunk: &str) -> Result<Vec<FunctionCall>> {
+        self.buffer.push_str(chunk);
+        let config = ParserConfig::default();
+        // Keep a marker split across chunks so it is seen whole next time
+        let prose_len = match find_list_candidate(&self.buffer, &config) {
+            Ok(offset) => offset,
+            Err(resume_at) if resume_at == self.buffer.len() => {
+                resume_at - partial_marker_len(&self.buffer, &config)
+            }
+            Err(resume_at) => resume_at,
+        };
+        self.drop_input(prose_len);
+
+        // Every list in the balanced part of the buffer is closed, so once it is parsed it
+        // is not needed again
+        let balanced = balanced_len(&self.buffer);
+        if balanced == 0 {
+            return Ok(vec![]);
+        }
+        let new_functions = self.parse_buffer(balanced)?;
+        let parsed_len = balanced - partial_marker_len(&self.buffer[..balanced], &config);
+        self.drop_input(parsed_len);
+        self.reported = 0;
+        Ok(new_functions)
+    }
+
+    fn finish(&mut self) -> Result<Vec<FunctionCall>> {
+        // Unlike `feed`, recover whatever a truncated trailing call contains
+        let recovered = self.parse_buffer(self.buffer.len())?;
+        self.truncated = !recovered.is_empty() && !self.is_complete();
+        Ok(self.parsed_functions.clone())
+    }
+
+    fn parsed(&self) -> &[FunctionCall] {
+        &self.parsed_functions
+    }
+
+    fn is_complete(&self) -> bool {
+        is_balanced(&self.buffer)
+    }
+
+    fn reset(&mut self) {
+        self.buffer.clear();
+        self.dropped = 0;
+        self.reported = 0;
+        self.in_python_block = false;
+        self.blocks_ended = 0;
+        self.parsed_functions.clear();
+        self.events.clear();
+        self.truncated = false;
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    fn is_inside_python_block(&self) -> bool {
+        python_block_state(&self.buffer, &ParserConfig::default(), self.in_python_block)
+    }
+
+    fn current_call_name(&self) -> Option<&str> {
+        open_call_name(&self.buffer)
+    }
+
+    fn has_pending_input(&self) -> bool {
+        // A balanced buffer has already been parsed in full
+        !self.is_complete()
+    }
+
+    fn drain_events(&mut self) -> Vec<ToolCallEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    // Calls are only known once they complete, so each produces all of its events at once,
+    // followed by the block ends in the chunk
+    fn feed_with(
+        &mut self,
+        chunk: &str,
+        events: &mut dyn ParserEvents,
+    ) -> Result<Vec<FunctionCall>> {
+        let blocks_ended = self.count_blocks_ended();
+        let first_index = self.parsed_functions.len();
+
+        let function_calls = self.feed(chunk)?;
+        for (index, function_call) in (first_index..).zip(&function_calls) {
+            events.on_call_start(&function_call.name);
+            let mut keys: Vec<&String> = function_call.kwargs.keys().collect();
+            keys.sort();
+            for key in keys {
+                events.on_kwarg(&function_call.name, key, &function_call.kwargs[key]);
+            }
+            events.on_call_complete(index, function_call);
+        }
+        for _ in blocks_ended..self.count_blocks_ended() {
+            events.on_block_end();
+        }
+        Ok(function_calls)
+    }
+}
+
+/// Name of the outermost call in `source` whose parenthesis is still open. Quotes are
+/// treated as in `is_balanced`, so apostrophes in prose do not open a string.
+fn open_call_name(source: &str) -> Option<&str> {
+    // One entry per open bracket, holding the call name when it is `name(`
+    let mut open: Vec<Option<&str>> = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut prev_significant: Option<char> = None;
+
+    for (i, c) in source.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+                prev_significant = Some(c);
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' if !open.is_empty() && expects_value(prev_significant) => quote = Some(c),
+            '(' => {
+                let before = &source[..i];
+                let name_start = before
+                    .char_indices()
+                    .rev()
+                    .take_while(|&(_, c)| is_identifier_char(c))
+                    .last()
+                    .map_or(i, |(start, _)| start);
+                open.push(Some(&source[name_start..i]).filter(|name| !name.is_empty()));
+            }
+            '[' | '{' => open.push(None),
+            ')' | ']' | '}' => {
+                open.pop();
+            }
+            _ => {}
+        }
+        track_significant(&mut prev_significant, c);
+    }
+
+    open.into_iter().flatten().next()
 }
 
 /// Find all the function calls in the format [function_name(arg="value")]
 /// Also handles comma-separated lists of function calls: [func1(arg1="val1"), func2(arg2="val2")]
 pub fn parse_nested_function_calls(source: &str) -> Result<Vec<Value>> {
+    parse_function_lists(&mut Token::lexer(source))
+}
+
+fn parse_function_lists(lexer: &mut Lexer<'_, Token>) -> Result<Vec<Value>> {
     tracing::debug!("\n---- PARSE_NESTED_FUNCTION_CALLS ----");
-    tracing::debug!("Source: {}", source);
+    tracing::debug!("Source: {}", lexer.source());
     // Reset for the actual parsing
     let mut result = Vec::new();
-    let mut lexer = Token::lexer(source);
     let mut in_python_block = false;
 
     // Simplified approach for direct function list parsing
@@ -97,51 +653,19 @@ pub fn parse_nested_function_calls(source: &str) -> Result<Vec<Value>> {
             }
             Ok(Token::BracketOpen) => {
                 tracing::debug!("Found BracketOpen - parsing function list");
-
-                // Process the first function
-                if let Some(first_func) = parse_next_function_in_list(&mut lexer)? {
-                    tracing::debug!("Parsed first function: {:?}", first_func);
-                    result.push(first_func);
-
-                    // Now look for comma-separated additional functions
-                    loop {
-                        match lexer.next() {
-                            Some(Ok(Token::Comma)) => {
-                                tracing::debug!("Found comma between functions");
-                                // After comma, try to parse another function
-                                if let Some(next_func) = parse_next_function_in_list(&mut lexer)? {
-                                    tracing::debug!("Parsed additional function: {:?}", next_func);
-                                    result.push(next_func);
-                                } else {
-                                    tracing::debug!("No function after comma");
-                                    break;
-                                }
-                            }
-                            Some(Ok(Token::BracketClose)) => {
-                                tracing::debug!("Found BracketClose - end of function list");
-                                break;
-                            }
-                            Some(Ok(Token::PythonEnd)) => {
-                                tracing::debug!("Found PythonEnd");
-                                in_python_block = false;
-                                break;
-                            }
-                            Some(other) => {
-                                tracing::debug!("Unexpected token between functions: {:?}", other);
-                                break;
-                            }
-                            None => {
-                                tracing::debug!("End of input in function list");
-                                break;
-                            }
-                        }
-                    }
-                }
+                parse_function_list(lexer, &mut result, &mut in_python_block)?;
             }
             Ok(Token::PythonEnd) => {
                 tracing::debug!("Found PythonEnd");
                 in_python_block = false;
             }
+            _ if !lexer.extras.allow_surrounding_text => {
+                return Err(ParseError::UnexpectedToken {
+                    found: format!("'{}'", lexer.slice()),
+                    expected: "a function call or list of calls".to_string(),
+                    span: lexer.span(),
+                });
+            }
             _ => {} // Skip other tokens
         }
     }
@@ -150,19 +674,92 @@ pub fn parse_nested_function_calls(source: &str) -> Result<Vec<Value>> {
     Ok(result)
 }
 
+/// Parse a comma-separated list of function calls, starting just after the opening bracket.
+/// Stops at the closing bracket or the end of the Python block, so the caller can keep
+/// scanning for further blocks.
+fn parse_function_list(
+    lexer: &mut Lexer<'_, Token>,
+    result: &mut Vec<Value>,
+    in_python_block: &mut bool,
+) -> Result<()> {
+    // Process the first function
+    let Some(first_func) = parse_next_function_in_list(lexer)? else {
+        return Ok(());
+    };
+    tracing::debug!("Parsed first function: {:?}", first_func);
+    result.push(first_func);
+
+    // Now look for comma-separated additional functions
+    loop {
+        match lexer.next() {
+            Some(Ok(Token::Comma)) => {
+                tracing::debug!("Found comma between functions");
+                // After comma, try to parse another function
+                if let Some(next_func) = parse_next_function_in_list(lexer)? {
+                    tracing::debug!("Parsed additional function: {:?}", next_func);
+                    result.push(next_func);
+                } else {
+                    tracing::debug!("No function after comma");
+                    break;
+                }
+            }
+            Some(Ok(Token::BracketClose)) => {
+                tracing::debug!("Found BracketClose - end of function list");
+                break;
+            }
+            Some(Ok(Token::PythonEnd)) => {
+                tracing::debug!("Found PythonEnd");
+                *in_python_block = false;
+                break;
+            }
+            Some(Ok(Token::BracketOpen)) => {
+                // The previous call was cut off at the end of its block and the
+                // next block's list has started, e.g. `[a(x=1<|python_end|><|python_start|>[b()]`
+                tracing::debug!("Found BracketOpen between functions - new function list");
+                return parse_function_list(lexer, result, in_python_block);
+            }
+            Some(Ok(Token::ParenClose | Token::BraceClose)) => {
+                tracing::debug!("Found mismatched closer in function list");
+                mismatched_delimiter(lexer, "]")?;
+                break;
+            }
+            Some(other) => {
+                tracing::debug!("Unexpected token between functions: {:?}", other);
+                if other.is_ok() {
+                    warn(
+                        lexer,
+                        format!("expected ',' or ']' after call, found '{}'", lexer.slice()),
+                        lexer.span(),
+                    );
+                } else {
+                    warn_unrecognised(lexer);
+                }
+                break;
+            }
+            None => {
+                tracing::debug!("End of input in function list");
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// Parse a single function from the token stream, starting at the function name
 fn parse_next_function_in_list(lexer: &mut Lexer<'_, Token>) -> Result<Option<Value>> {
     // First token should be an identifier (function name)
     match lexer.next() {
         Some(Ok(Token::Identifier(name))) => {
             tracing::debug!("Found function name: {}", name);
+            let start = lexer.span().start;
 
             // Next should be opening parenthesis
             match lexer.next() {
                 Some(Ok(Token::ParenOpen)) => {
                     tracing::debug!("Found opening parenthesis for {}", name);
                     // Parse function arguments
-                    let func_call = parse_function_with_kwargs(lexer, name)?;
+                    let func_call = parse_located_call(lexer, name, start)?;
                     Ok(Some(func_call))
                 }
                 other => {
@@ -211,11 +808,12 @@ fn parse_function_calls_in_list(
                 }
                 Ok(Token::Identifier(name)) => {
                     tracing::debug!("Found Identifier: {}", name);
+                    let start = lexer.span().start;
                     // Found a function name, now check for opening parenthesis
                     if let Some(Ok(Token::ParenOpen)) = lexer.next() {
                         tracing::debug!("Found opening parenthesis for {}", name);
                         // Parse the function arguments
-                        let func_call = parse_function_with_kwargs(lexer, name)?;
+                        let func_call = parse_located_call(lexer, name, start)?;
                         tracing::debug!("Parsed function: {:?}", func_call);
                         result.push(func_call);
                         found_function = true;
@@ -280,12 +878,24 @@ fn parse_function_calls_in_list(
     Ok(())
 }
 
-/// Helper function to handle post-value tokens (comma or closing parenthesis)
+/// Helper function to handle post-value tokens (comma or closing parenthesis).
+/// An identifier where the comma should be, as in `f(a=1 b=2)`, is left unconsumed so
+/// the caller reads it as the next key instead of dropping that kwarg.
 pub fn handle_post_value(
     lexer: &mut Lexer<'_, Token>,
     name: String,
     kwargs: HashMap<String, Value>,
 ) -> Result<Value> {
+    let mut peek = lexer.clone();
+    if let Some(Ok(Token::Identifier(key))) = peek.next() {
+        tracing::debug!(
+            "handle_post_value: Missing comma before {} - read as next key",
+            key
+        );
+        warn(lexer, format!("missing ',' before '{}'", key), peek.span());
+        return Ok(Value::Empty); // Signal to continue
+    }
+
     match lexer.next() {
         Some(Ok(Token::Comma)) => {
             tracing::debug!("handle_post_value: Found comma - continue to next parameter");
@@ -298,18 +908,74 @@ pub fn handle_post_value(
                 name
             );
             // End of arguments
-            Ok(Value::FunctionCall(FunctionCall { name, kwargs }))
+            Ok(Value::FunctionCall(FunctionCall {
+                name,
+                kwargs,
+                span: None,
+            }))
+        }
+        Some(Ok(
+            Token::BracketOpen | Token::BracketClose | Token::BraceClose | Token::PythonEnd,
+        )) => {
+            tracing::debug!("handle_post_value: Mismatched closer in args for {}", name);
+            mismatched_delimiter(lexer, ")")?;
+            // Skip it and continue
+            Ok(Value::Empty) // Signal to continue
         }
         other => {
             tracing::debug!("handle_post_value: Unexpected token: {:?}", other);
+            warn_token(lexer, &other);
             // Skip unexpected tokens and continue
             Ok(Value::Empty) // Signal to continue
         }
     }
 }
 
+/// Parse a top-level call whose name starts at `start`, spanning it up to its `)`. A call
+/// cut off by the end of its list or block ends with the text before that.
+fn parse_located_call(lexer: &mut Lexer<'_, Token>, name: String, start: usize) -> Result<Value> {
+    let mut value = parse_function_with_kwargs(lexer, name)?;
+    if let Value::FunctionCall(function_call) = &mut value {
+        let end = if lexer.slice() == ")" {
+            lexer.span().end
+        } else {
+            lexer.source()[..lexer.span().start].trim_end().len()
+        };
+        function_call.span = Some(start..end);
+    }
+    Ok(value)
+}
+
 /// Parse a function call with keyword arguments
 pub fn parse_function_with_kwargs(lexer: &mut Lexer<'_, Token>, name: String) -> Result<Value> {
+    let mut duplicates = Vec::new();
+    let value = parse_call_arguments(lexer, name, &mut duplicates)?;
+    let Value::FunctionCall(mut function_call) = value else {
+        return Ok(value);
+    };
+
+    // The arguments were parsed into a map, so any repeated key holds its last value
+    match lexer.extras.on_duplicate_kwarg {
+        DuplicateKwarg::KeepLast => {}
+        DuplicateKwarg::KeepFirst => function_call
+            .kwargs
+            .extend(duplicates.into_iter().map(|(key, first, _)| (key, first))),
+        DuplicateKwarg::Error => {
+            if let Some((name, _, span)) = duplicates.into_iter().next() {
+                return Err(ParseError::DuplicateKwarg { name, span });
+            }
+        }
+    }
+    Ok(Value::FunctionCall(function_call))
+}
+
+/// Parse the arguments of a call, recording the first value and the span of the second
+/// occurrence of each key given more than once
+fn parse_call_arguments(
+    lexer: &mut Lexer<'_, Token>,
+    name: String,
+    duplicates: &mut Vec<(String, Value, Span)>,
+) -> Result<Value> {
     tracing::debug!("Parsing function {} with kwargs", name);
     let mut kwargs = HashMap::new();
 
@@ -317,18 +983,35 @@ pub fn parse_function_with_kwargs(lexer: &mut Lexer<'_, Token>, name: String) ->
         match lexer.next() {
             Some(Ok(Token::PythonStart)) => {
                 tracing::debug!("Found PythonStart in kwargs");
+                warn_unclosed_call(lexer, &name);
                 // Start of a new Python block
-                return Ok(Value::FunctionCall(FunctionCall { name, kwargs }));
+                return Ok(Value::FunctionCall(FunctionCall {
+                    name,
+                    kwargs,
+                    span: None,
+                }));
             }
             Some(Ok(Token::ParenClose)) => {
                 tracing::debug!("Found ParenClose - end of arguments for {}", name);
                 // End of arguments
-                return Ok(Value::FunctionCall(FunctionCall { name, kwargs }));
+                return Ok(Value::FunctionCall(FunctionCall {
+                    name,
+                    kwargs,
+                    span: None,
+                }));
             }
             Some(Ok(Token::Identifier(key))) => {
                 tracing::debug!("Found parameter key: {}", key);
+                if let Some(first) = kwargs.get(&key)
+                    && !duplicates.iter().any(|(seen, _, _)| seen == &key)
+                {
+                    duplicates.push((key.clone(), first.clone(), lexer.span()));
+                }
                 // Expect an equals sign
-                if let Some(Ok(Token::Equals)) = lexer.next() {
+                let equals = lexer.next();
+                if !matches!(equals, Some(Ok(Token::Equals))) {
+                    warn(lexer, format!("expected '=' after '{}'", key), lexer.span());
+                } else {
                     tracing::debug!("Found equals sign for {}", key);
                     // Look for value
                     match lexer.next() {
@@ -340,6 +1023,14 @@ pub fn parse_function_with_kwargs(lexer: &mut Lexer<'_, Token>, name: String) ->
                                 return Ok(result);
                             }
                         }
+                        Some(Ok(Token::Bytes(val))) => {
+                            tracing::debug!("Found bytes value: {:?} for {}", val, key);
+                            kwargs.insert(key, Value::Bytes(val));
+                            let result = handle_post_value(lexer, name.clone(), kwargs.clone())?;
+                            if let Value::FunctionCall(_) = result {
+                                return Ok(result);
+                            }
+                        }
                         Some(Ok(Token::Bool(val))) => {
                             tracing::debug!("Found bool value: {} for {}", val, key);
                             kwargs.insert(key, Value::Bool(val));
@@ -356,9 +1047,56 @@ pub fn parse_function_with_kwargs(lexer: &mut Lexer<'_, Token>, name: String) ->
                                 return Ok(result);
                             }
                         }
+                        Some(Ok(Token::NoneKeyword)) => {
+                            tracing::debug!("Found None value for {}", key);
+                            kwargs.insert(key, Value::Null);
+                            let result = handle_post_value(lexer, name.clone(), kwargs.clone())?;
+                            if let Value::FunctionCall(_) = result {
+                                return Ok(result);
+                            }
+                        }
+                        Some(Ok(Token::Ellipsis)) => {
+                            tracing::debug!("Found ellipsis value for {}", key);
+                            kwargs.insert(key, Value::Ellipsis);
+                            let result = handle_post_value(lexer, name.clone(), kwargs.clone())?;
+                            if let Value::FunctionCall(_) = result {
+                                return Ok(result);
+                            }
+                        }
                         Some(Ok(Token::Identifier(val))) => {
                             tracing::debug!("Found identifier value: {} for {}", val, key);
-                            kwargs.insert(key, Value::Identifier(val));
+                            let value = match parse_float_call(lexer, &val) {
+                                Some(number) => Value::Number(number),
+                                None => Value::Identifier(val),
+                            };
+                            kwargs.insert(key, value);
+                            let result = handle_post_value(lexer, name.clone(), kwargs.clone())?;
+                            if let Value::FunctionCall(_) = result {
+                                return Ok(result);
+                            }
+                        }
+                        Some(Ok(Token::BracketOpen)) => {
+                            tracing::debug!("Found list value for {}", key);
+                            let list = parse_kwarg_container(lexer, parse_list_value)?;
+                            kwargs.insert(key, list);
+                            let result = handle_post_value(lexer, name.clone(), kwargs.clone())?;
+                            if let Value::FunctionCall(_) = result {
+                                return Ok(result);
+                            }
+                        }
+                        Some(Ok(Token::ParenOpen)) => {
+                            tracing::debug!("Found tuple value for {}", key);
+                            let tuple = parse_kwarg_container(lexer, parse_tuple_value)?;
+                            kwargs.insert(key, tuple);
+                            let result = handle_post_value(lexer, name.clone(), kwargs.clone())?;
+                            if let Value::FunctionCall(_) = result {
+                                return Ok(result);
+                            }
+                        }
+                        Some(Ok(Token::BraceOpen)) => {
+                            tracing::debug!("Found dict value for {}", key);
+                            let dict = parse_kwarg_container(lexer, parse_dict_value)?;
+                            kwargs.insert(key, dict);
                             let result = handle_post_value(lexer, name.clone(), kwargs.clone())?;
                             if let Value::FunctionCall(_) = result {
                                 return Ok(result);
@@ -366,6 +1104,7 @@ pub fn parse_function_with_kwargs(lexer: &mut Lexer<'_, Token>, name: String) ->
                         }
                         Some(Ok(Token::Comma)) => {
                             tracing::debug!("Found comma after equals - empty parameter");
+                            warn_empty_value(lexer, &key);
                             // Empty parameter value (key=,)
                             kwargs.insert(key, Value::Empty);
                             // Continue to next parameter
@@ -375,12 +1114,21 @@ pub fn parse_function_with_kwargs(lexer: &mut Lexer<'_, Token>, name: String) ->
                             tracing::debug!(
                                 "Found ParenClose after equals - empty parameter at end"
                             );
+                            warn_empty_value(lexer, &key);
                             // Empty parameter at the end (key=))
                             kwargs.insert(key, Value::Empty);
-                            return Ok(Value::FunctionCall(FunctionCall { name, kwargs }));
+                            return Ok(Value::FunctionCall(FunctionCall {
+                                name,
+                                kwargs,
+                                span: None,
+                            }));
                         }
                         other => {
                             tracing::debug!("Unexpected token after equals: {:?}", other);
+                            if let Some(Err(())) = other {
+                                warn_unrecognised(lexer);
+                            }
+                            warn_empty_value(lexer, &key);
                             // For any other token, treat it as an empty value and continue
                             kwargs.insert(key, Value::Empty);
                             continue;
@@ -390,29 +1138,362 @@ pub fn parse_function_with_kwargs(lexer: &mut Lexer<'_, Token>, name: String) ->
             }
             Some(Ok(Token::Comma)) => {
                 tracing::debug!("Found extra comma in arguments");
+                warn_skipped(lexer);
                 // Extra comma, continue
                 continue;
             }
             Some(Ok(Token::BracketOpen)) => {
                 tracing::debug!("Found BracketOpen in function args - nested list");
+                mismatched_delimiter(lexer, ")")?;
                 // We've reached a nested list - we're done with this function call
-                return Ok(Value::FunctionCall(FunctionCall { name, kwargs }));
+                return Ok(Value::FunctionCall(FunctionCall {
+                    name,
+                    kwargs,
+                    span: None,
+                }));
             }
             None => {
                 tracing::debug!("Reached end of input in function args");
+                warn_unclosed_call(lexer, &name);
                 // End of input
-                return Ok(Value::FunctionCall(FunctionCall { name, kwargs }));
+                return Ok(Value::FunctionCall(FunctionCall {
+                    name,
+                    kwargs,
+                    span: None,
+                }));
             }
             Some(Ok(Token::PythonEnd)) => {
                 tracing::debug!("Found PythonEnd in function args");
+                mismatched_delimiter(lexer, ")")?;
                 // End of Python block
-                return Ok(Value::FunctionCall(FunctionCall { name, kwargs }));
+                return Ok(Value::FunctionCall(FunctionCall {
+                    name,
+                    kwargs,
+                    span: None,
+                }));
+            }
+            Some(Ok(Token::BracketClose | Token::BraceClose)) => {
+                tracing::debug!("Skipping mismatched closer in function args");
+                mismatched_delimiter(lexer, ")")?;
+                continue;
             }
             other => {
                 tracing::debug!("Skipping other token in function args: {:?}", other);
+                warn_token(lexer, &other);
                 // Skip any other tokens
                 continue;
             }
         }
     }
 }
+
+fn warn_empty_value(lexer: &Lexer<'_, Token>, key: &str) {
+    warn(
+        lexer,
+        format!("empty argument value for '{}'", key),
+        lexer.span(),
+    );
+}
+
+fn warn_unclosed_call(lexer: &Lexer<'_, Token>, name: &str) {
+    warn(lexer, format!("unclosed call '{}'", name), lexer.span());
+}
+
+/// Parse a value literal that starts with `token`, recursing into lists, dicts and nested
+/// function calls. Returns `None` if the token cannot start a value, and fails once values
+/// nest deeper than `max_nesting_depth`.
+fn parse_value(lexer: &mut Lexer<'_, Token>, token: Token) -> Result<Option<Value>> {
+    // A top-level call's kwarg values are parsed without coming through here
+    nested(lexer, |lexer| parse_nested_value(lexer, token))
+}
+
+/// Parse the list, tuple or dict value of a kwarg with `parse`, once its opening bracket
+/// has been read. Only a nested call's kwarg values count towards the nesting depth, as
+/// they are nested in the value holding the call.
+fn parse_kwarg_container(
+    lexer: &mut Lexer<'_, Token>,
+    parse: fn(&mut Lexer<'_, Token>) -> Result<Value>,
+) -> Result<Value> {
+    if lexer.extras.depth == 0 {
+        parse(lexer)
+    } else {
+        nested(lexer, parse)
+    }
+}
+
+/// Run `parse` one nesting level deeper, failing instead once that is deeper than
+/// `max_nesting_depth`
+fn nested<T>(
+    lexer: &mut Lexer<'_, Token>,
+    parse: impl FnOnce(&mut Lexer<'_, Token>) -> Result<T>,
+) -> Result<T> {
+    let depth = lexer.extras.depth;
+    if depth >= lexer.extras.max_nesting_depth {
+        return Err(ParseError::RecursionLimit {
+            limit: lexer.extras.max_nesting_depth,
+            span: lexer.span(),
+        });
+    }
+    lexer.extras.depth = depth + 1;
+    let value = parse(lexer);
+    lexer.extras.depth = depth;
+    value
+}
+
+fn parse_nested_value(lexer: &mut Lexer<'_, Token>, token: Token) -> Result<Option<Value>> {
+    let value = match token {
+        Token::String(val) => Value::String(val),
+        Token::Bytes(val) => Value::Bytes(val),
+        Token::Bool(val) => Value::Bool(val),
+        Token::Number(val) => Value::Number(val),
+        Token::NoneKeyword => Value::Null,
+        Token::Ellipsis => Value::Ellipsis,
+        Token::BracketOpen => parse_list_value(lexer)?,
+        Token::ParenOpen => parse_tuple_value(lexer)?,
+        Token::BraceOpen => parse_dict_value(lexer)?,
+        Token::Identifier(val) => {
+            if let Some(number) = parse_float_call(lexer, &val) {
+                Value::Number(number)
+            } else if let Some(Ok(Token::ParenOpen)) = lexer.clone().next() {
+                // An identifier directly followed by `(` is a nested function call
+                tracing::debug!("Found nested function call: {}", val);
+                lexer.next();
+                parse_function_with_kwargs(lexer, val)?
+            } else {
+                Value::Identifier(val)
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(value))
+}
+
+/// Parse the `("inf")` after a `float` just read, the only way to write infinity or NaN in
+/// Python source. Leaves the lexer where it was unless the whole call is there.
+fn parse_float_call(lexer: &mut Lexer<'_, Token>, name: &str) -> Option<f64> {
+    if name != "float" {
+        return None;
+    }
+    let mut peek = lexer.clone();
+    let Some(Ok(Token::ParenOpen)) = peek.next() else {
+        return None;
+    };
+    let Some(Ok(Token::String(text))) = peek.next() else {
+        return None;
+    };
+    let Some(Ok(Token::ParenClose)) = peek.next() else {
+        return None;
+    };
+    let number = float_from_str(&text)?;
+    *lexer = peek;
+    Some(number)
+}
+
+/// Parse the name after the `*` or `**` just read, which unpacks it into the enclosing
+/// list or dict. Anything but a name is skipped with a warning.
+fn parse_splat_name(lexer: &mut Lexer<'_, Token>) -> Option<String> {
+    if let Some(Ok(Token::Identifier(name))) = lexer.clone().next() {
+        lexer.next();
+        return Some(name);
+    }
+    warn_skipped(lexer);
+    None
+}
+
+/// Parse a list literal in value position, starting just after the opening bracket.
+/// Nested lists and function calls (e.g. `[[create_test(size=1)]]`) are parsed recursively.
+fn parse_list_value(lexer: &mut Lexer<'_, Token>) -> Result<Value> {
+    tracing::debug!("Parsing list value");
+    let mut items = Vec::new();
+
+    loop {
+        match lexer.next() {
+            Some(Ok(Token::BracketClose)) => {
+                tracing::debug!(
+                    "Found BracketClose - end of list with {} items",
+                    items.len()
+                );
+                return Ok(Value::List(items));
+            }
+            Some(Ok(Token::Comma)) => {
+                // Separator between items
+                continue;
+            }
+            Some(Ok(Token::PythonEnd)) | None => {
+                tracing::debug!("List value not closed before end of input");
+                warn(lexer, "unclosed list".to_string(), lexer.span());
+                return Ok(Value::List(items));
+            }
+            Some(Ok(Token::Star)) => {
+                items.extend(parse_splat_name(lexer).map(Value::ListSplat));
+            }
+            Some(Ok(token)) => match parse_value(lexer, token)? {
+                Some(item) => {
+                    tracing::debug!("Found list item: {:?}", item);
+                    items.push(item);
+                }
+                None => {
+                    tracing::debug!("Skipping non-value token in list");
+                    warn_skipped(lexer);
+                }
+            },
+            Some(Err(err)) => {
+                tracing::debug!("Skipping lexer error in list value: {:?}", err);
+                warn_unrecognised(lexer);
+            }
+        }
+    }
+}
+
+/// Parse a tuple literal in value position, starting just after the opening parenthesis.
+/// A `(` that follows an identifier opens a nested call instead, so here it is always a
+/// tuple, or only grouping when it holds a single item without a trailing comma.
+fn parse_tuple_value(lexer: &mut Lexer<'_, Token>) -> Result<Value> {
+    tracing::debug!("Parsing tuple value");
+    let mut items = Vec::new();
+    let mut saw_comma = false;
+
+    loop {
+        match lexer.next() {
+            token @ (Some(Ok(Token::ParenClose)) | Some(Ok(Token::PythonEnd)) | None) => {
+                tracing::debug!("End of tuple with {} items", items.len());
+                if token != Some(Ok(Token::ParenClose)) {
+                    warn(lexer, "unclosed tuple".to_string(), lexer.span());
+                }
+                if items.len() == 1 && !saw_comma {
+                    return Ok(items.remove(0));
+                }
+                return Ok(Value::Tuple(items));
+            }
+            Some(Ok(Token::Comma)) => {
+                // Separator between items
+                saw_comma = true;
+                continue;
+            }
+            Some(Ok(token)) => match parse_value(lexer, token)? {
+                Some(item) => {
+                    tracing::debug!("Found tuple item: {:?}", item);
+                    items.push(item);
+                }
+                None => {
+                    tracing::debug!("Skipping non-value token in tuple");
+                    warn_skipped(lexer);
+                }
+            },
+            Some(Err(err)) => {
+                tracing::debug!("Skipping lexer error in tuple value: {:?}", err);
+                warn_unrecognised(lexer);
+            }
+        }
+    }
+}
+
+/// Parse a dict or set literal in value position, starting just after the opening brace.
+/// Dicts are represented like the nom engine does, as a list of alternating keys and values.
+/// If the first element is not followed by `:` the literal is read as a set instead.
+fn parse_dict_value(lexer: &mut Lexer<'_, Token>) -> Result<Value> {
+    tracing::debug!("Parsing dict value");
+    let mut entries = Vec::new();
+
+    loop {
+        match lexer.next() {
+            Some(Ok(Token::BraceClose)) => {
+                tracing::debug!(
+                    "Found BraceClose - end of dict with {} entries",
+                    entries.len() / 2
+                );
+                return Ok(Value::List(entries));
+            }
+            Some(Ok(Token::Comma)) => {
+                // Separator between entries
+                continue;
+            }
+            Some(Ok(Token::PythonEnd)) | None => {
+                tracing::debug!("Dict value not closed before end of input");
+                warn(lexer, "unclosed dict".to_string(), lexer.span());
+                return Ok(Value::List(entries));
+            }
+            Some(Ok(Token::DoubleStar)) => {
+                entries.extend(parse_splat_name(lexer).map(Value::DictSplat));
+            }
+            Some(Ok(token)) => {
+                let Some(key) = parse_value(lexer, token)? else {
+                    tracing::debug!("Skipping non-value token in dict");
+                    warn_skipped(lexer);
+                    continue;
+                };
+
+                if let Some(Ok(Token::Colon)) = lexer.clone().next() {
+                    lexer.next();
+                } else if entries.is_empty() {
+                    return parse_set_items(lexer, key);
+                } else {
+                    tracing::debug!("Expected colon after dict key {:?}", key);
+                    warn(
+                        lexer,
+                        "expected ':' after dict key".to_string(),
+                        lexer.span(),
+                    );
+                    continue;
+                }
+
+                // A missing value (`{'a': }`) is recorded as empty, like `key=,` in kwargs
+                let value = match lexer.clone().next() {
+                    Some(Ok(Token::Comma)) | Some(Ok(Token::BraceClose)) | None => None,
+                    _ => match lexer.next() {
+                        Some(Ok(token)) => parse_value(lexer, token)?,
+                        _ => None,
+                    },
+                };
+                let value = value.unwrap_or_else(|| {
+                    warn(lexer, "empty dict value".to_string(), lexer.span());
+                    Value::Empty
+                });
+                tracing::debug!("Found dict entry: {:?}: {:?}", key, value);
+                entries.push(key);
+                entries.push(value);
+            }
+            Some(Err(err)) => {
+                tracing::debug!("Skipping lexer error in dict value: {:?}", err);
+                warn_unrecognised(lexer);
+            }
+        }
+    }
+}
+
+/// Parse the remaining members of a set literal whose first member has been read.
+fn parse_set_items(lexer: &mut Lexer<'_, Token>, first: Value) -> Result<Value> {
+    let mut items = vec![first];
+
+    loop {
+        match lexer.next() {
+            Some(Ok(Token::BraceClose)) => {
+                tracing::debug!("Found BraceClose - end of set with {} items", items.len());
+                return Ok(Value::Set(items));
+            }
+            Some(Ok(Token::Comma)) => {
+                // Separator between items
+                continue;
+            }
+            Some(Ok(Token::PythonEnd)) | None => {
+                tracing::debug!("Set value not closed before end of input");
+                warn(lexer, "unclosed set".to_string(), lexer.span());
+                return Ok(Value::Set(items));
+            }
+            Some(Ok(token)) => match parse_value(lexer, token)? {
+                Some(item) => {
+                    tracing::debug!("Found set item: {:?}", item);
+                    items.push(item);
+                }
+                None => {
+                    tracing::debug!("Skipping non-value token in set");
+                    warn_skipped(lexer);
+                }
+            },
+            Some(Err(err)) => {
+                tracing::debug!("Skipping lexer error in set value: {:?}", err);
+                warn_unrecognised(lexer);
+            }
+        }
+    }
+}
@@ -1,19 +1,29 @@
 use nom::{
-    IResult, Parser,
+    IResult, Offset, Parser,
     branch::alt,
     bytes::complete::{escaped, tag, take_till, take_until, take_while},
-    character::complete::{char, digit1, multispace0, one_of},
-    combinator::{map, map_res, opt, recognize, value},
-    multi::{many0, many1, separated_list0},
+    character::complete::{
+        char, digit0, digit1, multispace0, multispace1, not_line_ending, one_of, satisfy,
+    },
+    combinator::{consumed, cut, map, map_opt, map_res, not, opt, peek, recognize, value},
+    multi::{many0, many0_count, many1, separated_list0, separated_list1},
     sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
 };
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::str::FromStr;
 
-use crate::{FunctionCall, Value};
+use crate::error::{ParseError, ParseStrategy};
+use crate::{
+    Diagnostic, DuplicateKwarg, FunctionCall, FunctionCallRef, MAX_NESTING_DEPTH_LIMIT, ParseEvent,
+    ParserConfig, ParserEvents, ToolCallEvent, Value, ValueRef, default_max_nesting_depth,
+    skip_import_statements, write_json_string,
+};
 
 // Parser state for incremental parsing
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NomParserState {
     // Any partial data from previous parse attempts
     pub remainder: String,
@@ -25,15 +35,70 @@ pub struct NomParserState {
     pub in_function_list: bool,
     // Current function being built
     pub current_function: Option<PartialFunction>,
+    // Byte offset into `remainder` up to which input has been processed
+    pub consumed: usize,
+    // Bytes of the stream dropped from the front of `remainder`, to locate calls in it
+    #[serde(default)]
+    pub drained: usize,
+    // Bytes the scans for the end of an element or kwarg have read over the stream, which
+    // only grows with the input if nothing is scanned twice. Tests read it through
+    // `testing::bytes_scanned`; it is not part of a snapshot.
+    #[serde(skip)]
+    pub(crate) scanned: usize,
+    // Offset in `remainder` of the `[` opening the current function list
+    pub list_start: usize,
+    // Calls parsed from the current function list, reported once the list closes
+    pub pending_functions: Vec<FunctionCall>,
+    // Whether the current list expects a `,` or `]` next rather than a call
+    pub expect_separator: bool,
+    // Largest number of bytes that may stay buffered without completing a call
+    pub max_buffer_size: Option<usize>,
+    // Whether `finish` had to complete a call cut off by the end of the stream
+    pub truncated: bool,
+    // Whether to record `ToolCallEvent`s for `drain_events`
+    pub emit_events: bool,
+    // Events recorded since the last `drain_events`
+    pub events: Vec<ToolCallEvent>,
+    // Index the next call to start will be given in its events
+    pub next_call_index: usize,
+    // How far the scan for the end of the list element still arriving has got
+    pub item_scan: TerminatorScan,
+    // Markers, names and limits the stream is parsed with
+    #[serde(default)]
+    pub config: ParserConfig,
 }
 
 // Track a function being parsed
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PartialFunction {
     pub name: String,
+    // Kwargs whose value has been terminated by a `,` or `)`
     pub kwargs: HashMap<String, Value>,
     // inside the function's parentheses?
     pub in_args: bool,
+    // Offset in `remainder` of the next kwarg, just after the `(` or a `,`
+    pub args_pos: usize,
+    // How far the scan for the end of the kwarg at `args_pos` has got
+    pub kwarg_scan: TerminatorScan,
+    // Index of the call in its `ToolCallEvent`s
+    #[serde(default)]
+    pub index: usize,
+    // The closing `)` has been sent as a `ToolCallEvent`
+    #[serde(default)]
+    pub finished: bool,
+}
+
+// Resumable search for the `,` or closing bracket ending a list element or kwarg. Text
+// still arriving is scanned once as it comes in, rather than from its start on every chunk.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TerminatorScan {
+    // Offset, from the start of the element or kwarg, up to which it has been scanned
+    pub pos: usize,
+    pub depth: usize,
+    pub quote: Option<char>,
+    pub escaped: bool,
+    // Inside a `# ...` comment, which runs to the end of the line
+    pub comment: bool,
 }
 
 impl NomParserState {
@@ -44,15 +109,88 @@ impl NomParserState {
             in_python_block: false,
             in_function_list: false,
             current_function: None,
+            consumed: 0,
+            drained: 0,
+            scanned: 0,
+            list_start: 0,
+            pending_functions: Vec::new(),
+            expect_separator: false,
+            max_buffer_size: None,
+            truncated: false,
+            emit_events: false,
+            events: Vec::new(),
+            next_call_index: 0,
+            item_scan: TerminatorScan::default(),
+            config: ParserConfig::default(),
+        }
+    }
+
+    // Create a state that parses the stream with `config`: its markers, `allowed_names`,
+    // `max_calls`, counted over the whole stream, and `on_duplicate_kwarg`, whose error
+    // span is relative to the call. The other options only apply to whole inputs.
+    pub fn with_config(config: ParserConfig) -> Self {
+        Self {
+            config,
+            ..Self::new()
         }
     }
 
+    // Create a state whose buffer may not grow beyond `max_buffer_size` bytes. Prose that
+    // cannot start a call is dropped as it arrives, so only an unfinished function list
+    // counts towards the limit; exceeding it makes `parse_incremental` return an error.
+    pub fn with_max_buffer_size(max_buffer_size: usize) -> Self {
+        Self {
+            max_buffer_size: Some(max_buffer_size),
+            ..Self::new()
+        }
+    }
+
+    // Snapshot the state, e.g. to move an in-flight stream to another worker. The bytes are
+    // CBOR and restore with `from_bytes` into a state that continues where this one stopped.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(self, &mut bytes).expect("Failed to serialize parser state");
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        ciborium::from_reader(bytes).map_err(|err| ParseError::InvalidState(err.to_string()))
+    }
+
+    pub fn set_max_buffer_size(&mut self, max_buffer_size: Option<usize>) {
+        self.max_buffer_size = max_buffer_size;
+    }
+
+    // Record `ToolCallEvent`s while parsing, to be collected with `drain_events`
+    pub fn set_emit_events(&mut self, emit_events: bool) {
+        self.emit_events = emit_events;
+    }
+
+    // Take the events recorded since the last call. A call is started as soon as `name(`
+    // arrives and each kwarg is sent once it is terminated, so events run ahead of the
+    // parsed calls: a list later abandoned as malformed leaves events for calls that are
+    // never reported. An event's index counts every call started, so a call after an
+    // abandoned list never shares its index with one of the abandoned calls.
+    pub fn drain_events(&mut self) -> Vec<ToolCallEvent> {
+        std::mem::take(&mut self.events)
+    }
+
     pub fn reset(&mut self) {
         self.remainder = String::new();
         self.parsed_functions = Vec::new();
         self.in_python_block = false;
         self.in_function_list = false;
         self.current_function = None;
+        self.consumed = 0;
+        self.drained = 0;
+        self.scanned = 0;
+        self.list_start = 0;
+        self.pending_functions = Vec::new();
+        self.expect_separator = false;
+        self.truncated = false;
+        self.events = Vec::new();
+        self.next_call_index = 0;
+        self.item_scan = TerminatorScan::default();
     }
 
     pub fn add_input(&mut self, input: &str) {
@@ -62,6 +200,105 @@ impl NomParserState {
     pub fn get_parsed_functions(&self) -> Vec<FunctionCall> {
         self.parsed_functions.clone()
     }
+
+    // Whether the buffered input is a balanced expression: every bracket, paren and
+    // string opened so far has been closed and no function is mid-parse
+    pub fn is_complete(&self) -> bool {
+        self.current_function.is_none() && is_balanced(&self.remainder)
+    }
+
+    pub fn is_inside_python_block(&self) -> bool {
+        self.in_python_block
+    }
+
+    // Whether a call's arguments are still arriving; `current_call_name` says which call
+    pub fn is_inside_call(&self) -> bool {
+        self.current_function.is_some()
+    }
+
+    pub fn current_call_name(&self) -> Option<&str> {
+        self.current_function.as_ref().map(|function| function.name.as_str())
+    }
+
+    // The call whose arguments are still arriving, with the kwargs completed so far
+    pub fn current_partial(&self) -> Option<&PartialFunction> {
+        self.current_function.as_ref()
+    }
+
+    // Whether any buffered input has yet to become a call or be dropped as prose
+    pub fn has_pending_input(&self) -> bool {
+        !self.remainder.is_empty()
+    }
+
+    // Signal the end of the stream, treating it as closing every open string, bracket and
+    // argument list. Calls still waiting for their list's `]` are kept, and a call cut off
+    // part-way is completed from its name and the kwargs closed so far, setting `truncated`.
+    // Returns every call parsed from the stream.
+    pub fn finish(&mut self) -> Result<Vec<FunctionCall>, ParseError> {
+        let rest = if self.in_function_list {
+            &self.remainder[self.consumed..]
+        } else {
+            // Only a trailing `[name` can be left over outside a list
+            self.remainder.strip_prefix('[').unwrap_or("")
+        };
+        let item = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        let item_start = self.drained + self.remainder.len() - item.len();
+
+        self.truncated = false;
+        if !item.is_empty() {
+            let limit = self.config.max_nesting_depth;
+            match with_nesting_limit(limit, || close_truncated_call(item)) {
+                Some((mut function_call, truncated)) => {
+                    function_call.span = Some(item_start..item_start + item.trim_end().len());
+                    if self.emit_events {
+                        let partial = self.current_function.take();
+                        finish_call_events(self, partial.as_ref(), &function_call);
+                    }
+                    self.pending_functions.push(function_call);
+                    self.truncated = truncated;
+                }
+                // Not a call, so the list was never a function list
+                None => self.pending_functions.clear(),
+            }
+        }
+
+        let pending = std::mem::take(&mut self.pending_functions);
+        let mut admitted = self.admit_calls(pending)?;
+        self.parsed_functions.append(&mut admitted);
+        self.drained += self.remainder.len();
+        self.remainder.clear();
+        self.consumed = 0;
+        self.in_function_list = false;
+        self.expect_separator = false;
+        self.current_function = None;
+        self.item_scan = TerminatorScan::default();
+        Ok(self.get_parsed_functions())
+    }
+
+    // Apply `allowed_names` and `max_calls` to calls about to be reported, counting those
+    // reported before towards the limit
+    fn admit_calls(
+        &self,
+        function_calls: Vec<FunctionCall>,
+    ) -> Result<Vec<FunctionCall>, ParseError> {
+        let mut admitted = Vec::with_capacity(function_calls.len());
+        for mut function_call in function_calls {
+            if self.config.admit_name(&mut function_call)? {
+                admitted.push(function_call);
+            }
+        }
+        if let Some(limit) = self.config.max_calls {
+            let room = limit.saturating_sub(self.parsed_functions.len());
+            if admitted.len() > room && self.config.strict {
+                return Err(ParseError::TooManyCalls {
+                    count: self.parsed_functions.len() + admitted.len(),
+                    limit,
+                });
+            }
+            admitted.truncate(room);
+        }
+        Ok(admitted)
+    }
 }
 
 impl Default for NomParserState {
@@ -70,13 +307,21 @@ impl Default for NomParserState {
     }
 }
 
+// Match a keyword such as `None`, but not the prefix of a longer identifier like `Nonexistent`
+fn keyword<'a>(word: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    terminated(
+        tag(word),
+        not(peek(satisfy(is_identifier_char))),
+    )
+}
+
 // Parse a boolean
 fn parse_bool(input: &str) -> IResult<&str, bool> {
-    alt((value(true, tag("True")), value(false, tag("False"))))(input)
+    alt((value(true, keyword("True")), value(false, keyword("False"))))(input)
 }
 
 // Helper function to handle escaped characters
-fn unescape_string(s: &str) -> String {
+pub(crate) fn unescape_string(s: &str) -> String {
     let mut result = String::new();
     let mut chars = s.chars().peekable();
     
@@ -106,203 +351,626 @@ fn unescape_string(s: &str) -> String {
     result
 }
 
-// Parse a string with escape sequences (either single or double quoted)
-fn parse_string(input: &str) -> IResult<&str, String> {
-    alt((
-        // Double quoted string - more permissive with escaped characters
-        map(
-            delimited(
-                char('"'),
-                escaped(
-                    take_while(|c| c != '"' && c != '\\'),
-                    '\\',
-                    one_of("\"\\nrt!(){}[].;:"), // Accept common escaped characters
-                ),
-                char('"'),
-            ),
-            unescape_string,
-        ),
-        // Single quoted string - more permissive with escaped characters
-        map(
-            delimited(
-                char('\''),
-                escaped(
-                    take_while(|c| c != '\'' && c != '\\'),
-                    '\\',
-                    one_of("'\\nrt!(){}[].;:"), // Accept common escaped characters
-                ),
-                char('\''),
-            ),
-            unescape_string,
-        ),
-    ))(input)
+// Like `unescape_string`, for the body of a bytes literal: `\xNN` gives any byte, and other
+// characters are kept as their UTF-8 encoding
+pub(crate) fn unescape_bytes(s: &str) -> Vec<u8> {
+    let mut result = Vec::new();
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        let c = if c == '\\' {
+            match chars.next() {
+                Some('x') => {
+                    let hex = chars.as_str().get(..2).unwrap_or("");
+                    // `from_str_radix` would also take a sign, as in `\x+f`
+                    let digits = hex.len() == 2 && hex.bytes().all(|b| b.is_ascii_hexdigit());
+                    match u8::from_str_radix(hex, 16) {
+                        Ok(byte) if digits => {
+                            result.push(byte);
+                            chars.nth(1);
+                        }
+                        // Not a valid escape, so keep it as written
+                        _ => result.extend_from_slice(b"\\x"),
+                    }
+                    continue;
+                }
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some(other) => other,
+                None => '\\',
+            }
+        } else {
+            c
+        };
+        result.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+    }
+
+    result
+}
+
+// Parse a string with escape sequences (single or double quoted, optionally raw). It is
+// borrowed from the input unless escapes have to be resolved.
+fn parse_string(input: &str) -> IResult<&str, Cow<'_, str>> {
+    traced("parse_string", input, |input| {
+        // A raw string such as `r"\d+"` keeps its backslashes. An f-string such as
+        // `f"Hi {name}"` is read as a plain string, with its braces kept as written for the
+        // caller to fill in.
+        let prefix_len =
+            input.bytes().take(3).position(|b| b == b'"' || b == b'\'').unwrap_or(0);
+        let (prefix, input) = input.split_at(prefix_len);
+        let raw = ["r", "rf", "fr"].iter().any(|p| prefix.eq_ignore_ascii_case(p));
+        if !raw && !prefix.is_empty() && !prefix.eq_ignore_ascii_case("f") {
+            return Err(nom::Err::Error(nom::error::Error::new(
+                input,
+                nom::error::ErrorKind::Char,
+            )));
+        }
+        let (rest, body) = quoted_body(input)?;
+        let value = if raw || !body.contains('\\') {
+            Cow::Borrowed(body)
+        } else {
+            Cow::Owned(unescape_string(body))
+        };
+        Ok((rest, value))
+    })
+}
+
+// Parse a bytes literal such as `b"\x00\xff"`, borrowed from the input unless it has escapes
+fn parse_bytes(input: &str) -> IResult<&str, Cow<'_, [u8]>> {
+    map(preceded(one_of("bB"), quoted_body), |body: &str| {
+        if body.contains('\\') {
+            Cow::Owned(unescape_bytes(body))
+        } else {
+            Cow::Borrowed(body.as_bytes())
+        }
+    })(input)
 }
 
-// Parse a number (integer or float)
-fn parse_number(input: &str) -> IResult<&str, f64> {
-    map_res(
+// The still-escaped interior of a single or double quoted literal. It is scanned by hand so
+// that empty strings and any escaped character are accepted.
+fn quoted_body(input: &str) -> IResult<&str, &str> {
+    let quote = match input.chars().next() {
+        Some(c @ ('"' | '\'')) => c,
+        _ => {
+            return Err(nom::Err::Error(nom::error::Error::new(
+                input,
+                nom::error::ErrorKind::Char,
+            )));
+        }
+    };
+
+    let body = &input[1..];
+    let mut escaped = false;
+    for (i, c) in body.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return Ok((&body[i + 1..], &body[..i]));
+        }
+    }
+
+    // Unterminated string
+    Err(nom::Err::Error(nom::error::Error::new(
+        input,
+        nom::error::ErrorKind::Char,
+    )))
+}
+
+// Parse a number (integer or float), with an optional unary `+` or `-`. As in Python,
+// either side of the decimal point may be empty, e.g. `.5e3` or `2.`, and the exponent is
+// optional. As Python allows, whitespace may separate the sign from the digits, e.g.
+// `- 5`. There is no arithmetic, so `1+2` is not a number. The logos engine lexes
+// numbers with this parser too, so both engines agree on what a number is.
+pub(crate) fn parse_number(input: &str) -> IResult<&str, f64> {
+    let (input, sign) = opt(terminated(one_of("+-"), multispace0))(input)?;
+    let (rest, magnitude) = map_res(
         recognize(tuple((
-            opt(char('-')),
-            digit1,
-            opt(tuple((char('.'), digit1))),
+            alt((
+                recognize(pair(digit1, opt(pair(char('.'), digit0)))),
+                recognize(pair(char('.'), digit1)),
+            )),
             opt(tuple((one_of("eE"), opt(one_of("+-")), digit1))),
         ))),
         |s: &str| f64::from_str(s),
+    )(input)?;
+    Ok((rest, if sign == Some('-') { -magnitude } else { magnitude }))
+}
+
+// Parse a `float("...")` call, the only way to write infinity or NaN in Python source
+fn parse_float_call(input: &str) -> IResult<&str, f64> {
+    map_opt(
+        preceded(
+            pair(keyword("float"), preceded(ws, char('('))),
+            terminated(preceded(ws, parse_string), preceded(ws, char(')'))),
+        ),
+        |text| float_from_str(&text),
     )(input)
 }
 
-// Parse an identifier
+// Read the string passed to `float()`, e.g. "inf", "-inf", "nan" or "1.5"
+pub(crate) fn float_from_str(text: &str) -> Option<f64> {
+    f64::from_str(text.trim()).ok()
+}
+
+// Parse an identifier. Like Python (PEP 3131), letters from any script are allowed, e.g.
+// `天気`, `température` or `खोज`.
 fn parse_identifier(input: &str) -> IResult<&str, String> {
-    map(
-        recognize(pair(
-            one_of("abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ_"),
-            take_while(|c: char| c.is_alphanumeric() || c == '_'),
-        )),
-        |s: &str| s.to_string(),
-    )(input)
+    map(identifier, |s: &str| s.to_string())(input)
 }
 
-// Forward declaration to handle recursive types
-fn parse_value(input: &str) -> IResult<&str, Value> {
-    preceded(
-        multispace0,
-        alt((
-            map(parse_bool, Value::Bool),
-            map(parse_string, Value::String),
-            map(parse_number, Value::Number),
-            map(tag("None"), |_| Value::Empty),
-            parse_list,
-            parse_dict,
-            map(parse_identifier, Value::Identifier),
-        )),
-    )(input)
+fn identifier(input: &str) -> IResult<&str, &str> {
+    recognize(pair(
+        satisfy(is_identifier_start),
+        take_while(is_identifier_char),
+    ))(input)
+}
+
+// Whether `c` can start a Python identifier: `_` or any character with XID_Start
+pub(crate) fn is_identifier_start(c: char) -> bool {
+    c == '_' || unicode_ident::is_xid_start(c)
+}
+
+// Whether `c` can continue a Python identifier, which also allows digits and combining
+// marks such as the vowel signs of Devanagari
+pub(crate) fn is_identifier_char(c: char) -> bool {
+    unicode_ident::is_xid_continue(c)
+}
+
+// Skip whitespace and `# ...` comments between tokens. Strings are parsed whole, so a `#`
+// inside one is never taken for a comment.
+fn ws(input: &str) -> IResult<&str, &str> {
+    recognize(many0_count(alt((
+        multispace1,
+        recognize(pair(char('#'), not_line_ending)),
+    ))))(input)
+}
+
+// `input` with its leading whitespace and comments skipped
+fn skip_ws(input: &str) -> &str {
+    ws(input).map_or(input, |(rest, _)| rest)
+}
+
+// The first 50 characters of `input`, to keep trace output short
+fn preview(input: &str) -> &str {
+    input.char_indices().nth(50).map_or(input, |(end, _)| &input[..end])
+}
+
+// Run `parser` on `input`, tracing where it starts and whether it matched. The arguments
+// to `trace!` are only evaluated when a subscriber wants them, so this costs nothing
+// otherwise.
+fn traced<'a, O>(
+    name: &'static str,
+    input: &'a str,
+    mut parser: impl FnMut(&'a str) -> IResult<&'a str, O>,
+) -> IResult<&'a str, O> {
+    tracing::trace!("{}: {:?}", name, preview(input));
+    let result = parser(input);
+    match &result {
+        Ok((rest, _)) => tracing::trace!("{} matched {} bytes", name, input.len() - rest.len()),
+        Err(_) => tracing::trace!("{} failed", name),
+    }
+    result
 }
 
-// Parse a list: [value1, value2, ...]
-fn parse_list(input: &str) -> IResult<&str, Value> {
+thread_local! {
+    // How many values enclose the one being parsed, and how many may, which
+    // `with_nesting_limit` sets for the duration of a parse
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+    static MAX_NESTING_DEPTH: Cell<usize> = Cell::new(default_max_nesting_depth());
+}
+
+// Run `parse` with values allowed to nest `limit` deep, or `MAX_NESTING_DEPTH_LIMIT`
+fn with_nesting_limit<T>(limit: usize, parse: impl FnOnce() -> T) -> T {
+    let outer_limit = MAX_NESTING_DEPTH.replace(limit.min(MAX_NESTING_DEPTH_LIMIT));
+    let outer_depth = DEPTH.replace(0);
+    let result = parse();
+    MAX_NESTING_DEPTH.set(outer_limit);
+    DEPTH.set(outer_depth);
+    result
+}
+
+// Forward declaration to handle recursive types. Fails with `ErrorKind::TooLarge` once
+// values nest deeper than `MAX_NESTING_DEPTH`, before the recursion can overflow the stack.
+fn parse_value(input: &str) -> IResult<&str, ValueRef<'_>> {
+    let depth = DEPTH.get();
+    if depth > MAX_NESTING_DEPTH.get() {
+        return Err(nom::Err::Failure(nom::error::Error::new(
+            skip_ws(input),
+            nom::error::ErrorKind::TooLarge,
+        )));
+    }
+    DEPTH.set(depth + 1);
+    let result = traced(
+        "parse_value",
+        input,
+        preceded(
+            ws,
+            alt((
+                map(parse_bool, ValueRef::Bool),
+                map(parse_bytes, ValueRef::Bytes),
+                map(parse_string, ValueRef::String),
+                map(parse_number, ValueRef::Number),
+                map(parse_float_call, ValueRef::Number),
+                map(keyword("None"), |_| ValueRef::Null),
+                map(tag("..."), |_| ValueRef::Ellipsis),
+                parse_list,
+                parse_tuple,
+                parse_set,
+                parse_dict,
+                map(identifier, ValueRef::Identifier),
+            )),
+        ),
+    );
+    DEPTH.set(depth);
+    result
+}
+
+// Parse a list: [value1, value2, ...], where an item may be `*name`
+fn parse_list(input: &str) -> IResult<&str, ValueRef<'_>> {
+    traced(
+        "parse_list",
+        input,
+        map(
+            delimited(
+                char('['),
+                separated_list0(
+                    preceded(ws, char(',')),
+                    preceded(ws, parse_list_item),
+                ),
+                preceded(ws, char(']')),
+            ),
+            ValueRef::List,
+        ),
+    )
+}
+
+// Parse a list item, a value or `*name`
+fn parse_list_item(input: &str) -> IResult<&str, ValueRef<'_>> {
+    alt((
+        map(preceded(char('*'), preceded(ws, identifier)), ValueRef::ListSplat),
+        parse_value,
+    ))(input)
+}
+
+// Parse a tuple: (), (value,) or (value1, value2, ...). As in Python, a single value needs a
+// trailing comma; without one the parentheses only group it, so `(1)` is just `1`.
+fn parse_tuple(input: &str) -> IResult<&str, ValueRef<'_>> {
+    let (rest, (mut items, trailing_comma)) = delimited(
+        char('('),
+        pair(
+            separated_list0(
+                preceded(ws, char(',')),
+                preceded(ws, parse_value),
+            ),
+            opt(preceded(ws, char(','))),
+        ),
+        preceded(ws, char(')')),
+    )(input)?;
+    match (items.len(), trailing_comma) {
+        // `(,)` is not a tuple
+        (0, Some(_)) => Err(nom::Err::Error(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::SeparatedList,
+        ))),
+        (1, None) => Ok((rest, items.remove(0))),
+        _ => Ok((rest, ValueRef::Tuple(items))),
+    }
+}
+
+// Parse a set: {value1, value2, ...}. Tried before `parse_dict`; any element followed by
+// `:` makes this fail so the input is parsed as a dict instead. `{}` is an empty dict.
+fn parse_set(input: &str) -> IResult<&str, ValueRef<'_>> {
     map(
         delimited(
-            char('['),
-            separated_list0(
-                preceded(multispace0, char(',')),
-                preceded(multispace0, parse_value),
+            char('{'),
+            terminated(
+                separated_list1(
+                    preceded(ws, char(',')),
+                    preceded(ws, parse_value),
+                ),
+                opt(preceded(ws, char(','))),
             ),
-            preceded(multispace0, char(']')),
+            preceded(ws, char('}')),
         ),
-        Value::List,
+        ValueRef::Set,
     )(input)
 }
 
-// Parse a dict: {'key1': value1, 'key2': value2, ...}
-fn parse_dict(input: &str) -> IResult<&str, Value> {
-    // Parse a dict directly
-    delimited(
-        char('{'),
+// Parse a dict entry, `'key': value` or `**name`. A splat has no value to go with it.
+fn parse_dict_item(input: &str) -> IResult<&str, (ValueRef<'_>, Option<ValueRef<'_>>)> {
+    alt((
+        map(preceded(tag("**"), preceded(ws, identifier)), |name| {
+            (ValueRef::DictSplat(name), None)
+        }),
         map(
-            separated_list0(
-                preceded(multispace0, char(',')),
-                preceded(
-                    multispace0,
-                    separated_pair(
-                        // Keys must be strings
-                        parse_string, 
-                        preceded(multispace0, char(':')), 
-                        parse_value
+            separated_pair(
+                // Keys must be strings
+                parse_string, 
+                preceded(ws, char(':')), 
+                parse_value
+            ),
+            |(key, value)| (ValueRef::String(key), Some(value)),
+        ),
+    ))(input)
+}
+
+// Parse a dict: {'key1': value1, 'key2': value2, **name, ...}
+fn parse_dict(input: &str) -> IResult<&str, ValueRef<'_>> {
+    traced(
+        "parse_dict",
+        input,
+        // Parse a dict directly
+        delimited(
+            char('{'),
+            map(
+                // A trailing comma is only allowed after an entry, as in Python
+                opt(terminated(
+                    separated_list1(
+                        preceded(ws, char(',')),
+                        preceded(ws, parse_dict_item),
                     ),
-                ),
+                    opt(preceded(ws, char(','))),
+                )),
+                |entries| {
+                    // Convert the entries to a list with alternating keys and values
+                    let mut values = Vec::new();
+                    for (key, value) in entries.unwrap_or_default() {
+                        values.push(key);
+                        values.extend(value);
+                    }
+                    ValueRef::List(values)
+                },
             ),
-            |entries| {
-                // Convert the entries to a list with alternating keys and values
-                let mut values = Vec::new();
-                for (key, value) in entries {
-                    values.push(Value::String(key));
-                    values.push(value);
-                }
-                Value::List(values)
-            },
+            preceded(ws, char('}')),
         ),
-        preceded(multispace0, char('}')),
-    )(input)
+    )
 }
 
-// Parse a keyword argument
-fn parse_kwarg(input: &str) -> IResult<&str, (String, Value)> {
+// Parse a keyword argument. Once its `=` has been seen the value must follow, so a bad
+// value fails the whole call where the value starts instead of backtracking.
+fn parse_kwarg_ref(input: &str) -> IResult<&str, (&str, ValueRef<'_>)> {
     separated_pair(
-        parse_identifier,
-        preceded(multispace0, char('=')),
-        preceded(multispace0, parse_value),
+        identifier,
+        preceded(ws, char('=')),
+        preceded(ws, cut(parse_value)),
     )(input)
 }
 
+// Parse a keyword argument into an owned key and value
+fn parse_kwarg(input: &str) -> IResult<&str, (String, Value)> {
+    map(parse_kwarg_ref, |(key, value)| (key.to_string(), value.into_owned()))(input)
+}
+
 // Parse a function's arguments
-fn parse_kwargs(input: &str) -> IResult<&str, HashMap<String, Value>> {
-    map(
-        delimited(
-            char('('),
-            separated_list0(
-                preceded(multispace0, char(',')),
-                preceded(multispace0, parse_kwarg),
-            ),
-            preceded(multispace0, char(')')),
+pub(crate) fn parse_kwargs(input: &str) -> IResult<&str, HashMap<String, Value>> {
+    traced(
+        "parse_kwargs",
+        input,
+        map(parse_kwarg_list, |pairs| {
+            pairs
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value.into_owned()))
+                .collect()
+        }),
+    )
+}
+
+// Parse a function's arguments in the order they were written
+fn parse_kwarg_list(input: &str) -> IResult<&str, Vec<(&str, ValueRef<'_>)>> {
+    delimited(
+        char('('),
+        separated_list0(
+            preceded(ws, char(',')),
+            preceded(ws, parse_kwarg_ref),
         ),
-        |pairs| pairs.into_iter().collect(),
+        cut(preceded(ws, char(')'))),
     )(input)
 }
 
+// Copy a call out of `source` into an owned one, handling a key given more than once
+// according to `policy`
+fn resolve_duplicate_kwargs(
+    source: &str,
+    function_call: FunctionCallRef<'_>,
+    policy: DuplicateKwarg,
+) -> Result<FunctionCall, ParseError> {
+    let mut kwargs = HashMap::with_capacity(function_call.kwargs.len());
+    for (key, value) in function_call.kwargs {
+        if kwargs.contains_key(key) {
+            match policy {
+                DuplicateKwarg::KeepLast => {}
+                DuplicateKwarg::KeepFirst => continue,
+                DuplicateKwarg::Error => {
+                    let start = source.offset(key);
+                    return Err(ParseError::DuplicateKwarg {
+                        name: key.to_string(),
+                        span: start..start + key.len(),
+                    });
+                }
+            }
+        }
+        kwargs.insert(key.to_string(), value.into_owned());
+    }
+    Ok(FunctionCall {
+        name: function_call.name.to_string(),
+        kwargs,
+        span: None,
+    })
+}
+
 // Parse a function call: name(arg1="value1", arg2=42)
 fn parse_function_call(input: &str) -> IResult<&str, FunctionCall> {
-    map(pair(parse_identifier, parse_kwargs), |(name, kwargs)| {
-        FunctionCall { name, kwargs }
-    })(input)
+    map(parse_function_call_ref, FunctionCallRef::into_owned)(input)
+}
+
+// Parse a function call borrowing from the input
+fn parse_function_call_ref(input: &str) -> IResult<&str, FunctionCallRef<'_>> {
+    tracing::debug!("parse_function_call: {:?}", preview(input));
+    let result = map(pair(identifier, parse_kwarg_list), |(name, kwargs)| {
+        FunctionCallRef { name, kwargs }
+    })(input);
+    match &result {
+        Ok((_, function_call)) => tracing::debug!("Parsed function call: {}", function_call.name),
+        Err(_) => tracing::debug!("No function call at {:?}", preview(input)),
+    }
+    result
+}
+
+// A parsed call together with the exact source text it was parsed from. The text is
+// borrowed, so carrying it through the parsers costs nothing when it is not wanted.
+type RawCall<'a> = (FunctionCallRef<'a>, &'a str);
+
+fn parse_raw_function_call(input: &str) -> IResult<&str, RawCall<'_>> {
+    map(consumed(parse_function_call_ref), |(raw, call)| (call, raw))(input)
+}
+
+// Set the call's span to where `raw`, the text it was parsed from, lies in `source`
+fn locate(source: &str, mut function_call: FunctionCall, raw: &str) -> FunctionCall {
+    let start = source.offset(raw);
+    function_call.span = Some(start..start + raw.len());
+    function_call
+}
+
+fn strip_raw(source: &str, calls: Vec<RawCall<'_>>) -> Vec<FunctionCall> {
+    calls
+        .into_iter()
+        .map(|(call, raw)| locate(source, call.into_owned(), raw))
+        .collect()
 }
 
 // Parse a list of function calls: [func1(arg1="val1"), func2(arg2="val2")]
-fn parse_function_list(input: &str) -> IResult<&str, Vec<FunctionCall>> {
+fn parse_function_list(input: &str) -> IResult<&str, Vec<RawCall<'_>>> {
     delimited(
         char('['),
         separated_list0(
-            preceded(multispace0, char(',')),
-            preceded(multispace0, parse_function_call),
+            preceded(ws, char(',')),
+            preceded(ws, parse_raw_function_call),
         ),
-        preceded(multispace0, char(']')),
+        preceded(ws, char(']')),
     )(input)
 }
 
-// Parse a Python block: <|python_start|>[function_calls]<|python_end|>
-fn parse_python_block(input: &str) -> IResult<&str, Vec<FunctionCall>> {
+// Parse a Python block: <|python_start|>[function_calls]<|python_end|>, using the
+// configured start and end markers
+fn parse_python_block<'a>(
+    input: &'a str,
+    config: &ParserConfig,
+) -> IResult<&'a str, Vec<RawCall<'a>>> {
     delimited(
-        tag("<|python_start|>"),
-        parse_function_list,
-        tag("<|python_end|>"),
+        tag(config.start_marker.as_str()),
+        preceded(ws, parse_function_list),
+        preceded(ws, tag(config.end_marker.as_str())),
+    )(input)
+}
+
+// Parse one or more consecutive Python blocks, concatenating their calls in order
+fn parse_python_blocks<'a>(
+    input: &'a str,
+    config: &ParserConfig,
+) -> IResult<&'a str, Vec<RawCall<'a>>> {
+    map(
+        pair(
+            |i| parse_python_block(i, config),
+            // A malformed later block ends the run rather than failing the blocks before it
+            many0(preceded(
+                multispace0,
+                backtrack(|i| parse_python_block(i, config)),
+            )),
+        ),
+        |(mut functions, rest)| {
+            for mut block in rest {
+                functions.append(&mut block);
+            }
+            functions
+        },
     )(input)
 }
 
+// Let a parser that failed past a `cut` be backtracked over like any other error
+fn backtrack<'a, O>(
+    mut parser: impl FnMut(&'a str) -> IResult<&'a str, O>,
+) -> impl FnMut(&'a str) -> IResult<&'a str, O> {
+    move |input| match parser(input) {
+        Err(nom::Err::Failure(e)) => Err(nom::Err::Error(e)),
+        result => result,
+    }
+}
+
 // Top-level parser that handles both Python blocks and bare function lists
 pub fn parse_python_nom(input: &str) -> IResult<&str, Vec<FunctionCall>> {
-    alt((parse_python_block, parse_function_list))(input)
+    tracing::debug!("parse_python_nom: {:?}", preview(input));
+    let result = map(
+        |i| parse_python_nom_with_config(i, &ParserConfig::default()),
+        |calls| strip_raw(input, calls),
+    )(input);
+    match &result {
+        Ok((_, function_calls)) => {
+            tracing::debug!("Parsed {} function calls", function_calls.len())
+        }
+        Err(err) => tracing::debug!("parse_python_nom failed: {:?}", err),
+    }
+    result
+}
+
+// Top-level parser using the configured block markers. A bare call such as
+// `get_weather(city="NYC")` with no enclosing list is accepted last, as a one-element list.
+fn parse_python_nom_with_config<'a>(
+    input: &'a str,
+    config: &ParserConfig,
+) -> IResult<&'a str, Vec<RawCall<'a>>> {
+    alt((
+        |i| parse_python_blocks(i, config),
+        parse_function_list,
+        map(preceded(multispace0, parse_raw_function_call), |call| vec![call]),
+    ))(input)
 }
 
 // Parse function calls that may be anywhere in the text with surrounding content
-pub fn parse_python_with_surrounding_text(input: &str) -> Result<Vec<FunctionCall>, String> {
+pub fn parse_python_with_surrounding_text(input: &str) -> Result<Vec<FunctionCall>, ParseError> {
+    tracing::debug!("parse_python_with_surrounding_text: {:?}", preview(input));
+    let (all_functions, _, _) = scan_surrounding_text(input, &ParserConfig::default());
+    tracing::debug!("Found {} function calls in surrounding text", all_functions.len());
+    Ok(strip_raw(input, all_functions))
+}
+
+// Scan the text for Python blocks or function lists, returning the parsed calls
+// together with the text that follows the last successfully parsed block. The text is
+// `None` if no block or list was found at all, which distinguishes an explicit empty
+// list (`[]`, the model choosing no tools) from input without any tool calls. Also
+// returns the offset of each candidate that could not be parsed and where it went wrong.
+fn scan_surrounding_text<'a>(
+    input: &'a str,
+    config: &ParserConfig,
+) -> (Vec<RawCall<'a>>, Option<&'a str>, Vec<(usize, ParseError)>) {
     let mut all_functions = Vec::new();
     let mut remaining = input;
-    
+    let mut after_last_block = None;
+    let mut abandoned = Vec::new();
+
     // Continue searching through the text until we've processed it all
     while !remaining.is_empty() {
         // Try to find a Python block or function list starting anywhere in the remaining text
-        if let Some(start_pos) = find_next_pattern_start(remaining) {
+        if let Some(start_pos) = find_next_pattern_start(remaining, config) {
             // Skip to the start of the pattern
             let from_pattern = &remaining[start_pos..];
-            
+
             // Try to parse from this position
-            match parse_python_nom(from_pattern) {
+            match parse_python_nom_with_config(from_pattern, config) {
                 Ok((rest, mut functions)) => {
                     // Add the found functions
                     all_functions.append(&mut functions);
                     // Continue with the remaining text after this parse
                     remaining = rest;
+                    after_last_block = Some(rest);
                 }
-                Err(_) => {
+                Err(e) => {
+                    let error = nom_error(input, e);
+                    tracing::debug!("Gave up on candidate at {}: {}", start_pos, error);
+                    abandoned.push((input.len() - from_pattern.len(), error));
                     // If parsing failed, skip this character and try again
                     if remaining.len() > start_pos + 1 {
                         remaining = &remaining[start_pos + 1..];
@@ -316,90 +984,1119 @@ pub fn parse_python_with_surrounding_text(input: &str) -> Result<Vec<FunctionCal
             break;
         }
     }
-    
-    Ok(all_functions)
+
+    (all_functions, after_last_block, abandoned)
 }
 
-// Find the next position where a Python block or function list might start
-fn find_next_pattern_start(input: &str) -> Option<usize> {
-    // Look for either "<|python_start|>" or "["
-    let python_start = input.find("<|python_start|>");
-    let bracket_start = input.find('[');
-    
-    match (python_start, bracket_start) {
-        (Some(p), Some(b)) => Some(p.min(b)),
-        (Some(p), None) => Some(p),
-        (None, Some(b)) => Some(b),
-        (None, None) => None,
+// Report a candidate the scan gave up on, spanning from its start to where it went wrong
+fn abandoned_candidate(source: &str, start: usize, error: &ParseError) -> Diagnostic {
+    let end = error.span().map_or(start, |span| span.end.min(source.len()));
+    let end = if end > start {
+        end
+    } else {
+        start + source[start..].chars().next().map_or(0, char::len_utf8)
+    };
+    Diagnostic {
+        message: format!("skipped candidate that failed to parse: {}", error),
+        snippet: source[start..end].to_string(),
+        span: start..end,
+    }
+}
+
+// Find the next position where a Python block or function list might start. A `[` is
+// only a candidate when it is followed by `name(` or closes immediately, so bracketed
+// prose and markdown links are skipped, as is anything inside a string literal that
+// follows `=`, `(`, `,` or `[`. A `[` after `=` is a kwarg's value, so the arguments of a
+// call that failed to parse are never rescanned as a function list of their own.
+fn find_next_pattern_start(input: &str, config: &ParserConfig) -> Option<usize> {
+    let mut chars = input.char_indices();
+    let mut prev_significant: Option<char> = None;
+
+    while let Some((i, c)) = chars.next() {
+        if !config.start_marker.is_empty() && input[i..].starts_with(config.start_marker.as_str()) {
+            return Some(i);
+        }
+        match c {
+            '[' if prev_significant != Some('=') && looks_like_list_start(&input[i + 1..]) => {
+                return Some(i);
+            }
+            '"' | '\'' if matches!(prev_significant, Some('=' | '(' | ',' | '[')) => {
+                if let Some(len) = string_literal_len(&input[i..]) {
+                    // Resume scanning after the closing quote
+                    let end = i + len;
+                    while chars.offset() < end {
+                        chars.next();
+                    }
+                }
+            }
+            _ => {}
+        }
+        track_significant(&mut prev_significant, c);
+    }
+    None
+}
+
+// Whether every bracket, paren, brace and argument string literal in `input` is closed.
+// Quotes only open a string where a value is expected, so apostrophes in prose are
+// ignored. Likewise a `#` only starts a comment inside brackets.
+pub(crate) fn is_balanced(input: &str) -> bool {
+    balanced_len(input) == input.len()
+}
+
+// Length of the longest prefix of `input` that `is_balanced`
+pub(crate) fn balanced_len(input: &str) -> usize {
+    let mut depth = 0usize;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut comment = false;
+    let mut prev_significant: Option<char> = None;
+    let mut len = 0;
+
+    for (i, c) in input.char_indices() {
+        if comment {
+            comment = c != '\n';
+            continue;
+        }
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+                prev_significant = Some(c);
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' if depth > 0 && expects_value(prev_significant) => quote = Some(c),
+            '#' if depth > 0 => comment = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        track_significant(&mut prev_significant, c);
+        if depth == 0 {
+            len = i + c.len_utf8();
+        }
+    }
+    len
+}
+
+// Record `c` as the last significant character, which decides whether a following quote
+// opens a string. Whitespace is skipped, and so is a raw, f-string or bytes prefix where a
+// value is expected, so that `=r"..."`, `=f"..."` and `=b"..."` still open a string after `=`.
+pub(crate) fn track_significant(prev_significant: &mut Option<char>, c: char) {
+    let string_prefix =
+        matches!(c, 'r' | 'R' | 'f' | 'F' | 'b' | 'B') && expects_value(*prev_significant);
+    if !c.is_whitespace() && !string_prefix {
+        *prev_significant = Some(c);
+    }
+}
+
+// Whether a value comes next after `prev_significant`: a kwarg value, an argument, or an
+// item, key or value of a list, tuple, set or dict
+pub(crate) fn expects_value(prev_significant: Option<char>) -> bool {
+    matches!(prev_significant, Some('=' | '(' | ',' | '[' | '{' | ':'))
+}
+
+// Whether the text after a `[` looks like the start of a function list: optional
+// whitespace followed by `name(`, a closing `]`, or the end of the input
+fn looks_like_list_start(after_bracket: &str) -> bool {
+    let candidate = after_bracket.trim_start();
+    candidate.is_empty()
+        || candidate.starts_with(']')
+        || pair(parse_identifier, char('('))(candidate).is_ok()
+}
+
+// Length in bytes of the quoted string literal at the start of `input`, including both
+// quotes, or `None` if it is never closed
+fn string_literal_len(input: &str) -> Option<usize> {
+    let mut chars = input.char_indices();
+    let (_, quote) = chars.next()?;
+    let mut escaped = false;
+
+    for (i, c) in chars {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return Some(i + c.len_utf8());
+        }
+    }
+    None
+}
+
+// Iterator over every function call attempted in the source, scanning lazily as it is
+// advanced. Function lists are found the same way `parse_python_with_surrounding_text`
+// finds them, but each element is parsed on its own, so a malformed call yields an `Err`
+// carrying its span and scanning resumes at the next element instead of abandoning the
+// list.
+pub struct ToolCallIter<'a> {
+    source: &'a str,
+    pos: usize,
+    in_list: bool,
+    config: ParserConfig,
+}
+
+impl<'a> ToolCallIter<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            pos: 0,
+            in_list: false,
+            config: ParserConfig::default(),
+        }
+    }
+
+    // Byte offset into the source up to which it has been scanned. Calls before it have
+    // been yielded; a later `next` picks up from here.
+    pub fn offset(&self) -> usize {
+        self.pos
+    }
+
+    // Advance past whitespace and commas between list elements
+    fn skip_separators(&mut self) {
+        let rest = &self.source[self.pos..];
+        let trimmed = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        self.pos += rest.len() - trimmed.len();
+    }
+
+    // Move to just inside the next function list, returning false if there is none
+    fn enter_next_list(&mut self) -> bool {
+        while let Some(start) = find_next_pattern_start(&self.source[self.pos..], &self.config) {
+            self.pos += start;
+            let from_pattern = &self.source[self.pos..];
+            match from_pattern.strip_prefix(self.config.start_marker.as_str()) {
+                // A block's calls must be in a list; anything else resumes after the marker
+                Some(after_marker) if !self.config.start_marker.is_empty() => {
+                    let list = after_marker.trim_start();
+                    self.pos = self.source.len() - list.len();
+                    if !list.starts_with('[') {
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            self.pos += 1;
+            self.in_list = true;
+            return true;
+        }
+        self.pos = self.source.len();
+        false
+    }
+
+    // Move to the start of the next list element, returning its offset
+    fn next_item(&mut self) -> Option<usize> {
+        loop {
+            if !self.in_list && !self.enter_next_list() {
+                return None;
+            }
+
+            self.skip_separators();
+            let rest = &self.source[self.pos..];
+
+            if rest.is_empty() {
+                self.in_list = false;
+                return None;
+            }
+            if let Some(after) = rest.strip_prefix(']') {
+                self.pos = self.source.len() - after.len();
+                self.in_list = false;
+                continue;
+            }
+            if rest.starts_with(self.config.end_marker.as_str()) {
+                self.in_list = false;
+                continue;
+            }
+            return Some(self.pos);
+        }
+    }
+}
+
+impl Iterator for ToolCallIter<'_> {
+    type Item = Result<FunctionCall, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item_start = self.next_item()?;
+        let rest = &self.source[item_start..];
+
+        match parse_function_call(rest) {
+            Ok((after, mut function_call)) => {
+                self.pos = self.source.len() - after.len();
+                function_call.span = Some(item_start..self.pos);
+                Some(Ok(function_call))
+            }
+            Err(e) => {
+                let mut scan = TerminatorScan::default();
+                let terminator = scan.item_end(rest, &self.config.end_marker, &mut 0);
+                let end = item_start + terminator.unwrap_or(rest.len()).max(1);
+                self.pos = end;
+                let snippet = self.source[item_start..end].trim_end();
+                let span = item_start..item_start + snippet.len();
+                tracing::debug!("Malformed function call {:?}: {:?}", snippet, e);
+                // An element that runs to the end of the input was cut off, so say what by
+                let error = match (terminator, scan.quote, parse_identifier(snippet)) {
+                    (None, Some(_), _) => ParseError::UnterminatedString { span },
+                    (None, None, Ok((after, name))) if after.starts_with('(') => {
+                        ParseError::UnclosedCall { name, span }
+                    }
+                    _ => ParseError::MalformedCall {
+                        snippet: snippet.to_string(),
+                        span,
+                    },
+                };
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+// Count the calls `parse_python_with_nom` would return, without building their values.
+// Lists are found the same way, but each element is only scanned for its end and checked
+// to be shaped like `name(...)`, so on malformed input the counts can differ.
+pub fn count_tool_calls(source: &str) -> usize {
+    let config = ParserConfig::default();
+    if find_next_pattern_start(source, &config).is_none() {
+        // Like the parser, fall back to a single call with no enclosing list
+        let bare_call = preceded(multispace0, pair(identifier, char('(')))(source);
+        return usize::from(bare_call.is_ok());
+    }
+
+    let mut calls = ToolCallIter::new(source);
+    let mut count = 0;
+    while let Some(item_start) = calls.next_item() {
+        let item = &source[item_start..];
+        let end = find_item_end(item, &calls.config.end_marker).max(1);
+        calls.pos = item_start + end;
+        let element = item[..end].trim_end();
+        if pair(identifier, char('('))(element).is_ok() && element.ends_with(')') {
+            count += 1;
+        }
+    }
+    count
+}
+
+// Find where the list element starting at `input` ends: the next `,` or `]` outside of
+// any nested brackets or string literals, or the `end_marker` ending the Python block
+fn find_item_end(input: &str, end_marker: &str) -> usize {
+    TerminatorScan::default().item_end(input, end_marker, &mut 0).unwrap_or(input.len())
+}
+
+impl TerminatorScan {
+    // Continue scanning the list element `input` for its `,` or `]`, or the `end_marker`
+    // ending the Python block, adding the bytes read to `scanned`
+    fn item_end(&mut self, input: &str, end_marker: &str, scanned: &mut usize) -> Option<usize> {
+        self.scan(input, ']', end_marker, scanned)
+    }
+
+    // Continue scanning the kwarg `input` for the `,` or `)` ending it
+    fn kwarg_end(&mut self, input: &str, scanned: &mut usize) -> Option<usize> {
+        self.scan(input, ')', "", scanned)
+    }
+
+    // `input` is the whole element or kwarg so far, which only ever grows between calls
+    fn scan(
+        &mut self,
+        input: &str,
+        close: char,
+        end_marker: &str,
+        scanned: &mut usize,
+    ) -> Option<usize> {
+        let start = self.pos;
+        let end = self.scan_from(input, close, end_marker);
+        *scanned += end.unwrap_or(self.pos).saturating_sub(start);
+        end
+    }
+
+    fn scan_from(&mut self, input: &str, close: char, end_marker: &str) -> Option<usize> {
+        let start = self.pos;
+        for (i, c) in input[start..].char_indices() {
+            let i = start + i;
+            if let Some(q) = self.quote {
+                if self.escaped {
+                    self.escaped = false;
+                } else if c == '\\' {
+                    self.escaped = true;
+                } else if c == q {
+                    self.quote = None;
+                }
+                continue;
+            }
+            if self.comment {
+                self.comment = c != '\n';
+                continue;
+            }
+            if end_marker.starts_with(c) {
+                if input[i..].starts_with(end_marker) {
+                    return Some(i);
+                }
+                if end_marker.starts_with(&input[i..]) {
+                    // Could still become the end marker, so resume from here
+                    self.pos = i;
+                    return None;
+                }
+            }
+            match c {
+                '"' | '\'' => self.quote = Some(c),
+                '#' => self.comment = true,
+                '(' | '[' | '{' => self.depth += 1,
+                ',' if self.depth == 0 => return Some(i),
+                c if c == close && self.depth == 0 => return Some(i),
+                ')' | ']' | '}' => self.depth = self.depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        self.pos = input.len();
+        None
+    }
+}
+
+// Locate a nom failure in `source`, which the failed parser's input is a suffix of
+pub(crate) fn nom_error(source: &str, err: nom::Err<nom::error::Error<&str>>) -> ParseError {
+    let (rest, expected) = match &err {
+        nom::Err::Error(e) => (e.input, "a function call or list of calls"),
+        // Only a `cut` fails: at a call's closing paren, or at a kwarg's value
+        nom::Err::Failure(e) if e.code == nom::error::ErrorKind::Char => {
+            (e.input, "',' or ')'")
+        }
+        nom::Err::Failure(e) if e.code == nom::error::ErrorKind::TooLarge => {
+            let offset = source.len().saturating_sub(e.input.len());
+            let len = e.input.chars().next().map_or(0, char::len_utf8);
+            return ParseError::RecursionLimit {
+                limit: MAX_NESTING_DEPTH.get(),
+                span: offset..offset + len,
+            };
+        }
+        nom::Err::Failure(e) => (e.input, "a value"),
+        nom::Err::Incomplete(_) => ("", "more input"),
+    };
+    let offset = source.len().saturating_sub(rest.len());
+    let found = match identifier(rest) {
+        Ok((_, name)) => name,
+        Err(_) => rest.chars().next().map_or("", |c| &rest[..c.len_utf8()]),
+    };
+    match found {
+        "\"" | "'" if string_literal_len(rest).is_none() => ParseError::UnterminatedString {
+            span: offset..source.len(),
+        },
+        "" => ParseError::UnexpectedToken {
+            found: "end of input".to_string(),
+            expected: expected.to_string(),
+            span: offset..offset,
+        },
+        found => ParseError::UnexpectedToken {
+            found: format!("'{}'", found),
+            expected: expected.to_string(),
+            span: offset..offset + found.len(),
+        },
     }
 }
 
 // Parse a string and return function calls, similar to the original parser
-pub fn parse_python_with_nom(source: &str) -> Result<Vec<FunctionCall>, String> {
-    // First try the new approach that handles surrounding text
-    match parse_python_with_surrounding_text(source) {
-        Ok(functions) if !functions.is_empty() => Ok(functions),
-        _ => {
-            // Fall back to the strict parser for backwards compatibility
-            match parse_python_nom(source) {
-                Ok((_, function_calls)) => Ok(function_calls),
-                Err(e) => Err(format!("Parse error: {:?}", e)),
+pub fn parse_python_with_nom(source: &str) -> Result<Vec<FunctionCall>, ParseError> {
+    parse_python_with_nom_config(source, &ParserConfig::default())
+}
+
+// Parse a string using custom block markers, e.g. for model families that don't
+// emit <|python_start|>/<|python_end|>
+pub fn parse_python_with_nom_config(
+    source: &str,
+    config: &ParserConfig,
+) -> Result<Vec<FunctionCall>, ParseError> {
+    let function_calls = parse_raw_calls(source, config)?;
+    config.filter_names(function_calls.into_iter().map(|(call, _)| call).collect())
+}
+
+// Parse a string without copying out of it: names, identifiers, and strings without
+// escapes are slices of `source`. Kwargs keep their order and any repeated key, and
+// `FunctionCallRef::to_owned` gives the call `parse_python_with_nom` would have.
+pub fn parse_python_borrowed(source: &str) -> Result<Vec<FunctionCallRef<'_>>, ParseError> {
+    let raw_calls = scan_raw_calls(source, &ParserConfig::default())?;
+    Ok(raw_calls.into_iter().map(|(call, _)| call).collect())
+}
+
+// Like `parse_python_with_nom_config`, but pairs each call with the exact text the model
+// produced for it, e.g. `get_weather(city="NYC")`, for auditing or replay
+pub fn parse_python_with_nom_raw<'a>(
+    source: &'a str,
+    config: &ParserConfig,
+) -> Result<Vec<(FunctionCall, &'a str)>, ParseError> {
+    let mut raw_calls = Vec::new();
+    for (mut function_call, raw) in parse_raw_calls(source, config)? {
+        if config.admit_name(&mut function_call)? {
+            raw_calls.push((function_call, raw));
+        }
+    }
+    config.limit_calls(raw_calls)
+}
+
+fn parse_raw_calls<'a>(
+    source: &'a str,
+    config: &ParserConfig,
+) -> Result<Vec<(FunctionCall, &'a str)>, ParseError> {
+    scan_raw_calls(source, config)?
+        .into_iter()
+        .map(|(function_call, raw)| {
+            let function_call =
+                resolve_duplicate_kwargs(source, function_call, config.on_duplicate_kwarg)?;
+            Ok((locate(source, function_call, raw), raw))
+        })
+        .collect()
+}
+
+fn scan_raw_calls<'a>(
+    source: &'a str,
+    config: &ParserConfig,
+) -> Result<Vec<RawCall<'a>>, ParseError> {
+    // Nothing at all is the model choosing not to call a tool
+    if source.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    match with_nesting_limit(config.max_nesting_depth, || scan_with_config(source, config)) {
+        Err(ParseError::NoToolCallsFound) if config.allow_no_calls => Ok(Vec::new()),
+        scanned => scanned.map(|(raw_calls, _)| raw_calls),
+    }
+}
+
+// Find the calls in `source`, along with the text after the last of them
+fn scan_with_config<'a>(
+    source: &'a str,
+    config: &ParserConfig,
+) -> Result<(Vec<RawCall<'a>>, &'a str), ParseError> {
+    let scanned = if config.strict || !config.allow_surrounding_text {
+        (parse_python_strict(source, config)?, &source[source.len()..])
+    } else {
+        // First try the new approach that handles surrounding text
+        let (functions, after_last_block, abandoned) = scan_surrounding_text(source, config);
+        let candidate_error = deepest_candidate_error(abandoned);
+        match (after_last_block, candidate_error) {
+            (Some(rest), _) => (functions, rest),
+            (None, Some(candidate_error)) if !config.recovery => return Err(candidate_error),
+            (None, None) if !config.recovery => return Err(ParseError::NoToolCallsFound),
+            (None, candidate_error) => {
+                // Fall back to the strict parser for backwards compatibility
+                match parse_python_nom_with_config(skip_import_statements(source), config) {
+                    Ok((rest, function_calls)) => (function_calls, rest),
+                    Err(e) => return Err(fallback_error(source, candidate_error, e)),
+                }
             }
         }
+    };
+    Ok(scanned)
+}
+
+// Why neither the scan nor the fallback found any calls: whichever error got further into
+// the input, noting the strategy it came from. Without a candidate the scan gave up on,
+// the fallback only reports an error once it got as far as a call's arguments, as the
+// input would otherwise be plain prose. The nesting limit is the same for both, so it is
+// returned as it is.
+fn fallback_error(
+    source: &str,
+    candidate_error: Option<ParseError>,
+    err: nom::Err<nom::error::Error<&str>>,
+) -> ParseError {
+    let (strategy, error) = match (candidate_error, err) {
+        (None, nom::Err::Error(_)) => return ParseError::NoToolCallsFound,
+        (None, err) => (ParseStrategy::Fallback, nom_error(source, err)),
+        (Some(candidate_error), err) => {
+            let fallback_error = nom_error(source, err);
+            if error_depth(&fallback_error) > error_depth(&candidate_error) {
+                (ParseStrategy::Fallback, fallback_error)
+            } else {
+                (ParseStrategy::Scan, candidate_error)
+            }
+        }
+    };
+    match error {
+        ParseError::RecursionLimit { .. } => error,
+        error => ParseError::StrategyFailed {
+            strategy,
+            error: Box::new(error),
+        },
     }
 }
 
-// Incremental parsing function that maintains state
+// The error of the candidate the scan got furthest into, the first of them if several
+// got as far
+fn deepest_candidate_error(abandoned: Vec<(usize, ParseError)>) -> Option<ParseError> {
+    abandoned.into_iter().map(|(_, error)| error).reduce(|deepest, error| {
+        if error_depth(&error) > error_depth(&deepest) { error } else { deepest }
+    })
+}
+
+// How far into the input an error is, for comparing them; one without a span is the least
+fn error_depth(error: &ParseError) -> Option<usize> {
+    error.span().map(|span| span.start)
+}
+
+// Parse with the default markers, keeping only calls to the functions in `allowed_names`
+pub fn parse_python_with_nom_filtered(
+    source: &str,
+    allowed_names: &[String],
+) -> Result<Vec<FunctionCall>, ParseError> {
+    let config = ParserConfig::default().with_allowed_names(allowed_names.to_vec());
+    parse_python_with_nom_config(source, &config)
+}
+
+// Strict mode: parse the top-level block or list at the start of the input and reject
+// any non-whitespace text around it instead of scanning past it
+fn parse_python_strict<'a>(
+    source: &'a str,
+    config: &ParserConfig,
+) -> Result<Vec<RawCall<'a>>, ParseError> {
+    match parse_python_nom_with_config(skip_import_statements(source).trim_start(), config) {
+        Ok((rest, function_calls)) if rest.trim().is_empty() => Ok(function_calls),
+        Ok((rest, _)) => {
+            let garbage = rest.trim();
+            let offset = source.len() - rest.trim_start().len();
+            Err(ParseError::TrailingInput {
+                text: garbage.to_string(),
+                span: offset..offset + garbage.len(),
+            })
+        }
+        Err(e) => Err(nom_error(source, e)),
+    }
+}
+
+// Parse like `parse_python_with_nom`, also returning a diagnostic for each candidate `[` or
+// start marker the scan gave up on, with the text it skipped. Scans the input a second
+// time to collect them.
+pub fn parse_python_with_nom_with_diagnostics(
+    source: &str,
+) -> Result<(Vec<FunctionCall>, Vec<Diagnostic>), ParseError> {
+    let function_calls = parse_python_with_nom(source)?;
+    let (_, _, abandoned) = scan_surrounding_text(source, &ParserConfig::default());
+    let diagnostics = abandoned
+        .iter()
+        .map(|(start, error)| abandoned_candidate(source, *start, error))
+        .collect();
+    Ok((function_calls, diagnostics))
+}
+
+// Parse a string and return function calls along with the unconsumed text that
+// follows the last parsed block, e.g. prose the model wrote after its tool calls
+pub fn parse_python_with_nom_remainder(
+    source: &str,
+) -> Result<(Vec<FunctionCall>, String), ParseError> {
+    if source.trim().is_empty() {
+        return Ok((Vec::new(), source.to_string()));
+    }
+    // Found the same way as by parse_python_with_nom, fallback included
+    let config = ParserConfig::default();
+    let (raw_calls, rest) =
+        with_nesting_limit(config.max_nesting_depth, || scan_with_config(source, &config))?;
+    Ok((strip_raw(source, raw_calls), rest.to_string()))
+}
+
+// Incremental parsing function that maintains state. Returns every call parsed so far;
+// see `parse_incremental_delta` for only the calls completed by this chunk.
+#[tracing::instrument(level = "debug", skip_all, fields(chunk = preview(chunk)))]
 pub fn parse_incremental(
     state: &mut NomParserState,
     chunk: &str,
-) -> Result<Vec<FunctionCall>, String> {
-    // Add new chunk to existing remainder
+) -> Result<Vec<FunctionCall>, ParseError> {
+    parse_incremental_delta(state, chunk)?;
+    tracing::debug!("{} function calls parsed so far", state.parsed_functions.len());
+    Ok(state.parsed_functions.clone())
+}
+
+// Add a chunk and return what it revealed about the calls, see `parse_incremental_with`
+pub fn parse_incremental_events(
+    state: &mut NomParserState,
+    chunk: &str,
+) -> Result<Vec<ParseEvent>, ParseError> {
+    let mut events = Vec::new();
+    parse_incremental_with(state, chunk, &mut events)?;
+    Ok(events)
+}
+
+// Add a chunk like `parse_incremental_delta`, calling `events` in source order as the chunk
+// reveals each part of a call: a call starts once `name(` arrives, each kwarg once it is
+// terminated, and the call completes once the `,` or `]` after it has arrived. A block end
+// is reported for each end marker outside a call. Like `drain_events`, these run ahead of
+// the parsed calls: a list later abandoned as malformed leaves events for calls that are
+// never reported. Unlike there, the index a call completes with is its position in the
+// parsed calls, so a call after an abandoned list can complete with an abandoned one's.
+pub fn parse_incremental_with(
+    state: &mut NomParserState,
+    chunk: &str,
+    events: &mut dyn ParserEvents,
+) -> Result<Vec<FunctionCall>, ParseError> {
+    feed_chunk(state, chunk, Some(events))
+}
+
+// Add a chunk and return only the calls it completed. Input is consumed as it is
+// parsed: each list element is scanned once as it arrives and parsed once, when it is
+// terminated, and the buffer is drained whenever a function list closes, so a long stream
+// is processed in linear time.
+// Calls are reported when their list closes, as a malformed element abandons the list.
+pub fn parse_incremental_delta(
+    state: &mut NomParserState,
+    chunk: &str,
+) -> Result<Vec<FunctionCall>, ParseError> {
+    feed_chunk(state, chunk, None)
+}
+
+fn feed_chunk(
+    state: &mut NomParserState,
+    chunk: &str,
+    events: Option<&mut dyn ParserEvents>,
+) -> Result<Vec<FunctionCall>, ParseError> {
+    with_nesting_limit(state.config.max_nesting_depth, || {
+        feed_chunk_with_limit(state, chunk, events)
+    })
+}
+
+fn feed_chunk_with_limit(
+    state: &mut NomParserState,
+    chunk: &str,
+    mut events: Option<&mut dyn ParserEvents>,
+) -> Result<Vec<FunctionCall>, ParseError> {
     state.add_input(chunk);
-    let input = &state.remainder;
-
-    // Use the new surrounding text parser for better compatibility
-    match parse_python_with_surrounding_text(input) {
-        Ok(function_calls) => {
-            // For incremental parsing, we need to be more careful about what's complete
-            // Check if we have complete function calls by trying the strict parser on parts
-            let mut new_functions = Vec::new();
-            
-            // Try to find complete patterns and parse them
-            for func in function_calls {
-                // Only add functions that weren't already parsed
-                if !state.parsed_functions.iter().any(|existing| 
-                    existing.name == func.name && existing.kwargs == func.kwargs) {
-                    new_functions.push(func);
+
+    let config = state.config.clone();
+    let mut completed = Vec::new();
+
+    loop {
+        let rest = &state.remainder[state.consumed..];
+
+        if !state.in_function_list {
+            // Text before the next candidate can never start a call, so drop it
+            match find_list_candidate(rest, &config) {
+                Ok(offset) => {
+                    if let Some(events) = events.as_deref_mut() {
+                        report_block_ends(&rest[..offset], &config, events);
+                    }
+                    state.in_python_block =
+                        python_block_state(&rest[..offset], &config, state.in_python_block);
+                    state.drained += state.consumed + offset;
+                    state.remainder.drain(..state.consumed + offset);
+                    state.list_start = 0;
+                    state.consumed = 1;
+                    state.in_function_list = true;
+                    state.expect_separator = false;
+                    continue;
+                }
+                Err(mut resume_at) => {
+                    // Keep a marker split across chunks so it is seen whole next time
+                    if resume_at == rest.len() {
+                        resume_at -= partial_marker_len(rest, &config);
+                    }
+                    if let Some(events) = events.as_deref_mut() {
+                        report_block_ends(&rest[..resume_at], &config, events);
+                    }
+                    state.in_python_block =
+                        python_block_state(&rest[..resume_at], &config, state.in_python_block);
+                    state.drained += state.consumed + resume_at;
+                    state.remainder.drain(..state.consumed + resume_at);
+                    state.consumed = 0;
+                    break;
                 }
             }
-            
-            // Add new functions to our state
-            state.parsed_functions.extend(new_functions);
-            
-            // For streaming, we might want to clear some of the remainder to avoid reprocessing
-            // but for now, let's keep it simple
-            Ok(state.parsed_functions.clone())
-        }
-        Err(e) => {
-            // If the new parser fails, fall back to the old approach
-            tracing::debug!("Incremental parse error with surrounding text parser: {:?}", e);
-            // Try the strict parser as fallback
-            match parse_python_nom(input) {
-                Ok((remainder, mut function_calls)) => {
-                    state.remainder = remainder.to_string();
-                    state.parsed_functions.append(&mut function_calls);
-                    Ok(state.parsed_functions.clone())
+        }
+
+        let item = skip_ws(rest);
+        let item_start = state.remainder.len() - item.len();
+        if item.is_empty() {
+            break;
+        }
+
+        if state.expect_separator && item.starts_with(',') {
+            state.consumed = item_start + 1;
+            state.expect_separator = false;
+            continue;
+        }
+        if item.starts_with(']') && (state.expect_separator || state.pending_functions.is_empty())
+        {
+            // The list is complete: report its calls and drop everything up to its end
+            completed.append(&mut state.pending_functions);
+            state.drained += item_start + 1;
+            state.remainder.drain(..item_start + 1);
+            state.consumed = 0;
+            state.in_function_list = false;
+            state.expect_separator = false;
+            continue;
+        }
+        if state.expect_separator {
+            abandon_function_list(state);
+            continue;
+        }
+
+        let item = &state.remainder[item_start..];
+
+        let Some(end) = state.item_scan.item_end(item, &config.end_marker, &mut state.scanned)
+        else {
+            // Wait for the rest of this element
+            advance_partial_function(state, item_start, events);
+            break;
+        };
+        state.item_scan = TerminatorScan::default();
+        let partial = state.current_function.take();
+
+        match parse_function_call_ref(item) {
+            Ok((after, function_call)) if item.len() - skip_ws(after).len() == end => {
+                let function_call = match resolve_duplicate_kwargs(
+                    item,
+                    function_call,
+                    config.on_duplicate_kwarg,
+                ) {
+                    Ok(mut function_call) => {
+                        let start = state.drained + item_start;
+                        function_call.span = Some(start..start + item.len() - after.len());
+                        function_call
+                    }
+                    Err(err) => {
+                        abandon_function_list(state);
+                        return Err(err);
+                    }
+                };
+                state.consumed = item_start + (item.len() - after.len());
+                if state.emit_events {
+                    finish_call_events(state, partial.as_ref(), &function_call);
                 }
-                Err(nom::Err::Incomplete(_)) => {
-                    // Not enough data yet, keep accumulating
-                    Ok(state.parsed_functions.clone())
+                if let Some(events) = events.as_deref_mut() {
+                    let index = state.parsed_functions.len()
+                        + completed.len()
+                        + state.pending_functions.len();
+                    complete_parse_events(events, partial, &function_call, index);
                 }
-                Err(_) => {
-                    // Return what we have so far
-                    Ok(state.parsed_functions.clone())
+                state.pending_functions.push(function_call);
+                state.expect_separator = true;
+            }
+            Err(nom::Err::Failure(e)) if e.code == nom::error::ErrorKind::TooLarge => {
+                let error = nom_error(item, nom::Err::Failure(e));
+                abandon_function_list(state);
+                return Err(error);
+            }
+            _ => abandon_function_list(state),
+        }
+    }
+
+    let completed = state.admit_calls(completed)?;
+    state.parsed_functions.extend(completed.iter().cloned());
+
+    if let Some(max_buffer_size) = state.max_buffer_size {
+        let buffered = state.remainder.len();
+        if buffered > max_buffer_size {
+            // Give up on the unfinished input so later chunks start from a clean buffer.
+            // Calls completed by this chunk are still kept in `parsed_functions`.
+            state.drained += buffered;
+            state.remainder.clear();
+            state.consumed = 0;
+            state.pending_functions.clear();
+            state.in_function_list = false;
+            state.expect_separator = false;
+            state.current_function = None;
+            state.item_scan = TerminatorScan::default();
+            return Err(ParseError::BufferOverflow {
+                buffered,
+                limit: max_buffer_size,
+            });
+        }
+    }
+
+    Ok(completed)
+}
+
+// Complete a call cut off by the end of the stream, first by closing whatever string,
+// brackets and parens are still open, then by keeping only the kwargs before the last
+// top-level comma, and finally as a call with no kwargs. Also reports whether anything
+// had to be added or dropped.
+fn close_truncated_call(item: &str) -> Option<(FunctionCall, bool)> {
+    let item = item.trim_end();
+    let (_, name) = parse_identifier(item).ok()?;
+
+    let mut open = Vec::new();
+    let mut quote = None;
+    let mut escaped = false;
+    let mut last_kwarg_end = None;
+    for (i, c) in item.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '(' | '[' | '{' => open.push(c),
+            ')' | ']' | '}' => {
+                open.pop();
+            }
+            ',' if open.len() == 1 => last_kwarg_end = Some(i),
+            _ => {}
+        }
+    }
+
+    // A dangling backslash would escape the closing quote
+    let mut closed = if escaped {
+        item[..item.len() - 1].to_string()
+    } else {
+        item.to_string()
+    };
+    closed.extend(quote);
+    if open.is_empty() && quote.is_none() && !item.contains('(') {
+        closed.push_str("()");
+    }
+    closed.extend(open.iter().rev().map(|c| match c {
+        '(' => ')',
+        '[' => ']',
+        _ => '}',
+    }));
+
+    let mut candidates = vec![closed];
+    if let Some(end) = last_kwarg_end {
+        candidates.push(format!("{})", &item[..end]));
+    }
+    candidates.push(format!("{}()", name));
+
+    candidates.into_iter().find_map(|candidate| match parse_function_call(&candidate) {
+        Ok((after, function_call)) if after.trim().is_empty() => {
+            Some((function_call, candidate != item))
+        }
+        _ => None,
+    })
+}
+
+// Give up on a function list with a malformed element, and resume scanning just after its
+// opening `[`, the same way the batch scanner retries
+fn abandon_function_list(state: &mut NomParserState) {
+    tracing::debug!("Abandoning malformed function list at {}", state.list_start);
+    state.pending_functions.clear();
+    state.consumed = state.list_start + 1;
+    state.in_function_list = false;
+    state.expect_separator = false;
+    state.current_function = None;
+    state.item_scan = TerminatorScan::default();
+}
+
+// Bring `current_function` up to date with the call starting at `item_start`: it is set
+// once `name(` has arrived, and each kwarg is added once it is terminated. Both `events`
+// and, when they are enabled, the state's `ToolCallEvent`s are sent as it goes.
+fn advance_partial_function(
+    state: &mut NomParserState,
+    item_start: usize,
+    mut events: Option<&mut dyn ParserEvents>,
+) {
+    if state.current_function.is_none() {
+        let item = &state.remainder[item_start..];
+        let Ok((args, (name, _))) = pair(parse_identifier, char('('))(item) else {
+            return;
+        };
+        if let Some(events) = events.as_deref_mut() {
+            events.on_call_start(&name);
+        }
+        let index = state.next_call_index;
+        state.next_call_index += 1;
+        if state.emit_events {
+            state.events.push(ToolCallEvent::CallStarted {
+                index,
+                name: name.clone(),
+            });
+        }
+        state.current_function = Some(PartialFunction {
+            name,
+            kwargs: HashMap::new(),
+            in_args: true,
+            args_pos: state.remainder.len() - args.len(),
+            kwarg_scan: TerminatorScan::default(),
+            index,
+            finished: false,
+        });
+    }
+
+    let Some(function) = state.current_function.as_mut() else {
+        return;
+    };
+    while function.in_args {
+        let args = &state.remainder[function.args_pos..];
+        if skip_ws(args).starts_with(')') {
+            function.in_args = false;
+            if state.emit_events {
+                let json_fragment = if function.kwargs.is_empty() { "{}" } else { "}" };
+                state.events.push(ToolCallEvent::ArgumentsDelta {
+                    index: function.index,
+                    json_fragment: json_fragment.to_string(),
+                });
+                state.events.push(ToolCallEvent::CallFinished {
+                    index: function.index,
+                });
+                function.finished = true;
+            }
+            return;
+        }
+
+        let Some(end) = function.kwarg_scan.kwarg_end(args, &mut state.scanned) else {
+            return;
+        };
+        function.kwarg_scan = TerminatorScan::default();
+        match preceded(ws, parse_kwarg)(&args[..end]) {
+            Ok((rest, (key, value))) if rest.trim().is_empty() => {
+                if let Some(events) = events.as_deref_mut() {
+                    events.on_kwarg(&function.name, &key, &value);
+                }
+                if state.emit_events {
+                    let mut json_fragment =
+                        String::from(if function.kwargs.is_empty() { "{" } else { ", " });
+                    write_json_string(&key, &mut json_fragment);
+                    json_fragment.push_str(": ");
+                    json_fragment.push_str(&value.to_json());
+                    state.events.push(ToolCallEvent::ArgumentsDelta {
+                        index: function.index,
+                        json_fragment,
+                    });
                 }
+                function.kwargs.insert(key, value);
+            }
+            // The whole call will fail to parse too, so stop following its arguments
+            _ => {
+                function.in_args = false;
+                return;
             }
         }
+        function.args_pos += if args[end..].starts_with(',') { end + 1 } else { end };
     }
 }
+
+// Report whatever `partial` did not for the completed `function_call`: its start when it
+// was never seen unfinished, the kwargs not yet reported, in sorted order, and its end
+fn complete_parse_events(
+    events: &mut dyn ParserEvents,
+    partial: Option<PartialFunction>,
+    function_call: &FunctionCall,
+    index: usize,
+) {
+    let reported = match partial {
+        Some(partial) => partial.kwargs,
+        None => {
+            events.on_call_start(&function_call.name);
+            HashMap::new()
+        }
+    };
+
+    let mut keys: Vec<&String> = function_call
+        .kwargs
+        .keys()
+        .filter(|key| !reported.contains_key(*key))
+        .collect();
+    keys.sort();
+    for key in keys {
+        events.on_kwarg(&function_call.name, key, &function_call.kwargs[key]);
+    }
+    events.on_call_complete(index, function_call);
+}
+
+fn report_block_ends(text: &str, config: &ParserConfig, events: &mut dyn ParserEvents) {
+    for _ in text.matches(config.end_marker.as_str()) {
+        events.on_block_end();
+    }
+}
+
+// Send whatever `partial` did not for the completed `function_call`: the start when it was
+// never seen unfinished, the kwargs not yet sent, in sorted order, and the end of the call
+fn finish_call_events(
+    state: &mut NomParserState,
+    partial: Option<&PartialFunction>,
+    function_call: &FunctionCall,
+) {
+    let empty = HashMap::new();
+    let (index, emitted) = match partial {
+        Some(partial) if partial.finished => return,
+        Some(partial) => (partial.index, &partial.kwargs),
+        None => {
+            let index = state.next_call_index;
+            state.next_call_index += 1;
+            state.events.push(ToolCallEvent::CallStarted {
+                index,
+                name: function_call.name.clone(),
+            });
+            (index, &empty)
+        }
+    };
+
+    let mut keys: Vec<&String> = function_call
+        .kwargs
+        .keys()
+        .filter(|key| !emitted.contains_key(*key))
+        .collect();
+    keys.sort();
+
+    let mut json_fragment = String::new();
+    for key in keys {
+        json_fragment.push_str(if emitted.is_empty() && json_fragment.is_empty() {
+            "{"
+        } else {
+            ", "
+        });
+        write_json_string(key, &mut json_fragment);
+        json_fragment.push_str(": ");
+        json_fragment.push_str(&function_call.kwargs[key].to_json());
+    }
+    if emitted.is_empty() && json_fragment.is_empty() {
+        json_fragment.push('{');
+    }
+    json_fragment.push('}');
+
+    state.events.push(ToolCallEvent::ArgumentsDelta {
+        index,
+        json_fragment,
+    });
+    state.events.push(ToolCallEvent::CallFinished { index });
+}
+
+// Whether the stream is inside a python block after `text`, judged by which marker it
+// mentions last; `inside` carries over when it mentions neither
+pub(crate) fn python_block_state(text: &str, config: &ParserConfig, inside: bool) -> bool {
+    let start = text.rfind(config.start_marker.as_str());
+    let end = text.rfind(config.end_marker.as_str());
+    match (start, end) {
+        (None, None) => inside,
+        _ => start > end,
+    }
+}
+
+// Length of the longest proper prefix of a block marker that `text` ends with
+pub(crate) fn partial_marker_len(text: &str, config: &ParserConfig) -> usize {
+    [&config.start_marker, &config.end_marker]
+        .into_iter()
+        .filter_map(|marker| {
+            (1..marker.len())
+                .rev()
+                .find(|&len| marker.is_char_boundary(len) && text.ends_with(&marker[..len]))
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+// Find the next `[` that may open a function list, stepping over block markers. When
+// there is none, returns `Err` with the offset to resume from once more input arrives,
+// which keeps a trailing `[name` that could still turn into `[name(`.
+pub(crate) fn find_list_candidate(input: &str, config: &ParserConfig) -> Result<usize, usize> {
+    let mut offset = 0;
+    while let Some(found) = find_next_pattern_start(&input[offset..], config) {
+        let pos = offset + found;
+        if input[pos..].starts_with('[') {
+            return Ok(pos);
+        }
+        offset = pos + config.start_marker.len();
+    }
+
+    let resume_at = input[offset..]
+        .rfind('[')
+        .map(|found| offset + found)
+        .filter(|&pos| {
+            !input[..pos].trim_end().ends_with('=')
+                && input[pos + 1..]
+                    .trim_start()
+                    .chars()
+                    .all(is_identifier_char)
+        })
+        .unwrap_or(input.len());
+    Err(resume_at)
+}
@@ -0,0 +1,49 @@
+use crate::FunctionCall;
+use crate::diff::diff_function_calls;
+use crate::nom_parser::NomParserState;
+
+/// Describe how two lists of function calls differ, or `None` if they are equal
+pub fn describe_mismatch(left: &[FunctionCall], right: &[FunctionCall]) -> Option<String> {
+    let mut message = String::new();
+
+    if left.len() != right.len() {
+        message.push_str(&format!(
+            "expected {} calls, got {}\n",
+            right.len(),
+            left.len()
+        ));
+    }
+
+    for (i, (a, b)) in left.iter().zip(right.iter()).enumerate() {
+        let diff = diff_function_calls(a, b);
+        if !diff.is_empty() {
+            message.push_str(&format!("call {} ({}):\n{}", i, b.name, diff));
+        }
+    }
+
+    if message.is_empty() {
+        None
+    } else {
+        Some(message)
+    }
+}
+
+/// How many bytes `state` has scanned for the ends of list elements and kwargs, to check a
+/// stream is parsed in linear time without timing it
+pub fn bytes_scanned(state: &NomParserState) -> usize {
+    state.scanned
+}
+
+/// A call with one kwarg of each common value type, shared by tests of both engines
+pub const MORE_TYPES_FUNCTION_OUTPUT: &str = "register_user(name=\"John Doe\", age=37, address={'city': 'San Francisco', 'state': 'CA'}, role=None, passed_test=True, aliases=['John', 'Johnny'])";
+
+/// Assert two lists of function calls are equal, printing a per-kwarg diff on failure
+/// instead of the unordered `HashMap` debug output `assert_eq!` would produce.
+#[macro_export]
+macro_rules! assert_function_calls_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        if let Some(message) = $crate::testing::describe_mismatch(&$left, &$right) {
+            panic!("function calls differ:\n{}", message);
+        }
+    };
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+
+use serde_json::Value as JsonValue;
+
+use crate::{FunctionCall, Value};
+
+/// A way in which a parsed call does not match its tool's JSON Schema
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    MissingRequired {
+        field: String,
+    },
+    TypeMismatch {
+        field: String,
+        expected: &'static str,
+        got: &'static str,
+    },
+    /// Kwarg not among the schema's properties, and `additionalProperties` is not allowed
+    UnknownField {
+        field: String,
+    },
+}
+
+/// Check a call against a tool definition, either the `{"name", "parameters"}` object or
+/// one wrapped as `{"type": "function", "function": {...}}`. Required parameters must be
+/// present, unknown kwargs are rejected unless `additionalProperties` allows them, and
+/// each kwarg must have its property's `type`. Errors are ordered by field name.
+pub fn validate_call(call: &FunctionCall, schema: &JsonValue) -> Result<(), Vec<ValidationError>> {
+    let tool = schema.get("function").unwrap_or(schema);
+    let parameters = tool.get("parameters").unwrap_or(&JsonValue::Null);
+    let properties = parameters.get("properties").and_then(JsonValue::as_object);
+    // Kwargs are only unknown when the schema lists its properties and does not opt in to
+    // others
+    let allows_unknown = properties.is_none()
+        || parameters
+            .get("additionalProperties")
+            .is_some_and(|allowed| allowed != &JsonValue::Bool(false));
+
+    let mut errors = Vec::new();
+
+    let mut required: Vec<&str> = parameters
+        .get("required")
+        .and_then(JsonValue::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(JsonValue::as_str)
+        .filter(|field| !call.kwargs.contains_key(*field))
+        .collect();
+    required.sort();
+    errors.extend(
+        required
+            .into_iter()
+            .map(|field| ValidationError::MissingRequired {
+                field: field.to_string(),
+            }),
+    );
+
+    // Walk the kwargs in sorted order so the errors are stable
+    let mut keys: Vec<&String> = call.kwargs.keys().collect();
+    keys.sort();
+    for key in keys {
+        let value = &call.kwargs[key];
+        match properties.and_then(|properties| properties.get(key.as_str())) {
+            Some(property) => {
+                if let Some(expected) = type_mismatch(property, value) {
+                    errors.push(ValidationError::TypeMismatch {
+                        field: key.clone(),
+                        expected,
+                        got: type_name(value),
+                    });
+                }
+            }
+            None if allows_unknown => {}
+            None => errors.push(ValidationError::UnknownField { field: key.clone() }),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+// The type `property` expects if `value` has none of its types. A property without a
+// `type`, or with only types JSON Schema does not define, accepts any value.
+fn type_mismatch(property: &JsonValue, value: &Value) -> Option<&'static str> {
+    let types: Vec<&'static str> = match property.get("type") {
+        Some(JsonValue::String(name)) => schema_type(name).into_iter().collect(),
+        Some(JsonValue::Array(names)) => names
+            .iter()
+            .filter_map(JsonValue::as_str)
+            .filter_map(schema_type)
+            .collect(),
+        _ => Vec::new(),
+    };
+    if types.is_empty() || types.iter().any(|expected| matches_type(expected, value)) {
+        None
+    } else {
+        Some(types[0])
+    }
+}
+
+fn schema_type(name: &str) -> Option<&'static str> {
+    match name {
+        "string" => Some("string"),
+        "integer" => Some("integer"),
+        "number" => Some("number"),
+        "boolean" => Some("boolean"),
+        "null" => Some("null"),
+        "array" => Some("array"),
+        "object" => Some("object"),
+        _ => None,
+    }
+}
+
+fn matches_type(expected: &str, value: &Value) -> bool {
+    match (expected, value) {
+        ("string", Value::String(_)) => true,
+        // Numbers are all parsed as floats, so an integer is any whole number
+        ("integer", Value::Number(n)) => n.fract() == 0.0,
+        ("number", Value::Number(_)) => true,
+        ("boolean", Value::Bool(_)) => true,
+        ("null", Value::Null | Value::Empty) => true,
+        ("array", Value::List(_) | Value::Tuple(_) | Value::Set(_)) => true,
+        // Dicts are stored as lists of interleaved keys and values
+        ("object", Value::List(items)) => {
+            items.len() % 2 == 0
+                && items
+                    .iter()
+                    .step_by(2)
+                    .all(|key| matches!(key, Value::String(_)))
+        }
+        _ => false,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.fract() == 0.0 => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Bytes(_) => "bytes",
+        Value::Identifier(_) => "identifier",
+        Value::Null | Value::Empty => "null",
+        Value::Ellipsis => "ellipsis",
+        Value::List(_) | Value::Tuple(_) | Value::Set(_) => "array",
+        Value::FunctionCall(_) => "function call",
+        Value::DictSplat(_) => "dict splat",
+        Value::ListSplat(_) => "list splat",
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::MissingRequired { field } => {
+                write!(f, "missing required field '{}'", field)
+            }
+            ValidationError::TypeMismatch {
+                field,
+                expected,
+                got,
+            } => write!(f, "field '{}' should be {}, got {}", field, expected, got),
+            ValidationError::UnknownField { field } => write!(f, "unknown field '{}'", field),
+        }
+    }
+}
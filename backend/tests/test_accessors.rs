@@ -0,0 +1,327 @@
+use std::collections::{HashMap, HashSet};
+
+use backend::{
+    FunctionCall, ParseError, Value, normalize_function_call, normalize_value,
+    parse_python_with_nom,
+};
+use serde::Deserialize;
+
+fn parse_one(source: &str) -> FunctionCall {
+    parse_python_with_nom(source).unwrap().remove(0)
+}
+
+#[test]
+fn test_get_str() {
+    let call = parse_one(r#"[f(city="Tokyo", days=7)]"#);
+
+    assert_eq!(call.get_str("city"), Some("Tokyo"));
+    assert_eq!(call.get_str("missing"), None);
+    assert_eq!(call.get_str("days"), None);
+}
+
+#[test]
+fn test_get_i64() {
+    let call = parse_one(r#"[f(days=7, ratio=0.5, huge=1e300, city="Tokyo")]"#);
+
+    assert_eq!(call.get_i64("days"), Some(7));
+    assert_eq!(call.get_i64("missing"), None);
+    assert_eq!(call.get_i64("ratio"), None);
+    assert_eq!(call.get_i64("huge"), None);
+    assert_eq!(call.get_i64("city"), None);
+}
+
+#[test]
+fn test_get_f64() {
+    let call = parse_one(r#"[f(ratio=0.5, days=7, flag=True)]"#);
+
+    assert_eq!(call.get_f64("ratio"), Some(0.5));
+    assert_eq!(call.get_f64("days"), Some(7.0));
+    assert_eq!(call.get_f64("missing"), None);
+    assert_eq!(call.get_f64("flag"), None);
+}
+
+#[test]
+fn test_get_bool() {
+    let call = parse_one(r#"[f(flag=True, other=False, name="x")]"#);
+
+    assert_eq!(call.get_bool("flag"), Some(true));
+    assert_eq!(call.get_bool("other"), Some(false));
+    assert_eq!(call.get_bool("missing"), None);
+    assert_eq!(call.get_bool("name"), None);
+}
+
+#[test]
+fn test_get_list() {
+    let call = parse_one(r#"[f(tags=["a", "b"], empty=[], name="x")]"#);
+
+    assert_eq!(
+        call.get_list("tags"),
+        Some(&[Value::String("a".into()), Value::String("b".into())][..])
+    );
+    assert_eq!(call.get_list("empty"), Some(&[][..]));
+    assert_eq!(call.get_list("missing"), None);
+    assert_eq!(call.get_list("name"), None);
+}
+
+#[test]
+fn test_builder() {
+    let call = FunctionCall::new("get_weather")
+        .with_kwarg("city", Value::String("Tokyo".into()))
+        .with_kwarg("days", Value::Number(7.0))
+        .with_kwarg("metric", Value::Bool(true))
+        .without_kwarg("metric")
+        .without_kwarg("missing");
+
+    assert_eq!(call, parse_one(r#"[get_weather(city="Tokyo", days=7)]"#));
+    assert!(FunctionCall::new("ping").kwargs.is_empty());
+}
+
+#[test]
+fn test_rename_kwargs() {
+    let call = parse_one(r#"[get_weather(city="Tokyo", days=7)]"#);
+
+    let renamed = call.rename_kwargs(&HashMap::from([("city", "location"), ("unit", "units")]));
+    assert_eq!(
+        renamed,
+        parse_one(r#"[get_weather(location="Tokyo", days=7)]"#)
+    );
+    // The original is left as it was
+    assert_eq!(call.get_str("city"), Some("Tokyo"));
+
+    let upper = call.transform_kwargs(|key, value| (key.to_uppercase(), value));
+    assert_eq!(upper, parse_one(r#"[get_weather(CITY="Tokyo", DAYS=7)]"#));
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct WeatherArgs {
+    city: String,
+    metric: String,
+}
+
+#[test]
+fn test_deserialize_args() {
+    let call = parse_one(r#"[get_weather(city="Tokyo", metric="celsius")]"#);
+
+    let args: WeatherArgs = call.deserialize_args().unwrap();
+    assert_eq!(
+        args,
+        WeatherArgs {
+            city: "Tokyo".to_string(),
+            metric: "celsius".to_string(),
+        }
+    );
+
+    let missing = parse_one(r#"[get_weather(city="Tokyo")]"#).deserialize_args::<WeatherArgs>();
+    assert!(missing.unwrap_err().to_string().contains("metric"));
+}
+
+#[test]
+fn test_deserialize_args_numbers_and_lists() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct SearchArgs {
+        limit: u32,
+        ratio: f64,
+        tags: Vec<String>,
+        owner: Option<String>,
+    }
+
+    let call = parse_one(r#"[search(limit=10, ratio=0.25, tags=["a", "b"], owner=None)]"#);
+
+    assert_eq!(
+        call.deserialize_args::<SearchArgs>().unwrap(),
+        SearchArgs {
+            limit: 10,
+            ratio: 0.25,
+            tags: vec!["a".to_string(), "b".to_string()],
+            owner: None,
+        }
+    );
+}
+
+#[test]
+fn test_openai_format_round_trip() {
+    let call = parse_one(
+        r#"[search(query="rust \"nom\"", limit=10, ratio=0.25, exact=False, owner=None, tags=["a", "b"], filters={"lang": "en"})]"#,
+    );
+
+    let openai = call.to_openai_format("call_0");
+    assert_eq!(openai["id"], "call_0");
+    assert_eq!(openai["type"], "function");
+    assert_eq!(openai["function"]["name"], "search");
+    assert!(openai["function"]["arguments"].is_string());
+
+    assert_eq!(FunctionCall::from_openai_json(&openai).unwrap(), call);
+}
+
+#[test]
+fn test_bytes_json() {
+    let call = parse_one(r#"[upload(data=b"\x00\x01\x02", empty=b"", tail=b"ab")]"#);
+
+    assert_eq!(call.get_bytes("data"), Some(&[0u8, 1, 2][..]));
+    assert_eq!(call.get_bytes("missing"), None);
+    assert_eq!(call.kwargs["data"].to_json(), r#"{"__bytes__": "AAEC"}"#);
+    assert_eq!(call.kwargs["empty"].to_json(), r#"{"__bytes__": ""}"#);
+    assert_eq!(call.kwargs["tail"].to_json(), r#"{"__bytes__": "YWI="}"#);
+
+    // Bytes come back from the OpenAI format, and through the derived serde form
+    let openai = call.to_openai_format("call_0");
+    assert_eq!(FunctionCall::from_openai_json(&openai).unwrap(), call);
+    let tagged = serde_json::to_string(&call).unwrap();
+    assert_eq!(serde_json::from_str::<FunctionCall>(&tagged).unwrap(), call);
+}
+
+#[test]
+fn test_tuple_json() {
+    let call = parse_one(r#"[locate(coords=(40.7, -74.0), pair=(1,), path=[(1, 2)])]"#);
+
+    assert_eq!(
+        call.kwargs["coords"].to_json(),
+        r#"{"__tuple__": [40.7, -74]}"#
+    );
+    assert_eq!(call.kwargs["pair"].to_json(), r#"{"__tuple__": [1]}"#);
+    assert_eq!(call.kwargs["path"].to_json(), r#"[{"__tuple__": [1, 2]}]"#);
+
+    let openai = call.to_openai_format("call_0");
+    assert_eq!(FunctionCall::from_openai_json(&openai).unwrap(), call);
+}
+
+#[test]
+fn test_from_openai_json_errors() {
+    let no_arguments = serde_json::json!({"type": "function", "function": {"name": "f"}});
+    assert!(FunctionCall::from_openai_json(&no_arguments).is_err());
+
+    let not_an_object = serde_json::json!({"function": {"name": "f", "arguments": "[1]"}});
+    assert!(FunctionCall::from_openai_json(&not_an_object).is_err());
+
+    let bad_json = serde_json::json!({"function": {"name": "f", "arguments": "{\"x\": "}});
+    assert!(matches!(
+        FunctionCall::from_openai_json(&bad_json),
+        Err(ParseError::InvalidJson(message)) if message.starts_with("invalid arguments for f")
+    ));
+}
+
+#[test]
+fn test_from_json_object() {
+    let charge = FunctionCall::new("charge").with_kwarg("amount", Value::Number(10.0));
+    let call = parse_one(
+        r#"[book(city="Paris", nights=3, price=99.5, late=True, notes=None, tags=["a", (1, 2)], meta={"k": 1})]"#,
+    )
+    .with_kwarg("pay", Value::FunctionCall(charge));
+    let kwargs: serde_json::Value = serde_json::from_str(&call.arguments_json()).unwrap();
+    let object = serde_json::json!({"name": call.name, "kwargs": kwargs});
+    assert_eq!(FunctionCall::from_json_object(&object).unwrap(), call);
+
+    assert_eq!(
+        Value::from_json(&serde_json::json!([1, 2.5, true, null, {"k": "v"}])),
+        Value::List(vec![
+            Value::Number(1.0),
+            Value::Number(2.5),
+            Value::Bool(true),
+            Value::Null,
+            Value::List(vec![Value::String("k".into()), Value::String("v".into())]),
+        ])
+    );
+
+    for invalid in [
+        serde_json::json!([]),
+        serde_json::json!({"kwargs": {}}),
+        serde_json::json!({"name": 1, "kwargs": {}}),
+        serde_json::json!({"name": "f", "kwargs": [1]}),
+        serde_json::json!({"name": "f"}),
+    ] {
+        assert!(matches!(
+            FunctionCall::from_json_object(&invalid),
+            Err(ParseError::InvalidJson(_))
+        ));
+    }
+}
+
+#[test]
+fn test_dict_with_call_keys_stays_a_dict() {
+    let openai = serde_json::json!({"function": {
+        "name": "register",
+        "arguments": r#"{"user": {"name": "Bob", "kwargs": {}}}"#,
+    }});
+    let call = FunctionCall::from_openai_json(&openai).unwrap();
+    assert_eq!(
+        call.kwargs["user"],
+        Value::List(vec![
+            Value::String("kwargs".into()),
+            Value::List(vec![]),
+            Value::String("name".into()),
+            Value::String("Bob".into()),
+        ])
+    );
+
+    // Only a tagged object is read back as a nested call
+    let nested = Value::FunctionCall(FunctionCall::new("g").with_kwarg("k", Value::Bool(false)));
+    let json: serde_json::Value = serde_json::from_str(&nested.to_json()).unwrap();
+    assert_eq!(Value::from_json(&json), nested);
+}
+
+#[test]
+fn test_hash_set_deduplicates_calls() {
+    let calls = parse_python_with_nom(
+        r#"[f(a=1, b=[1, 2], c={"k": (1,)}), f(c={"k": (1,)}, b=[1, 2], a=1), f(a=2), g(a=1)]"#,
+    )
+    .unwrap();
+
+    let unique: HashSet<FunctionCall> = calls.into_iter().collect();
+    assert_eq!(unique.len(), 3);
+    assert!(unique.contains(&parse_one("[f(a=2)]")));
+}
+
+#[test]
+fn test_nan_equals_itself() {
+    let nan = Value::Number(f64::NAN);
+    assert_eq!(nan, nan.clone());
+    assert_ne!(nan, Value::Number(0.0));
+
+    let numbers: HashSet<Value> = [
+        Value::Number(f64::NAN),
+        Value::Number(-f64::NAN),
+        Value::Number(0.0),
+        Value::Number(-0.0),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(numbers.len(), 2);
+}
+
+#[test]
+fn test_normalize_value() {
+    assert_eq!(
+        normalize_value(Value::Identifier("True".to_string())),
+        Value::Bool(true)
+    );
+    assert_eq!(
+        normalize_value(Value::Identifier("None".to_string())),
+        Value::Null
+    );
+    assert_eq!(
+        normalize_value(Value::Tuple(vec![
+            Value::Identifier("False".to_string()),
+            Value::Identifier("Celsius".to_string()),
+        ])),
+        Value::Tuple(vec![
+            Value::Bool(false),
+            Value::Identifier("Celsius".to_string())
+        ])
+    );
+
+    let spelled_out = FunctionCall::new("f")
+        .with_kwarg("flag", Value::Identifier("True".to_string()))
+        .with_kwarg(
+            "nested",
+            Value::FunctionCall(
+                FunctionCall::new("g").with_kwarg("x", Value::Identifier("None".to_string())),
+            ),
+        );
+    let parsed = FunctionCall::new("f")
+        .with_kwarg("flag", Value::Bool(true))
+        .with_kwarg(
+            "nested",
+            Value::FunctionCall(FunctionCall::new("g").with_kwarg("x", Value::Null)),
+        );
+    assert_eq!(normalize_function_call(spelled_out), parsed);
+}
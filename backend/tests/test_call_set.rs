@@ -0,0 +1,74 @@
+use backend::{FunctionCallSet, ParseError, parse_python, parse_python_with_nom};
+
+const TURNS: [&str; 2] = [
+    r#"[get_weather(city="SF"), get_time(zone="PST")]"#,
+    r#"Checking again. [get_weather(city="SF"), get_weather(city="NYC")]"#,
+];
+
+fn summary(set: &FunctionCallSet) -> Vec<(usize, String, &str)> {
+    set.calls()
+        .iter()
+        .map(|call| {
+            (
+                call.index,
+                call.id.clone(),
+                call.function_call.name.as_str(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_extend_from_source() {
+    let mut set = FunctionCallSet::new();
+    assert_eq!(
+        set.extend_from_source(TURNS[0], parse_python_with_nom)
+            .unwrap()
+            .len(),
+        2
+    );
+    let added = set.extend_from_source(TURNS[1], parse_python).unwrap();
+    assert_eq!(added[0].index, 2);
+
+    assert_eq!(set.len(), 4);
+    assert_eq!(
+        summary(&set),
+        [
+            (0, "call_0".to_string(), "get_weather"),
+            (1, "call_1".to_string(), "get_time"),
+            (2, "call_2".to_string(), "get_weather"),
+            (3, "call_3".to_string(), "get_weather"),
+        ]
+    );
+    // Spans point into the source each call came from
+    let third = &set.calls()[2].function_call;
+    assert_eq!(
+        &TURNS[1][third.span.clone().unwrap()],
+        r#"get_weather(city="SF")"#
+    );
+
+    // A source that fails to parse adds nothing
+    assert_eq!(
+        set.extend_from_source("no calls", parse_python_with_nom)
+            .unwrap_err(),
+        ParseError::NoToolCallsFound
+    );
+    assert_eq!(set.len(), 4);
+}
+
+#[test]
+fn test_dedup_across_sources() {
+    let mut set = FunctionCallSet::new().with_dedup(true);
+    for turn in TURNS {
+        set.extend_from_source(turn, parse_python_with_nom).unwrap();
+    }
+
+    let names: Vec<&str> = set
+        .calls()
+        .iter()
+        .map(|c| c.function_call.name.as_str())
+        .collect();
+    assert_eq!(names, ["get_weather", "get_time", "get_weather"]);
+    assert_eq!(set.calls()[2].index, 2);
+    assert_eq!(set.calls()[2].function_call.get_str("city"), Some("NYC"));
+}
@@ -0,0 +1,47 @@
+use backend::{count_function_calls, count_tool_calls, parse_python};
+
+#[test]
+fn test_count_function_calls() {
+    assert_eq!(count_function_calls("[f(x=1), g(y=2)]"), 2);
+    assert_eq!(count_function_calls("[]"), 0);
+    assert_eq!(count_function_calls("no tools here"), 0);
+    assert_eq!(count_function_calls("[[a()], [b()]] and [c()]"), 3);
+}
+
+#[test]
+fn test_count_ignores_parens_in_strings_and_nested_calls() {
+    let source = r#"<|python_start|>[calculate(expression="5 * (3 + 2)", note="f(x)"), run_benchmark(test_cases=[create_test(size=1)])]<|python_end|>"#;
+
+    assert_eq!(count_function_calls(source), 2);
+}
+
+#[test]
+fn test_count_matches_parse_python() {
+    let inputs = [
+        "[get_weather(city=\"SF\")]",
+        "Sure! I found [several options]: [a(), b(x=[1, 2]), c(d={'k': e(f=1)})] done",
+        "<|python_start|>[f1(x=1<|python_end|><|python_start|>[f2(y=2)]<|python_end|>",
+        "call foo(bar) outside a list",
+    ];
+
+    for input in inputs {
+        assert_eq!(
+            count_function_calls(input),
+            parse_python(input).unwrap().len(),
+            "{:?}",
+            input
+        );
+    }
+}
+
+#[test]
+fn test_count_tool_calls() {
+    assert_eq!(count_tool_calls("[f(x=1), g(y=2)]"), 2);
+    assert_eq!(count_tool_calls("[]"), 0);
+    assert_eq!(count_tool_calls("no tools here"), 0);
+    assert_eq!(count_tool_calls("see [the docs] then [a(), b(x=[c()])]"), 2);
+    assert_eq!(
+        count_tool_calls(r#"[calc(expr="f(x), g(y)]", note='[h()]')]"#),
+        1
+    );
+}
@@ -0,0 +1,92 @@
+use backend::diff::{DiffItem, diff_function_calls};
+use backend::testing::describe_mismatch;
+use backend::{FunctionCall, Value, assert_function_calls_eq};
+use std::collections::HashMap;
+
+fn create_function_call(name: &str, args: Vec<(&str, Value)>) -> FunctionCall {
+    let mut kwargs = HashMap::new();
+    for (k, v) in args {
+        kwargs.insert(k.to_string(), v);
+    }
+    FunctionCall {
+        name: name.to_string(),
+        kwargs,
+        span: None,
+    }
+}
+
+#[test]
+fn test_identical_calls_have_empty_diff() {
+    let call = create_function_call("get_weather", vec![("city", Value::String("SF".into()))]);
+
+    assert!(diff_function_calls(&call, &call.clone()).is_empty());
+}
+
+#[test]
+fn test_diff_items() {
+    let left = create_function_call(
+        "get_weather",
+        vec![
+            ("city", Value::String("SF".into())),
+            ("days", Value::Number(3.0)),
+        ],
+    );
+    let right = create_function_call(
+        "get_forecast",
+        vec![
+            ("city", Value::String("NYC".into())),
+            ("metric", Value::String("celsius".into())),
+        ],
+    );
+
+    let diff = diff_function_calls(&left, &right);
+
+    assert_eq!(
+        diff.items,
+        vec![
+            DiffItem::NameMismatch {
+                left: "get_weather".into(),
+                right: "get_forecast".into(),
+            },
+            DiffItem::ValueMismatch {
+                key: "city".into(),
+                left_value: Value::String("SF".into()),
+                right_value: Value::String("NYC".into()),
+            },
+            DiffItem::ExtraKwarg {
+                key: "days".into(),
+                value: Value::Number(3.0),
+            },
+            DiffItem::MissingKwarg {
+                key: "metric".into(),
+                value: Value::String("celsius".into()),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_describe_mismatch_is_readable() {
+    let left = vec![create_function_call("f", vec![("a", Value::Number(1.0))])];
+    let right = vec![create_function_call("f", vec![("a", Value::Number(2.0))])];
+
+    let message = describe_mismatch(&left, &right).unwrap();
+
+    assert_eq!(
+        message,
+        "call 0 (f):\n  kwarg a: Number(1.0) != Number(2.0)\n"
+    );
+    assert_function_calls_eq!(left, left.clone());
+}
+
+#[test]
+#[should_panic(expected = "expected 2 calls, got 1")]
+fn test_assert_macro_panics_on_length_mismatch() {
+    let left = vec![create_function_call("f", vec![])];
+    let right = vec![
+        create_function_call("f", vec![]),
+        create_function_call("g", vec![]),
+    ];
+
+    assert_function_calls_eq!(left, right);
+}
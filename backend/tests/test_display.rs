@@ -0,0 +1,156 @@
+use backend::{FunctionCall, Value, parse_python, parse_python_with_nom};
+use std::collections::HashMap;
+
+#[test]
+fn test_display_scalars() {
+    assert_eq!(format!("{}", Value::Bool(true)), "True");
+    assert_eq!(format!("{}", Value::Bool(false)), "False");
+    assert_eq!(format!("{}", Value::Null), "None");
+    assert_eq!(format!("{}", Value::Number(42.0)), "42");
+    assert_eq!(format!("{}", Value::Number(-3.0)), "-3");
+    assert_eq!(format!("{}", Value::Number(0.5)), "0.5");
+    assert_eq!(format!("{}", Value::Identifier("x".to_string())), "x");
+}
+
+#[test]
+fn test_display_string_escapes() {
+    let value = Value::String("say \"hi\"\n\\ done".to_string());
+
+    assert_eq!(format!("{}", value), r#""say \"hi\"\n\\ done""#);
+}
+
+#[test]
+fn test_display_bytes() {
+    let value = Value::Bytes(b"\x00ok\"\n\xff".to_vec());
+    assert_eq!(format!("{}", value), r#"b"\x00ok\"\n\xff""#);
+
+    let source = format!("[f(data={})]", value);
+    let calls = parse_python_with_nom(&source).unwrap();
+    assert_eq!(calls[0].kwargs["data"], value);
+    assert_eq!(parse_python(&source).unwrap(), calls);
+}
+
+#[test]
+fn test_display_collections() {
+    let list = Value::List(vec![
+        Value::Number(1.0),
+        Value::String("a".to_string()),
+        Value::List(vec![]),
+    ]);
+    assert_eq!(format!("{}", list), r#"[1, "a", []]"#);
+
+    let set = Value::Set(vec![Value::Number(1.0), Value::Number(2.0)]);
+    assert_eq!(format!("{}", set), "{1, 2}");
+
+    let pair = Value::Tuple(vec![Value::Number(1.0), Value::Number(2.0)]);
+    assert_eq!(format!("{}", pair), "(1, 2)");
+    assert_eq!(
+        format!("{}", Value::Tuple(vec![Value::Number(1.0)])),
+        "(1,)"
+    );
+    assert_eq!(format!("{}", Value::Tuple(vec![])), "()");
+}
+
+#[test]
+fn test_display_function_call() {
+    let mut kwargs = HashMap::new();
+    kwargs.insert("location".to_string(), Value::String("Tokyo".to_string()));
+    kwargs.insert("days".to_string(), Value::Number(7.0));
+    kwargs.insert("metric".to_string(), Value::Bool(true));
+    let call = FunctionCall {
+        name: "get_weather".to_string(),
+        kwargs,
+        span: None,
+    };
+
+    // Kwargs are sorted by name
+    assert_eq!(
+        format!("{}", call),
+        r#"get_weather(days=7, location="Tokyo", metric=True)"#
+    );
+    assert_eq!(
+        format!("{}", Value::FunctionCall(call)),
+        r#"get_weather(days=7, location="Tokyo", metric=True)"#
+    );
+}
+
+#[test]
+fn test_display_round_trips_through_parsers() {
+    let source = r#"[search(query="rust \"nom\"", limit=10, ratio=0.25, exact=False, tags=["a", "b"], owner=None)]"#;
+
+    let calls = parse_python_with_nom(source).unwrap();
+    let printed = format!("[{}]", calls[0]);
+
+    assert_eq!(parse_python_with_nom(&printed).unwrap(), calls);
+    assert_eq!(parse_python(&printed).unwrap(), calls);
+}
+
+#[test]
+fn test_display_non_finite_numbers_round_trip() {
+    let call = FunctionCall::new("f")
+        .with_kwarg("high", Value::Number(f64::INFINITY))
+        .with_kwarg("low", Value::Number(f64::NEG_INFINITY))
+        .with_kwarg("missing", Value::Number(f64::NAN));
+    assert_eq!(
+        format!("{}", call),
+        r#"f(high=float("inf"), low=float("-inf"), missing=float("nan"))"#
+    );
+
+    let printed = format!("[{}]", call);
+    for calls in [
+        parse_python_with_nom(&printed).unwrap(),
+        parse_python(&printed).unwrap(),
+    ] {
+        assert_eq!(calls[0].kwargs["high"], Value::Number(f64::INFINITY));
+        assert_eq!(calls[0].kwargs["low"], Value::Number(f64::NEG_INFINITY));
+        assert!(matches!(calls[0].kwargs["missing"], Value::Number(n) if n.is_nan()));
+    }
+
+    // Nested in a list, too
+    let source = r#"[f(bounds=[float("-inf"), float(" Infinity ")])]"#;
+    let expected = Value::List(vec![
+        Value::Number(f64::NEG_INFINITY),
+        Value::Number(f64::INFINITY),
+    ]);
+    assert_eq!(
+        parse_python_with_nom(source).unwrap()[0].kwargs["bounds"],
+        expected
+    );
+    assert_eq!(parse_python(source).unwrap()[0].kwargs["bounds"], expected);
+}
+
+#[test]
+fn test_display_raw_strings() {
+    let pattern = Value::String(r"\w+@\w+\.\w+".to_string());
+    assert_eq!(format!("{}", pattern), r#"r"\w+@\w+\.\w+""#);
+
+    // A raw string can't hold its own quote or end with a backslash
+    let quoted = Value::String(r#"\d "x""#.to_string());
+    assert_eq!(format!("{}", quoted), r#""\\d \"x\"""#);
+    let trailing = Value::String(r"C:\temp\".to_string());
+    assert_eq!(format!("{}", trailing), r#""C:\\temp\\""#);
+
+    let source = format!("[f(pattern={})]", pattern);
+    let calls = parse_python_with_nom(&source).unwrap();
+    assert_eq!(calls[0].kwargs["pattern"], pattern);
+    assert_eq!(parse_python(&source).unwrap(), calls);
+}
+
+#[test]
+fn test_to_json() {
+    let call = parse_python_with_nom(
+        r#"[f(s="a \"b\"\n", n=7, x=0.5, b=True, none=None, mode=fast, tags=["x", 1])]"#,
+    )
+    .unwrap()
+    .remove(0)
+    .with_kwarg(
+        "inner",
+        Value::FunctionCall(FunctionCall::new("g").with_kwarg("k", Value::Bool(false))),
+    );
+
+    assert_eq!(
+        call.arguments_json(),
+        r#"{"b": true, "inner": {"__call__": {"name": "g", "kwargs": {"k": false}}}, "mode": "fast", "n": 7, "none": null, "s": "a \"b\"\n", "tags": ["x", 1], "x": 0.5}"#
+    );
+    assert_eq!(Value::Number(f64::NAN).to_json(), "null");
+}
@@ -0,0 +1,1055 @@
+use backend::nom_parser::parse_python_with_surrounding_text;
+use backend::testing::MORE_TYPES_FUNCTION_OUTPUT;
+use backend::{
+    FunctionCall, IncrementalBackend, LogosParserState, NomParserState, ParseEvent, ParserConfig,
+    ParserEvents, ToolCallEvent, Value, assert_function_calls_eq, normalize_function_call,
+    parse_incremental, parse_python, parse_python_all, parse_python_with_config,
+    parse_python_with_nom, parse_python_with_nom_config, parse_python_with_nom_remainder,
+};
+
+#[test]
+fn test_none_parity() {
+    let input = "[f(role=None)]";
+
+    let logos = parse_python(input).unwrap();
+    let nom = parse_python_with_nom(input).unwrap();
+
+    assert_eq!(logos, nom);
+    assert_eq!(logos[0].kwargs.get("role"), Some(&Value::Null));
+}
+
+#[test]
+fn test_ellipsis_parity() {
+    let input = "[foo(x=..., y=[1, ...], z=(..., 2))]";
+
+    let logos = parse_python(input).unwrap();
+    let nom = parse_python_with_nom(input).unwrap();
+
+    assert_eq!(logos, nom);
+    assert_eq!(nom[0].kwargs["x"], Value::Ellipsis);
+    assert_eq!(
+        nom[0].kwargs["y"],
+        Value::List(vec![Value::Number(1.0), Value::Ellipsis])
+    );
+    assert_eq!(
+        nom[0].kwargs["z"],
+        Value::Tuple(vec![Value::Ellipsis, Value::Number(2.0)])
+    );
+    // Printed back as written
+    assert_eq!(nom[0].to_string(), input[1..input.len() - 1]);
+}
+
+#[test]
+fn test_splat_parity() {
+    let input = r#"[func(opts={**defaults, "key": "value"}, items=[*base_items, "extra"])]"#;
+
+    let logos = parse_python(input).unwrap();
+    let nom = parse_python_with_nom(input).unwrap();
+
+    assert_eq!(logos, nom);
+    assert_eq!(
+        nom[0].kwargs["opts"],
+        Value::List(vec![
+            Value::DictSplat("defaults".to_string()),
+            Value::String("key".to_string()),
+            Value::String("value".to_string()),
+        ])
+    );
+    assert_eq!(
+        nom[0].kwargs["items"],
+        Value::List(vec![
+            Value::ListSplat("base_items".to_string()),
+            Value::String("extra".to_string()),
+        ])
+    );
+    assert_eq!(
+        parse_python_with_nom("[func(opts={**defaults})]").unwrap()[0].kwargs["opts"],
+        Value::List(vec![Value::DictSplat("defaults".to_string())])
+    );
+    assert_eq!(
+        nom[0].kwargs["items"].to_json(),
+        r#"[{"__list_splat__": "base_items"}, "extra"]"#
+    );
+    assert_eq!(
+        nom[0].kwargs["opts"].to_json(),
+        r#"[{"__splat__": "defaults"}, "key", "value"]"#
+    );
+}
+
+#[test]
+fn test_more_types_parity() {
+    let input = format!("[{}]", MORE_TYPES_FUNCTION_OUTPUT);
+
+    let logos = parse_python(&input).unwrap();
+    let nom = parse_python_with_nom(&input).unwrap();
+
+    assert_function_calls_eq!(logos, nom);
+}
+
+#[test]
+fn test_nested_dict_parity() {
+    let input = r#"[f(config={'db': {'host': 'localhost', 'ports': [5432, 5433]}, 'debug': True}, after=1)]"#;
+
+    let logos = parse_python(input).unwrap();
+    let nom = parse_python_with_nom(input).unwrap();
+
+    assert_function_calls_eq!(logos, nom);
+    assert_eq!(
+        logos[0].kwargs.get("config"),
+        Some(&Value::List(vec![
+            Value::String("db".to_string()),
+            Value::List(vec![
+                Value::String("host".to_string()),
+                Value::String("localhost".to_string()),
+                Value::String("ports".to_string()),
+                Value::List(vec![Value::Number(5432.0), Value::Number(5433.0)]),
+            ]),
+            Value::String("debug".to_string()),
+            Value::Bool(true),
+        ]))
+    );
+    assert_eq!(logos[0].kwargs.get("after"), Some(&Value::Number(1.0)));
+}
+
+#[test]
+fn test_trailing_comma_in_braces_parity() {
+    let input = r#"[f(ids={1, 2,}, mapping={'a': 1, 'b': [2],})]"#;
+
+    assert_function_calls_eq!(
+        parse_python(input).unwrap(),
+        parse_python_with_nom(input).unwrap()
+    );
+}
+
+#[test]
+fn test_empty_dict_parity() {
+    let input = "[do_something_cool(additional_data={})]";
+
+    assert_function_calls_eq!(
+        parse_python(input).unwrap(),
+        parse_python_with_nom(input).unwrap()
+    );
+}
+
+#[test]
+fn test_none_in_list_and_dict_parity() {
+    let input = "[f(items=[1, None], mapping={'a': None, 'b': [None]})]";
+
+    let logos = parse_python(input).unwrap();
+    let nom = parse_python_with_nom(input).unwrap();
+
+    assert_function_calls_eq!(logos, nom);
+    assert_eq!(
+        logos[0].kwargs.get("items"),
+        Some(&Value::List(vec![Value::Number(1.0), Value::Null]))
+    );
+    assert_eq!(
+        logos[0].kwargs.get("mapping"),
+        Some(&Value::List(vec![
+            Value::String("a".to_string()),
+            Value::Null,
+            Value::String("b".to_string()),
+            Value::List(vec![Value::Null]),
+        ]))
+    );
+}
+
+#[test]
+fn test_keyword_prefixed_identifier_parity() {
+    let input = "[f(a=Nonexistent, b=Trueish, c=None)]";
+
+    let logos = parse_python(input).unwrap();
+    let nom = parse_python_with_nom(input).unwrap();
+
+    assert_function_calls_eq!(logos, nom);
+    assert_eq!(
+        nom[0].kwargs.get("a"),
+        Some(&Value::Identifier("Nonexistent".to_string()))
+    );
+    assert_eq!(
+        nom[0].kwargs.get("b"),
+        Some(&Value::Identifier("Trueish".to_string()))
+    );
+    assert_eq!(nom[0].kwargs.get("c"), Some(&Value::Null));
+}
+
+#[test]
+fn test_custom_markers_parity() {
+    let config = ParserConfig::new("<|tool_calls_begin|>", "<|tool_calls_end|>");
+    let input = r#"Let me check.<|tool_calls_begin|>[get_weather(city="SF"), get_time()]<|tool_calls_end|>"#;
+
+    let logos = parse_python_with_config(input, &config).unwrap();
+    let nom = parse_python_with_nom_config(input, &config).unwrap();
+
+    assert_function_calls_eq!(logos, nom);
+    let names: Vec<&str> = nom.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["get_weather", "get_time"]);
+}
+
+#[test]
+fn test_mistral_prefix_parity() {
+    let config = ParserConfig::mistral();
+    let input = r#"[TOOL_CALLS][get_weather(city="SF"), get_time(zone='UTC')]"#;
+
+    let logos = parse_python_with_config(input, &config).unwrap();
+    let nom = parse_python_with_nom_config(input, &config).unwrap();
+    let strict = parse_python_with_nom_config(input, &config.clone().with_strict(true)).unwrap();
+
+    assert_function_calls_eq!(logos, nom);
+    assert_eq!(nom, strict);
+    let names: Vec<&str> = nom.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["get_weather", "get_time"]);
+}
+
+#[test]
+fn test_set_literal_parity() {
+    let input = r#"[f(ids={3, 1, 2}, tags={'a'}, empty={}, mapping={'k': 1})]"#;
+
+    let logos = parse_python(input).unwrap();
+    let nom = parse_python_with_nom(input).unwrap();
+
+    assert_function_calls_eq!(logos, nom);
+    assert_eq!(
+        logos[0].kwargs.get("ids"),
+        Some(&Value::Set(vec![
+            Value::Number(3.0),
+            Value::Number(1.0),
+            Value::Number(2.0),
+        ]))
+    );
+    assert_eq!(
+        logos[0].kwargs.get("tags"),
+        Some(&Value::Set(vec![Value::String("a".to_string())]))
+    );
+    assert_eq!(logos[0].kwargs.get("empty"), Some(&Value::List(vec![])));
+    assert_eq!(
+        logos[0].kwargs.get("mapping"),
+        Some(&Value::List(vec![
+            Value::String("k".to_string()),
+            Value::Number(1.0),
+        ]))
+    );
+}
+
+#[test]
+fn test_empty_call_list() {
+    let inputs = [
+        "[]",
+        "[ \n ]",
+        "<|python_start|>[]<|python_end|>",
+        "<|python_start|> [ ] <|python_end|>",
+        "No tools needed. <|python_start|>[]<|python_end|> Done.",
+    ];
+
+    for input in inputs {
+        assert_eq!(parse_python(input), Ok(vec![]), "logos: {:?}", input);
+        assert_eq!(parse_python_with_nom(input), Ok(vec![]), "nom: {:?}", input);
+        assert_eq!(
+            parse_python_with_surrounding_text(input),
+            Ok(vec![]),
+            "surrounding text: {:?}",
+            input
+        );
+        assert!(parse_python_all(input).is_empty(), "all: {:?}", input);
+        assert_eq!(
+            parse_python_with_nom_remainder(input).map(|(calls, _)| calls),
+            Ok(vec![]),
+            "remainder: {:?}",
+            input
+        );
+
+        let mut state = NomParserState::new();
+        assert_eq!(
+            parse_incremental(&mut state, input),
+            Ok(vec![]),
+            "incremental: {:?}",
+            input
+        );
+        assert!(state.is_complete());
+    }
+}
+
+fn feed_all(backend: &mut dyn IncrementalBackend, chunks: &[&str]) -> Vec<Vec<FunctionCall>> {
+    chunks
+        .iter()
+        .map(|chunk| backend.feed(chunk).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_incremental_backend_parity() {
+    let chunks = [
+        "Let me check. <|python_start|>[",
+        r#"search(query="test", limit=5"#,
+        "), write_file(",
+        r#"filename="test.txt", content="it's done""#,
+        ")]<|python_end|>",
+    ];
+
+    let mut nom = NomParserState::new();
+    let mut logos = LogosParserState::new();
+
+    let nom_steps = feed_all(&mut nom, &chunks);
+    let logos_steps = feed_all(&mut logos, &chunks);
+
+    for (nom_calls, logos_calls) in nom_steps.iter().zip(&logos_steps) {
+        assert_function_calls_eq!(logos_calls, nom_calls);
+    }
+    assert!(nom_steps[..4].iter().all(|calls| calls.is_empty()));
+    assert_eq!(nom_steps[4].len(), 2);
+
+    assert_function_calls_eq!(logos.finish().unwrap(), nom.finish().unwrap());
+    assert_function_calls_eq!(logos.parsed(), nom.parsed());
+    assert!(logos.is_complete() && nom.is_complete());
+
+    logos.reset();
+    nom.reset();
+    assert!(logos.parsed().is_empty() && nom.parsed().is_empty());
+}
+
+#[test]
+fn test_logos_incremental_finish_recovers_truncated_call() {
+    let mut logos = LogosParserState::new();
+
+    assert_eq!(logos.feed("[func1(x=1").unwrap(), vec![]);
+    assert!(!logos.is_complete());
+
+    let calls = logos.finish().unwrap();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].kwargs.get("x"), Some(&Value::Number(1.0)));
+    assert!(logos.is_truncated());
+
+    logos.reset();
+    logos.feed("[func1(x=1)]").unwrap();
+    logos.finish().unwrap();
+    assert!(!logos.is_truncated());
+}
+
+fn roll_dice() -> FunctionCall {
+    FunctionCall {
+        name: "roll_dice".to_string(),
+        kwargs: [("sides".to_string(), Value::Number(6.0))].into(),
+        span: None,
+    }
+}
+
+#[test]
+fn test_duplicate_calls_batch() {
+    let adjacent = "[roll_dice(sides=6), roll_dice(sides=6)]";
+    let non_adjacent = "[roll_dice(sides=6), flip_coin(), roll_dice(sides=6)]";
+
+    for input in [adjacent, non_adjacent] {
+        let logos = parse_python(input).unwrap();
+        let nom = parse_python_with_nom(input).unwrap();
+
+        assert_function_calls_eq!(logos, nom);
+        let dice = nom.iter().filter(|call| **call == roll_dice()).count();
+        assert_eq!(dice, 2, "{:?}", input);
+    }
+}
+
+#[test]
+fn test_duplicate_calls_streaming() {
+    let adjacent = ["[roll_dice(sides=6), ", "roll_dice(sides=6)", "]"];
+    let non_adjacent = [
+        "[roll_dice(sides=6)] ",
+        "[flip_coin()] ",
+        "[roll_dice(",
+        "sides=6)]",
+    ];
+
+    for chunks in [&adjacent[..], &non_adjacent[..]] {
+        let mut nom = NomParserState::new();
+        let mut logos = LogosParserState::new();
+
+        let nom_calls: Vec<FunctionCall> = feed_all(&mut nom, chunks).concat();
+        let logos_calls: Vec<FunctionCall> = feed_all(&mut logos, chunks).concat();
+
+        assert_function_calls_eq!(logos_calls, nom_calls);
+        let dice = nom_calls
+            .iter()
+            .filter(|call| **call == roll_dice())
+            .count();
+        assert_eq!(dice, 2, "{:?}", chunks);
+        assert_eq!(nom.parsed(), &nom_calls[..]);
+    }
+}
+
+#[test]
+fn test_trailing_escaped_backslash_parity() {
+    let input = r#"[f(a="a\\", b="a\\b", c="\\", d='x\\', e="q\"")]"#;
+
+    let logos = parse_python(input).unwrap();
+    let nom = parse_python_with_nom(input).unwrap();
+
+    assert_function_calls_eq!(logos, nom);
+    assert_eq!(nom[0].get_str("a"), Some("a\\"));
+    assert_eq!(nom[0].get_str("b"), Some("a\\b"));
+    assert_eq!(nom[0].get_str("c"), Some("\\"));
+    assert_eq!(nom[0].get_str("d"), Some("x\\"));
+    assert_eq!(nom[0].get_str("e"), Some("q\""));
+}
+
+#[test]
+fn test_incremental_status_parity() {
+    // (chunk, inside block, current call, pending input) after each chunk
+    let steps = [
+        ("Let me check. ", false, None, false),
+        ("<|python_start|>[search(", true, Some("search"), true),
+        (r#"query="a (b""#, true, Some("search"), true),
+        ("), write_file(", true, Some("write_file"), true),
+        (r#"content="it's done")]"#, true, None, false),
+        ("<|python_end|> Done.", false, None, false),
+    ];
+
+    let mut nom = NomParserState::new();
+    let mut logos = LogosParserState::new();
+    for backend in [&mut nom as &mut dyn IncrementalBackend, &mut logos] {
+        for (chunk, inside_block, call, pending) in steps {
+            backend.feed(chunk).unwrap();
+            assert_eq!(backend.is_inside_python_block(), inside_block, "{}", chunk);
+            assert_eq!(backend.current_call_name(), call, "{}", chunk);
+            assert_eq!(backend.has_pending_input(), pending, "{}", chunk);
+            assert_eq!(backend.is_complete(), !pending, "{}", chunk);
+        }
+    }
+}
+
+#[test]
+fn test_delimiters_in_set_and_dict_strings_keep_the_call_open() {
+    for chunk in [r#"[f(a={")]"}"#, r#"[f(a={"k": "a, ) ] b"}"#] {
+        let mut nom = NomParserState::new();
+        let mut logos = LogosParserState::new();
+        for backend in [&mut nom as &mut dyn IncrementalBackend, &mut logos] {
+            backend.feed(chunk).unwrap();
+            assert!(!backend.is_complete(), "{}", chunk);
+            backend.feed(", b=1)]").unwrap();
+            assert!(backend.is_complete(), "{}", chunk);
+            assert_eq!(
+                backend.parsed()[0].kwargs.get("b"),
+                Some(&Value::Number(1.0))
+            );
+        }
+    }
+}
+
+#[test]
+fn test_raw_string_parity() {
+    let source = r#"[func(pattern=r"\d+\.\d+", path=r'C:\Users\foo', plain="a\tb")]"#;
+
+    let nom_result = parse_python_with_nom(source).unwrap();
+    let logos_result = parse_python(source).unwrap();
+
+    assert_eq!(nom_result, logos_result);
+    assert_eq!(nom_result[0].get_str("pattern"), Some(r"\d+\.\d+"));
+    assert_eq!(nom_result[0].get_str("path"), Some(r"C:\Users\foo"));
+    assert_eq!(nom_result[0].get_str("plain"), Some("a\tb"));
+}
+
+#[test]
+fn test_f_string_parity() {
+    let source = r#"[greet(msg=f"Hi {name}\t({n})", raw=fr'\d{x}', plain="{x}")]"#;
+
+    let nom_result = parse_python_with_nom(source).unwrap();
+    let logos_result = parse_python(source).unwrap();
+
+    assert_eq!(nom_result, logos_result);
+    assert_eq!(nom_result[0].get_str("msg"), Some("Hi {name}\t({n})"));
+    assert_eq!(nom_result[0].get_str("raw"), Some(r"\d{x}"));
+}
+
+#[test]
+fn test_missing_comma_between_kwargs() {
+    let source = "[foo(a=1 b=2), bar(c=3)]";
+
+    // The logos engine reads an identifier after a value as the next kwarg
+    let logos_result = parse_python(source).unwrap();
+    assert_eq!(logos_result.len(), 2);
+    assert_eq!(logos_result[0].get_f64("a"), Some(1.0));
+    assert_eq!(logos_result[0].get_f64("b"), Some(2.0));
+    assert_eq!(logos_result[1].get_f64("c"), Some(3.0));
+
+    // The nom engine rejects the malformed call rather than guessing
+    assert!(parse_python_with_nom(source).is_err());
+    let attempts = parse_python_all(source);
+    assert!(attempts[0].is_err());
+    assert_eq!(attempts[1].as_ref().unwrap().name, "bar");
+}
+
+#[test]
+fn test_streaming_events_parity() {
+    let chunks = ["Sure. [get_weather(", r#"city="SF", days=3), "#, "ping()]"];
+
+    let mut nom = NomParserState::new();
+    nom.set_emit_events(true);
+    let mut logos = LogosParserState::new();
+    logos.set_emit_events(true);
+
+    for backend in [&mut nom as &mut dyn IncrementalBackend, &mut logos] {
+        feed_all(backend, &chunks);
+        let events = backend.drain_events();
+
+        let names: Vec<&str> = events
+            .iter()
+            .filter_map(|event| match event {
+                ToolCallEvent::CallStarted { name, .. } => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["get_weather", "ping"]);
+
+        let arguments: Vec<String> = (0..2)
+            .map(|call_index| {
+                events
+                    .iter()
+                    .filter_map(|event| match event {
+                        ToolCallEvent::ArgumentsDelta {
+                            index,
+                            json_fragment,
+                        } if *index == call_index => Some(json_fragment.as_str()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(arguments, [r#"{"city": "SF", "days": 3}"#, "{}"]);
+        assert!(backend.drain_events().is_empty());
+    }
+}
+
+#[test]
+fn test_signed_numbers_parity() {
+    let source = "[func(offset=+5, delta=-2.5, scale=+1e-3)]";
+
+    let nom_result = parse_python_with_nom(source).unwrap();
+    assert_eq!(parse_python(source).unwrap(), nom_result);
+    assert_eq!(nom_result[0].get_f64("offset"), Some(5.0));
+    assert_eq!(nom_result[0].get_f64("delta"), Some(-2.5));
+    assert_eq!(nom_result[0].get_f64("scale"), Some(0.001));
+}
+
+#[test]
+fn test_no_arithmetic_on_numbers() {
+    let source = "[func(a=1+2)]";
+
+    // `+2` after a value is not folded into it: nom rejects the call and logos keeps `a=1`
+    assert!(parse_python_with_nom(source).is_err());
+    assert_eq!(parse_python(source).unwrap()[0].get_f64("a"), Some(1.0));
+}
+
+#[test]
+fn test_parse_events_parity_for_whole_calls() {
+    let source = r#"Sure. [get_weather(days=3, city="SF"), ping()]"#;
+
+    let nom = NomParserState::new().feed_events(source).unwrap();
+    let logos = LogosParserState::new().feed_events(source).unwrap();
+
+    assert_eq!(nom, logos);
+    assert_eq!(
+        nom[..3],
+        [
+            ParseEvent::FunctionStarted {
+                name: "get_weather".to_string()
+            },
+            ParseEvent::ArgumentParsed {
+                name: "get_weather".to_string(),
+                key: "city".to_string(),
+                value: Value::String("SF".to_string()),
+            },
+            ParseEvent::ArgumentParsed {
+                name: "get_weather".to_string(),
+                key: "days".to_string(),
+                value: Value::Number(3.0),
+            },
+        ]
+    );
+    assert_eq!(
+        nom.last(),
+        Some(&ParseEvent::FunctionCompleted { index: 1 })
+    );
+}
+
+#[test]
+fn test_unicode_identifiers_parity() {
+    let input = r#"<|python_start|>[天気予報(都市="東京", días=3), météo(ville="Paris", unité=Celsius)]<|python_end|>"#;
+
+    let logos = parse_python(input).unwrap();
+    let nom = parse_python_with_nom(input).unwrap();
+
+    assert_function_calls_eq!(logos, nom);
+    assert_eq!(logos[0].name, "天気予報");
+    assert_eq!(
+        logos[0].kwargs.get("都市"),
+        Some(&Value::String("東京".to_string()))
+    );
+    assert_eq!(logos[0].kwargs.get("días"), Some(&Value::Number(3.0)));
+    assert_eq!(logos[1].name, "météo");
+    assert_eq!(
+        logos[1].kwargs.get("unité"),
+        Some(&Value::Identifier("Celsius".to_string()))
+    );
+
+    let mut state = NomParserState::new();
+    for c in input.chars() {
+        parse_incremental(&mut state, &c.to_string()).unwrap();
+    }
+    assert_eq!(state.parsed_functions, nom);
+}
+
+#[test]
+fn test_numeric_literal_parity() {
+    let literals = [
+        ("0", 0.0),
+        ("42", 42.0),
+        ("-7", -7.0),
+        ("+3.25", 3.25),
+        ("1e10", 1e10),
+        ("1E5", 1e5),
+        ("1.5e-3", 1.5e-3),
+        ("-2.5E+2", -250.0),
+        (".5", 0.5),
+        (".5e3", 500.0),
+        ("-.25", -0.25),
+        ("2.", 2.0),
+        ("2.e2", 200.0),
+        ("007", 7.0),
+        ("-5.0e-2", -0.05),
+        // Whitespace may follow the sign, as in Python
+        ("- 5", -5.0),
+        ("+ .5", 0.5),
+        ("-\n2.5", -2.5),
+    ];
+
+    for (literal, expected) in literals {
+        let input = format!("[f(x={})]", literal);
+        let logos = parse_python(&input).unwrap();
+        let nom = parse_python_with_nom(&input).unwrap();
+
+        assert_eq!(logos, nom, "{}", literal);
+        assert_eq!(nom[0].kwargs["x"], Value::Number(expected), "{}", literal);
+    }
+    // In a list, the sign stays with its number
+    let list = parse_python_with_nom("[f(x=[- 1, -2])]").unwrap();
+    assert_eq!(parse_python("[f(x=[- 1, -2])]").unwrap(), list);
+    assert_eq!(
+        list[0].kwargs["x"],
+        Value::List(vec![Value::Number(-1.0), Value::Number(-2.0)])
+    );
+
+    // A sign with no digits after it is not a number
+    let strict = ParserConfig::default().with_strict(true);
+    for source in ["[f(x=-)]", "[f(x=- y)]"] {
+        assert!(parse_python_with_nom(source).is_err(), "{}", source);
+        assert!(
+            parse_python_with_config(source, &strict).is_err(),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_pathological_numbers() {
+    let long_digits = "9".repeat(10_000);
+    let long_fraction = format!("0.{}1", "0".repeat(10_000));
+    // Too large or too small for an f64, so they round to infinity or zero
+    let out_of_range = [
+        ("1e999999", f64::INFINITY),
+        ("-1e999999", f64::NEG_INFINITY),
+        ("1e-999999", 0.0),
+        (long_digits.as_str(), f64::INFINITY),
+        (long_fraction.as_str(), 0.0),
+    ];
+    for (literal, expected) in out_of_range {
+        let input = format!("[f(x={})]", literal);
+        for function_calls in [parse_python(&input), parse_python_with_nom(&input)] {
+            assert_eq!(
+                function_calls.unwrap()[0].kwargs["x"],
+                Value::Number(expected)
+            );
+        }
+    }
+
+    // Malformed numbers are recovered from or rejected, never a panic
+    let malformed = [
+        ".", "-", "+-1", "--5", "-.", "+.e1", "1e", "1e+", "1.2.3", "1e1e1", "0x1F", "1_000",
+    ];
+    let strict = ParserConfig::default().with_strict(true);
+    for literal in malformed {
+        let input = format!("[f(x={})]", literal);
+        assert!(parse_python(&input).is_ok(), "{}", literal);
+        let _ = parse_python_with_config(&input, &strict);
+        let _ = parse_python_with_nom(&input);
+        let mut state = NomParserState::new();
+        for c in input.chars() {
+            let _ = parse_incremental(&mut state, &c.to_string());
+        }
+    }
+}
+
+#[test]
+fn test_normalized_parity() {
+    let input =
+        r#"[configure(enabled=True, fallback=None, modes=[False, fast], opts={"k": (True,)})]"#;
+
+    let logos: Vec<FunctionCall> = parse_python(input)
+        .unwrap()
+        .into_iter()
+        .map(normalize_function_call)
+        .collect();
+    let nom: Vec<FunctionCall> = parse_python_with_nom(input)
+        .unwrap()
+        .into_iter()
+        .map(normalize_function_call)
+        .collect();
+
+    assert_eq!(logos, nom);
+    assert_eq!(nom[0].kwargs["fallback"], Value::Null);
+}
+
+#[test]
+fn test_identifiers_cannot_start_with_a_digit() {
+    for input in ["[3d(x=1)]", "[٣d(x=1)]"] {
+        assert!(parse_python_with_nom(input).is_err(), "nom: {:?}", input);
+        assert!(
+            parse_python(input).map_or(true, |calls| calls.is_empty()),
+            "logos: {:?}",
+            input
+        );
+    }
+}
+
+#[test]
+fn test_xid_identifiers_round_trip() {
+    // Devanagari vowel signs and viramas are combining marks, which Python allows after the
+    // first character of an identifier
+    let input = r#"[данные(x=1), खोज(प्रश्न="मौसम"), _取得データ2()]"#;
+
+    let logos = parse_python(input).unwrap();
+    let nom = parse_python_with_nom(input).unwrap();
+
+    assert_function_calls_eq!(logos, nom);
+    let names: Vec<&str> = nom.iter().map(|call| call.name.as_str()).collect();
+    assert_eq!(names, ["данные", "खोज", "_取得データ2"]);
+    assert_eq!(nom[1].get_str("प्रश्न"), Some("मौसम"));
+
+    let printed = format!(
+        "[{}]",
+        nom.iter()
+            .map(|call| call.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    assert_eq!(parse_python_with_nom(&printed).unwrap(), nom);
+    assert_eq!(parse_python(&printed).unwrap(), nom);
+}
+
+struct BlockEnds(usize);
+
+impl ParserEvents for BlockEnds {
+    fn on_block_end(&mut self) {
+        self.0 += 1;
+    }
+}
+
+#[test]
+fn test_markers_split_at_every_offset() {
+    let start = "<|python_start|>";
+    let end = "<|python_end|>";
+    let calls = r#"[get_weather(city="SF"), ping()]"#;
+
+    for start_split in 1..start.len() {
+        for end_split in 1..end.len() {
+            let chunks = [
+                format!("Sure. {}", &start[..start_split]),
+                format!("{}{}{}", &start[start_split..], calls, &end[..end_split]),
+                format!("{} Done.", &end[end_split..]),
+            ];
+
+            let mut nom = NomParserState::new();
+            let mut logos = LogosParserState::new();
+            for backend in [&mut nom as &mut dyn IncrementalBackend, &mut logos] {
+                let mut block_ends = BlockEnds(0);
+                let mut inside = Vec::new();
+                for chunk in &chunks {
+                    backend.feed_with(chunk, &mut block_ends).unwrap();
+                    inside.push(backend.is_inside_python_block());
+                }
+
+                let names: Vec<&str> = backend.parsed().iter().map(|f| f.name.as_str()).collect();
+                assert_eq!(names, ["get_weather", "ping"], "chunks: {:?}", chunks);
+                assert_eq!(inside, [false, true, false], "chunks: {:?}", chunks);
+                assert_eq!(block_ends.0, 1, "chunks: {:?}", chunks);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_tracked_indices_parity() {
+    let chunks = [
+        "Sure. [get_weather(",
+        r#"city="SF"), ping()] "#,
+        "and then [search(q=",
+        "1)]",
+    ];
+
+    let mut nom = NomParserState::new();
+    let mut logos = LogosParserState::new();
+    for backend in [&mut nom as &mut dyn IncrementalBackend, &mut logos] {
+        let mut tracked = Vec::new();
+        for chunk in &chunks {
+            tracked.extend(backend.feed_tracked(chunk).unwrap());
+        }
+
+        let summary: Vec<(usize, &str, &str)> = tracked
+            .iter()
+            .map(|call| {
+                (
+                    call.index,
+                    call.id.as_str(),
+                    call.function_call.name.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (0, "call_0", "get_weather"),
+                (1, "call_1", "ping"),
+                (2, "call_2", "search")
+            ]
+        );
+        assert_eq!(backend.tracked_functions(), tracked);
+
+        // The indices match the ones the events report
+        backend.reset();
+        let mut completed = Vec::new();
+        for chunk in &chunks {
+            for event in backend.feed_events(chunk).unwrap() {
+                if let ParseEvent::FunctionCompleted { index } = event {
+                    completed.push(index);
+                }
+            }
+        }
+        assert_eq!(completed, [0, 1, 2]);
+    }
+}
+
+#[test]
+fn test_bytes_literal_parity() {
+    let input = r#"[upload(data=b"\x00\x01\x02", header=B'PK\x03\x04', text=b"a\n", bad=b"\xZZ", signed=b"\x+f")]"#;
+
+    let logos = parse_python(input).unwrap();
+    let nom = parse_python_with_nom(input).unwrap();
+
+    assert_function_calls_eq!(logos, nom);
+    assert_eq!(nom[0].kwargs["data"], Value::Bytes(vec![0, 1, 2]));
+    assert_eq!(
+        nom[0].kwargs["header"],
+        Value::Bytes(b"PK\x03\x04".to_vec())
+    );
+    assert_eq!(nom[0].kwargs["text"], Value::Bytes(b"a\n".to_vec()));
+    // An invalid escape is kept as written
+    assert_eq!(nom[0].kwargs["bad"], Value::Bytes(br"\xZZ".to_vec()));
+    assert_eq!(nom[0].kwargs["signed"], Value::Bytes(br"\x+f".to_vec()));
+
+    let mut state = NomParserState::new();
+    for c in input.chars() {
+        parse_incremental(&mut state, &c.to_string()).unwrap();
+    }
+    assert_eq!(state.parsed_functions, nom);
+}
+
+#[test]
+fn test_tuple_literal_parity() {
+    let input =
+        r#"[locate(coords=(40.7, -74.0), pair=(1,), empty=(), grouped=(5), nested=((1, 2), [3]))]"#;
+
+    let logos = parse_python(input).unwrap();
+    let nom = parse_python_with_nom(input).unwrap();
+
+    assert_function_calls_eq!(logos, nom);
+    let kwargs = &nom[0].kwargs;
+    assert_eq!(
+        kwargs["coords"],
+        Value::Tuple(vec![Value::Number(40.7), Value::Number(-74.0)])
+    );
+    assert_eq!(kwargs["pair"], Value::Tuple(vec![Value::Number(1.0)]));
+    assert_eq!(kwargs["empty"], Value::Tuple(vec![]));
+    // Without a trailing comma the parentheses only group the value
+    assert_eq!(kwargs["grouped"], Value::Number(5.0));
+    assert_eq!(
+        kwargs["nested"],
+        Value::Tuple(vec![
+            Value::Tuple(vec![Value::Number(1.0), Value::Number(2.0)]),
+            Value::List(vec![Value::Number(3.0)]),
+        ])
+    );
+    assert!(parse_python_with_nom("[locate(pair=(,))]").is_err());
+
+    let mut state = NomParserState::new();
+    for c in input.chars() {
+        parse_incremental(&mut state, &c.to_string()).unwrap();
+    }
+    assert_eq!(state.parsed_functions, nom);
+}
+
+#[test]
+fn test_comments_parity() {
+    let input = "<|python_start|>[foo(a=1)  # the first call, it's [not] (done\n, bar(tag=\"#1 # not a comment\",  # why\n y=2)]<|python_end|>";
+
+    let logos = parse_python(input).unwrap();
+    let nom = parse_python_with_nom(input).unwrap();
+
+    assert_function_calls_eq!(logos, nom);
+    assert_eq!(nom.len(), 2);
+    assert_eq!(nom[0].kwargs["a"], Value::Number(1.0));
+    assert_eq!(
+        nom[1].kwargs["tag"],
+        Value::String("#1 # not a comment".to_string())
+    );
+    assert_eq!(nom[1].kwargs["y"], Value::Number(2.0));
+
+    for backend in [
+        &mut NomParserState::new() as &mut dyn IncrementalBackend,
+        &mut LogosParserState::new(),
+    ] {
+        for c in input.chars() {
+            backend.feed(&c.to_string()).unwrap();
+        }
+        assert_eq!(backend.parsed(), nom);
+    }
+}
+
+const PROSE_AND_CALLS: &str = "Let me check that. I'll look at [the docs](https://example.com) (and \
+    [1], it's quick) first.\n\n<|python_start|>[get_weather(city=\"San Francisco\", days=3)]\
+    <|python_end|>\n\nBased on the forecast [2], it's sunny :( so (maybe) I'll [also] run \
+    <|python_start|>[search(query=\"parks [open]\", near={\"k\": \"a, ) ] b\", \"j\": 1}, \
+    tags={\")]\"}, limit=2), ping()]<|python_end|> and we're done [3";
+
+#[test]
+fn test_streaming_prose_between_blocks() {
+    let expected = parse_python_with_nom(PROSE_AND_CALLS).unwrap();
+    let names: Vec<&str> = expected.iter().map(|call| call.name.as_str()).collect();
+    assert_eq!(names, ["get_weather", "search", "ping"]);
+    assert_eq!(expected[1].kwargs["limit"], Value::Number(2.0));
+
+    for chunk_len in [1, 2, 3, 7, 16, 64] {
+        let chars: Vec<char> = PROSE_AND_CALLS.chars().collect();
+        let chunks: Vec<String> = chars
+            .chunks(chunk_len)
+            .map(|chunk| chunk.iter().collect())
+            .collect();
+
+        for backend in [
+            &mut NomParserState::new() as &mut dyn IncrementalBackend,
+            &mut LogosParserState::new(),
+        ] {
+            let mut streamed = Vec::new();
+            let mut seen = String::new();
+            for chunk in &chunks {
+                seen.push_str(chunk);
+                streamed.extend(backend.feed(chunk).unwrap());
+                // Nothing is reported before its list closes, and prose never yields a call
+                let closed = parse_python_with_nom(&seen).map_or(0, |calls| calls.len());
+                assert!(
+                    streamed.len() <= closed && expected.starts_with(&streamed),
+                    "chunk_len {}, after {:?}: {:?}",
+                    chunk_len,
+                    seen,
+                    streamed
+                );
+            }
+            assert_eq!(streamed, expected, "chunk_len {}", chunk_len);
+            assert_eq!(backend.finish().unwrap(), expected);
+        }
+    }
+}
+
+#[test]
+fn test_streaming_prose_is_not_rescanned() {
+    fn stream_prose(chunks: usize) -> std::time::Duration {
+        let start = std::time::Instant::now();
+        for backend in [
+            &mut NomParserState::new() as &mut dyn IncrementalBackend,
+            &mut LogosParserState::new(),
+        ] {
+            for i in 0..chunks {
+                let chunk = if i % 2 == 0 {
+                    "Some prose with (parens) and [brackets]. "
+                } else {
+                    "It's more text :( with [a link](https://example.com) "
+                };
+                assert_eq!(backend.feed(chunk).unwrap(), vec![]);
+            }
+            backend.feed("[ping()]").unwrap();
+            assert_eq!(backend.parsed().len(), 1);
+        }
+        start.elapsed()
+    }
+
+    // Each prose chunk should cost about its own length, not that of everything before it
+    let small = stream_prose(2_000);
+    let large = stream_prose(8_000);
+    assert!(
+        large < small * 10,
+        "8000 chunks took {:?}, 2000 took {:?}",
+        large,
+        small
+    );
+}
+
+#[test]
+fn test_span_parity() {
+    fn spanned(function_calls: &[FunctionCall], source: &str) -> Vec<String> {
+        let text = |call: &FunctionCall| source[call.span.clone().unwrap()].to_string();
+        function_calls.iter().map(text).collect()
+    }
+
+    let source = r#"Sure! [get_weather(city="SF" ), search(q="x")] and <|python_start|>[ping()]"#;
+    let expected = [r#"get_weather(city="SF" )"#, r#"search(q="x")"#, "ping()"];
+    for function_calls in [parse_python(source), parse_python_with_nom(source)] {
+        assert_eq!(spanned(&function_calls.unwrap(), source), expected);
+    }
+
+    // Located in the input as given, not with its markers rewritten
+    let tagged = "<tool>[ping()]</tool> <tool>[pong(x=1)]</tool>";
+    let config = ParserConfig::new("<tool>", "</tool>");
+    for parse in [parse_python_with_config, parse_python_with_nom_config] {
+        let function_calls = parse(tagged, &config).unwrap();
+        assert_eq!(spanned(&function_calls, tagged), ["ping()", "pong(x=1)"]);
+    }
+
+    // A stream's calls are located in the whole stream, wherever the chunks split it
+    let expected = spanned(
+        &parse_python_with_nom(PROSE_AND_CALLS).unwrap(),
+        PROSE_AND_CALLS,
+    );
+    for chunk_len in [1, 3, 16] {
+        let chars: Vec<char> = PROSE_AND_CALLS.chars().collect();
+        for backend in [
+            &mut NomParserState::new() as &mut dyn IncrementalBackend,
+            &mut LogosParserState::new(),
+        ] {
+            for chunk in chars.chunks(chunk_len) {
+                backend.feed(&chunk.iter().collect::<String>()).unwrap();
+            }
+            let function_calls = backend.finish().unwrap();
+            assert_eq!(spanned(&function_calls, PROSE_AND_CALLS), expected);
+        }
+    }
+
+    // A truncated call runs to the end of what arrived
+    let cut_off = "Ok. [a(), b(x=[1, 2";
+    for backend in [
+        &mut NomParserState::new() as &mut dyn IncrementalBackend,
+        &mut LogosParserState::new(),
+    ] {
+        backend.feed(cut_off).unwrap();
+        assert_eq!(
+            spanned(&backend.finish().unwrap(), cut_off),
+            ["a()", "b(x=[1, 2"]
+        );
+    }
+}
@@ -0,0 +1,335 @@
+use backend::{
+    ParseError, ParseStrategy, ParserConfig, Value, describe_error, offset_to_line_col,
+    parse_python, parse_python_all, parse_python_strict, parse_python_with_config,
+    parse_python_with_diagnostics, parse_python_with_nom, parse_python_with_nom_config,
+    parse_python_with_nom_with_diagnostics,
+};
+
+#[test]
+fn test_offset_to_line_col() {
+    let source = "first line\nsecond\nthird ✓ line";
+
+    assert_eq!(offset_to_line_col(source, 0), (1, 1));
+    assert_eq!(offset_to_line_col(source, 5), (1, 6));
+    // The newline itself ends its line
+    assert_eq!(offset_to_line_col(source, 10), (1, 11));
+    assert_eq!(offset_to_line_col(source, 11), (2, 1));
+    assert_eq!(offset_to_line_col(source, 20), (3, 3));
+    // Columns count characters, and offsets inside a character or past the end are clamped
+    let after_check = source.rfind(" line").unwrap();
+    assert_eq!(offset_to_line_col(source, after_check), (3, 8));
+    assert_eq!(offset_to_line_col(source, after_check - 1), (3, 7));
+    assert_eq!(offset_to_line_col(source, source.len() + 10), (3, 13));
+}
+
+#[test]
+fn test_error_describes_line_and_column() {
+    let source = "Checking both.\n[get_weather(city=\"SF\"),\n  get_time(tz=)]";
+
+    let results = parse_python_all(source);
+    let err = results[1].as_ref().unwrap_err();
+
+    let span = err.span().unwrap();
+    assert_eq!(
+        describe_error(source, err),
+        format!(
+            "Parse error at 3:3: Malformed function call at bytes {}..{}: get_time(tz=)",
+            span.start, span.end
+        )
+    );
+    assert_eq!(&source[span], "get_time(tz=)");
+}
+
+#[test]
+fn test_error_variants() {
+    let source = r#"[get_weather(city="SF"), get_time(tz="UTC"#;
+
+    let results = parse_python_all(source);
+    assert_eq!(
+        results[1],
+        Err(ParseError::UnterminatedString {
+            span: 25..source.len()
+        })
+    );
+
+    let results = parse_python_all("[get_weather(city=[1, 2]");
+    assert_eq!(
+        results[0],
+        Err(ParseError::UnclosedCall {
+            name: "get_weather".to_string(),
+            span: 1..24,
+        })
+    );
+    assert_eq!(
+        results[0].as_ref().unwrap_err().to_string(),
+        "Unclosed call to get_weather at bytes 1..24"
+    );
+
+    assert_eq!(
+        parse_python_with_nom("Sorry, I can't help with that."),
+        Err(ParseError::NoToolCallsFound)
+    );
+    assert_eq!(
+        describe_error("", &ParseError::NoToolCallsFound),
+        "Parse error: No tool calls found"
+    );
+}
+
+#[test]
+fn test_nom_errors_locate_the_failure() {
+    // (input, offset, line and column of the failure, and its message)
+    let cases = [
+        (
+            "Sure!\n[get_weather(city=\"SF\", days=)]",
+            35,
+            (2, 30),
+            "Unexpected ')' at bytes 35..36, expected a value (in the scan for lists and python blocks)",
+        ),
+        (
+            r#"[get_weather(city="SF" days=3)]"#,
+            23,
+            (1, 24),
+            "Unexpected 'days' at bytes 23..27, expected ',' or ')' (in the scan for lists and python blocks)",
+        ),
+        (
+            r#"Let me check. [f(x=1), g(y="unterminated)]"#,
+            27,
+            (1, 28),
+            "Unterminated string at bytes 27..42 (in the scan for lists and python blocks)",
+        ),
+        (
+            "<|python_start|>[f(x=[1, 2)]<|python_end|>",
+            21,
+            (1, 22),
+            "Unexpected '[' at bytes 21..22, expected a value (in the scan for lists and python blocks)",
+        ),
+        // A bare call with no enclosing list
+        (
+            "get_weather(city=)",
+            17,
+            (1, 18),
+            "Unexpected ')' at bytes 17..18, expected a value (in the fallback parse of the whole \
+             input)",
+        ),
+    ];
+
+    for (input, offset, line_col, message) in cases {
+        let err = parse_python_with_nom(input).unwrap_err();
+        assert_eq!(err.span().map(|span| span.start), Some(offset), "{}", input);
+        assert_eq!(err.line_col(input), Some(line_col), "{}", input);
+        assert_eq!(err.to_string(), message);
+    }
+}
+
+#[test]
+fn test_nom_errors_come_from_the_deepest_strategy() {
+    // Of the candidates the scan gave up on, the one it got furthest into
+    let source = r#"Try [foo(x=1 y)] or [bar(z=)]"#;
+    assert_eq!(
+        parse_python_with_nom(source),
+        Err(ParseError::StrategyFailed {
+            strategy: ParseStrategy::Scan,
+            error: Box::new(ParseError::UnexpectedToken {
+                found: "')'".to_string(),
+                expected: "a value".to_string(),
+                span: 27..28,
+            }),
+        })
+    );
+
+    // The list in the string is a candidate, but the fallback gets further
+    let source = r#"get_weather(opts={"k": "[f(y=)]"}, days=)"#;
+    let err = parse_python_with_nom(source).unwrap_err();
+    assert!(matches!(
+        err,
+        ParseError::StrategyFailed {
+            strategy: ParseStrategy::Fallback,
+            ..
+        }
+    ));
+    assert_eq!(err.span(), Some(40..41));
+
+    // A scan that finds nothing isn't blamed on the fallback failing at the first word
+    assert_eq!(
+        parse_python_with_nom("Sure! [x y] is not a call (nor is this)"),
+        Err(ParseError::NoToolCallsFound)
+    );
+}
+
+#[test]
+fn test_strict_errors_carry_spans() {
+    let config = ParserConfig::default().with_strict(true);
+
+    let err = parse_python_with_nom_config("[ping()] extra", &config).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::TrailingInput {
+            text: "extra".to_string(),
+            span: 9..14,
+        }
+    );
+
+    let config = config.with_allowed_names(vec!["ping".to_string()]);
+    let err = parse_python_with_nom_config("[pong()]", &config).unwrap_err();
+    assert_eq!(err.to_string(), "Function not allowed: pong");
+    assert_eq!(err.span(), None);
+
+    // Usable wherever a boxed error is expected
+    let boxed: Box<dyn std::error::Error> = Box::new(err);
+    assert_eq!(boxed.to_string(), "Function not allowed: pong");
+}
+
+#[test]
+fn test_diagnostics_for_recovered_input() {
+    let source = r#"[get_weather(city="SF", days=, units="C" lang="en"), ping(x=[1, =]]"#;
+
+    let (function_calls, diagnostics) = parse_python_with_diagnostics(source).unwrap();
+
+    // The calls are recovered just as `parse_python` recovers them
+    assert_eq!(function_calls, parse_python(source).unwrap());
+    assert_eq!(function_calls[0].kwargs["days"], Value::Empty);
+    assert_eq!(function_calls[0].get_str("lang"), Some("en"));
+    let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "empty argument value for 'days' at 29..30",
+            "missing ',' before 'lang' at 41..45",
+            "skipped unexpected token '=' at 64..65",
+            "mismatched delimiter: expected ')', found ']' at 66..67",
+            "unclosed call 'ping' at 67..67",
+        ]
+    );
+    assert_eq!(&source[diagnostics[1].span.clone()], "lang");
+    assert_eq!(diagnostics[1].snippet, "lang");
+}
+
+#[test]
+fn test_mismatched_delimiters() {
+    let strict = ParserConfig::default().with_strict(true);
+
+    for (source, expected, found, span) in [
+        ("[foo(a=1]", ")", "]", 8..9),
+        ("[foo(a=1))", "]", ")", 9..10),
+        ("[foo(a=1 [bar()]", ")", "[", 9..10),
+        (
+            "<|python_start|>[foo(a=1<|python_end|>",
+            ")",
+            "<|python_end|>",
+            24..38,
+        ),
+    ] {
+        let err = ParseError::MismatchedDelimiter {
+            expected: expected.to_string(),
+            found: found.to_string(),
+            span: span.clone(),
+        };
+        assert_eq!(parse_python_strict(source), Err(err.clone()), "{}", source);
+        assert_eq!(
+            parse_python_with_config(source, &strict),
+            Err(err),
+            "{}",
+            source
+        );
+
+        // Lenient parsing still recovers the call, and says why
+        let (function_calls, diagnostics) = parse_python_with_diagnostics(source).unwrap();
+        assert_eq!(function_calls[0].kwargs["a"], Value::Number(1.0));
+        assert_eq!(
+            diagnostics[0].message,
+            format!(
+                "mismatched delimiter: expected '{}', found '{}'",
+                expected, found
+            )
+        );
+        assert_eq!(diagnostics[0].span, span);
+    }
+
+    assert_eq!(
+        parse_python_strict("[foo(a=1]").unwrap_err().to_string(),
+        "Mismatched delimiter at bytes 8..9: expected ')', found ']'"
+    );
+}
+
+#[test]
+fn test_skipped_candidates_are_diagnosed() {
+    let source = r#"Try [foo(x=1 y)] or [bar(z="a")] <|python_start|>[baz(q=]<|python_end|>"#;
+
+    let (function_calls, diagnostics) = parse_python_with_nom_with_diagnostics(source).unwrap();
+
+    assert_eq!(function_calls, parse_python_with_nom(source).unwrap());
+    assert_eq!(function_calls[0].name, "bar");
+    let skipped: Vec<&str> = diagnostics.iter().map(|d| d.snippet.as_str()).collect();
+    // The marker is a candidate, and so is the `[` inside it once it fails
+    assert_eq!(
+        skipped,
+        vec!["[foo(x=1 y", "<|python_start|>[baz(q=]", "[baz(q=]"]
+    );
+    assert_eq!(&source[diagnostics[0].span.clone()], diagnostics[0].snippet);
+    assert_eq!(
+        diagnostics[0].message,
+        "skipped candidate that failed to parse: Unexpected 'y' at bytes 13..14, \
+         expected ',' or ')'"
+    );
+}
+
+#[test]
+fn test_lexer_errors_are_diagnosed() {
+    let source = r#"[get_weather(city="SF", units=@), ping(x=[1, $]), note(text="hi)]"#;
+
+    let (function_calls, diagnostics) = parse_python_with_diagnostics(source).unwrap();
+
+    let names: Vec<&str> = function_calls.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["get_weather", "ping", "note"]);
+    assert_eq!(function_calls[0].kwargs["units"], Value::Empty);
+    assert_eq!(
+        function_calls[1].kwargs["x"],
+        Value::List(vec![Value::Number(1.0)])
+    );
+    let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+    assert_eq!(
+        messages[..4],
+        [
+            "unrecognised input '@' at 30..31",
+            "empty argument value for 'units' at 30..31",
+            "unrecognised input '$' at 45..46",
+            "unterminated string at 60..61",
+        ]
+    );
+
+    // Strict parsing fails with the first of them instead
+    let err = parse_python_strict(source).unwrap_err();
+    assert_eq!(
+        err,
+        ParseError::Recovered {
+            message: "unrecognised input '@'".to_string(),
+            span: 30..31,
+        }
+    );
+    assert_eq!(err.to_string(), "unrecognised input '@' at bytes 30..31");
+    assert_eq!(
+        parse_python_strict(r#"[get_weather(city="SF", days=)]"#).unwrap_err(),
+        ParseError::Recovered {
+            message: "empty argument value for 'days'".to_string(),
+            span: 29..30,
+        }
+    );
+}
+
+#[test]
+fn test_no_diagnostics_for_well_formed_input() {
+    let source = r#"Sure! [see the docs] [get_weather(city="SF", tags=["a"], opts={"k": (1,)})]"#;
+
+    let (function_calls, diagnostics) = parse_python_with_diagnostics(source).unwrap();
+
+    assert_eq!(function_calls.len(), 1);
+    assert_eq!(diagnostics, vec![]);
+    let (function_calls, diagnostics) = parse_python_with_nom_with_diagnostics(source).unwrap();
+    assert_eq!(function_calls.len(), 1);
+    assert_eq!(diagnostics, vec![]);
+    // Input the lexer can't read outside of a call list is prose, not a problem
+    assert_eq!(
+        parse_python_strict("Sure, here's $5 worth: [a(x=1)]").unwrap(),
+        parse_python("[a(x=1)]").unwrap()
+    );
+}
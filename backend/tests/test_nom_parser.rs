@@ -1,15 +1,20 @@
-use backend::{FunctionCall, NomParserState, Value, parse_incremental, parse_python_with_nom};
-use std::collections::HashMap;
+use std::borrow::Cow;
+
+use backend::testing::bytes_scanned;
+use backend::{
+    FunctionCall, NomParserState, ParseEvent, ParserConfig, ParserEvents, ToolCallEvent, Value,
+    ValueRef, iter_tool_calls, parse_incremental, parse_incremental_delta,
+    parse_incremental_events, parse_incremental_with, parse_kwargs_only, parse_python_all,
+    parse_python_borrowed, parse_python_for_each, parse_python_with_nom,
+    parse_python_with_nom_config, parse_python_with_nom_filtered, parse_python_with_nom_raw,
+    parse_python_with_nom_remainder,
+};
 
 fn create_function_call(name: &str, args: Vec<(&str, Value)>) -> FunctionCall {
-    let mut kwargs = HashMap::new();
-    for (k, v) in args {
-        kwargs.insert(k.to_string(), v);
-    }
-    FunctionCall {
-        name: name.to_string(),
-        kwargs,
-    }
+    args.into_iter()
+        .fold(FunctionCall::new(name), |call, (k, v)| {
+            call.with_kwarg(k, v)
+        })
 }
 
 #[test]
@@ -97,6 +102,83 @@ fn test_incremental_multiple_functions() {
     assert_eq!(result, expected);
 }
 
+#[test]
+fn test_incremental_delta() {
+    let mut state = NomParserState::new();
+
+    assert_eq!(
+        parse_incremental_delta(&mut state, "[test_function("),
+        Ok(vec![])
+    );
+    assert_eq!(
+        parse_incremental_delta(&mut state, "arg1=\"value1\", "),
+        Ok(vec![])
+    );
+
+    let expected = vec![create_function_call(
+        "test_function",
+        vec![
+            ("arg1", Value::String("value1".to_string())),
+            ("arg2", Value::Number(42.0)),
+        ],
+    )];
+    assert_eq!(
+        parse_incremental_delta(&mut state, "arg2=42)]"),
+        Ok(expected.clone())
+    );
+    assert_eq!(state.get_parsed_functions(), expected);
+}
+
+#[test]
+fn test_incremental_delta_multiple_functions() {
+    let mut state = NomParserState::new();
+
+    assert_eq!(
+        parse_incremental_delta(&mut state, "[func1(arg=\"val1\"), func2("),
+        Ok(vec![])
+    );
+
+    let expected = vec![
+        create_function_call("func1", vec![("arg", Value::String("val1".to_string()))]),
+        create_function_call("func2", vec![("arg", Value::Number(42.0))]),
+    ];
+    assert_eq!(
+        parse_incremental_delta(&mut state, "arg=42)]"),
+        Ok(expected.clone())
+    );
+    assert_eq!(state.get_parsed_functions(), expected);
+}
+
+#[test]
+fn test_incremental_delta_repeated_call() {
+    let mut state = NomParserState::new();
+    let ping = vec![create_function_call("ping", vec![])];
+
+    assert_eq!(
+        parse_incremental_delta(&mut state, "[ping()]"),
+        Ok(ping.clone())
+    );
+    assert_eq!(parse_incremental_delta(&mut state, " then "), Ok(vec![]));
+    assert_eq!(parse_incremental_delta(&mut state, "[ping("), Ok(vec![]));
+    assert_eq!(parse_incremental_delta(&mut state, ")]"), Ok(ping.clone()));
+    assert_eq!(state.get_parsed_functions().len(), 2);
+}
+
+#[test]
+fn test_incremental_is_complete() {
+    let mut state = NomParserState::new();
+    assert!(state.is_complete());
+
+    let _ = parse_incremental(&mut state, "I'll check: [foo(");
+    assert!(!state.is_complete());
+
+    let _ = parse_incremental(&mut state, r#"note="a ) in text", tags=["x""#);
+    assert!(!state.is_complete());
+
+    let _ = parse_incremental(&mut state, "])]");
+    assert!(state.is_complete());
+}
+
 #[test]
 fn test_boolean_values() {
     let input = r#"[test_function(flag1=True, flag2=False)]"#;
@@ -109,3 +191,1070 @@ fn test_boolean_values() {
     let result = parse_python_with_nom(input).unwrap();
     assert_eq!(result, expected);
 }
+
+#[test]
+fn test_trailing_text_remainder() {
+    let input = r#"[foo(a=1)] Now I'll explain..."#;
+
+    let (calls, rest) = parse_python_with_nom_remainder(input).unwrap();
+
+    assert_eq!(
+        calls,
+        vec![create_function_call("foo", vec![("a", Value::Number(1.0))])]
+    );
+    assert_eq!(rest, " Now I'll explain...");
+}
+
+#[test]
+fn test_trailing_text_remainder_after_last_block() {
+    let input =
+        r#"Sure. <|python_start|>[foo()]<|python_end|> then [bar(b=True)]<|python_end|> Done."#;
+
+    let (calls, rest) = parse_python_with_nom_remainder(input).unwrap();
+
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[1].name, "bar");
+    assert_eq!(rest, "<|python_end|> Done.");
+}
+
+#[test]
+fn test_trailing_text_remainder_after_imports() {
+    let input = "import os\nfrom json import dumps\nf(a=1) Done.";
+
+    let (calls, rest) = parse_python_with_nom_remainder(input).unwrap();
+
+    assert_eq!(calls, parse_python_with_nom(input).unwrap());
+    assert_eq!(calls[0].kwargs["a"], Value::Number(1.0));
+    assert_eq!(rest, " Done.");
+}
+
+#[test]
+fn test_multiple_python_blocks() {
+    let input = r#"<|python_start|>[func1(a=1)]<|python_end|> and <|python_start|>[func2(b="x"), func3()]<|python_end|>"#;
+
+    let expected = vec![
+        create_function_call("func1", vec![("a", Value::Number(1.0))]),
+        create_function_call("func2", vec![("b", Value::String("x".to_string()))]),
+        create_function_call("func3", vec![]),
+    ];
+
+    let result = parse_python_with_nom(input).unwrap();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_adjacent_python_blocks() {
+    let inputs = [
+        "<|python_start|>[a()]<|python_end|><|python_start|>[b()]<|python_end|>",
+        "<|python_start|>\n[a()]\n<|python_end|>\n<|python_start|> [b()] <|python_end|>",
+    ];
+
+    for input in inputs {
+        let result = parse_python_with_nom(input).unwrap();
+        let names: Vec<&str> = result.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "b"], "input: {:?}", input);
+    }
+}
+
+#[test]
+fn test_parse_python_all_records_each_attempt() {
+    let input = r#"Calling: [func1(a=1), func2(b=), func3(c="x")] done"#;
+
+    let results = parse_python_all(input);
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(
+        results[0],
+        Ok(create_function_call(
+            "func1",
+            vec![("a", Value::Number(1.0))]
+        ))
+    );
+    let err = results[1].clone().unwrap_err();
+    assert_eq!(&input[err.span().unwrap()], "func2(b=)");
+    assert!(err.to_string().contains("func2(b=)"));
+    assert_eq!(
+        results[2],
+        Ok(create_function_call(
+            "func3",
+            vec![("c", Value::String("x".to_string()))]
+        ))
+    );
+}
+
+#[test]
+fn test_parse_python_all_skips_prose_brackets() {
+    let input = r#"See [the docs](http://x) and <|python_start|>[f(), g(x=[1, 2)]<|python_end|> then [h()]"#;
+
+    let results = parse_python_all(input);
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0], Ok(create_function_call("f", vec![])));
+    assert!(results[1].is_err());
+    assert_eq!(results[2], Ok(create_function_call("h", vec![])));
+}
+
+#[test]
+fn test_iter_tool_calls_mixed_document() {
+    let input = concat!(
+        "log line 1 [INFO] started\n",
+        r#"<|python_start|>[get_weather(city="SF"), broken(x=), ping()]<|python_end|>"#,
+        "\nlog line 2 says [nothing here]\n",
+        r#"[search(q="a, b"), lookup(id=)]"#,
+    );
+
+    let mut calls = iter_tool_calls(input);
+    assert_eq!(
+        calls.next(),
+        Some(Ok(create_function_call(
+            "get_weather",
+            vec![("city", Value::String("SF".to_string()))]
+        )))
+    );
+    // Scanning stops at the call just yielded, so it can be picked up again later
+    let offset = calls.offset();
+    assert!(input[..offset].ends_with(r#"get_weather(city="SF")"#));
+
+    let err = calls.next().unwrap().unwrap_err();
+    assert_eq!(&input[err.span().unwrap()], "broken(x=)");
+    assert!(err.to_string().contains("broken(x=)"));
+    assert_eq!(calls.next(), Some(Ok(create_function_call("ping", vec![]))));
+    assert_eq!(
+        calls.next(),
+        Some(Ok(create_function_call(
+            "search",
+            vec![("q", Value::String("a, b".to_string()))]
+        )))
+    );
+    let span = calls.next().unwrap().unwrap_err().span().unwrap();
+    assert_eq!(&input[span], "lookup(id=)");
+    assert_eq!(calls.next(), None);
+    assert_eq!(calls.offset(), input.len());
+}
+
+#[test]
+fn test_parse_python_for_each() {
+    let input = r#"Checking. [get_weather(city="SF"), ping()] then [search(q="a")]"#;
+
+    let mut names = Vec::new();
+    parse_python_for_each(input, |call| names.push(call.name)).unwrap();
+    assert_eq!(names, vec!["get_weather", "ping", "search"]);
+
+    // Calls before a malformed one have been seen by the time it fails
+    let mut names = Vec::new();
+    let err = parse_python_for_each("[a(), b(x=), c()]", |call| names.push(call.name));
+    assert_eq!(names, vec!["a"]);
+    assert!(err.is_err());
+}
+
+#[test]
+fn test_iter_tool_calls_skips_kwarg_lists() {
+    // The list inside a call that failed to parse is not a function list of its own
+    let results: Vec<_> = iter_tool_calls("[a(x=[b()]), c()] [d()]").collect();
+
+    assert_eq!(results.len(), 3);
+    assert!(results[0].is_err());
+    assert_eq!(results[1], Ok(create_function_call("c", vec![])));
+    assert_eq!(results[2], Ok(create_function_call("d", vec![])));
+}
+
+#[test]
+fn test_custom_markers_with_prefix_bracket() {
+    // Without an end marker, the start marker must not be mistaken for a list
+    let config = ParserConfig::new("[TOOL_CALLS]", "");
+    let input = r#"[TOOL_CALLS] [get_weather(city="SF")]"#;
+
+    let result = parse_python_with_nom_config(input, &config).unwrap();
+
+    assert_eq!(
+        result,
+        vec![create_function_call(
+            "get_weather",
+            vec![("city", Value::String("SF".to_string()))]
+        )]
+    );
+}
+
+#[test]
+fn test_bracketed_prose_before_call() {
+    let input = r#"I found [several options] for you: [get_weather(city="SF")]"#;
+
+    let result = parse_python_with_nom(input).unwrap();
+
+    assert_eq!(
+        result,
+        vec![create_function_call(
+            "get_weather",
+            vec![("city", Value::String("SF".to_string()))]
+        )]
+    );
+}
+
+#[test]
+fn test_markdown_links_around_calls() {
+    let input = r#"See [the docs](https://example.com/docs) first.
+[search(query="rust")]
+Then read [the guide](https://example.com/guide) and [the FAQ](https://example.com/faq).
+<|python_start|>[lookup(id=7)]<|python_end|>
+More at [home](https://example.com)."#;
+
+    let result = parse_python_with_nom(input).unwrap();
+
+    assert_eq!(
+        result,
+        vec![
+            create_function_call("search", vec![("query", Value::String("rust".to_string()))]),
+            create_function_call("lookup", vec![("id", Value::Number(7.0))]),
+        ]
+    );
+}
+
+#[test]
+fn test_brackets_inside_string_literal_are_not_calls() {
+    // The first call is truncated; the list inside its string argument must not be
+    // picked up as a call of its own
+    let input = r#"[note(text="try [ping(host=1)] later" [echo(msg="ok")]"#;
+
+    let result = parse_python_with_nom(input).unwrap();
+
+    assert_eq!(
+        result,
+        vec![create_function_call(
+            "echo",
+            vec![("msg", Value::String("ok".to_string()))]
+        )]
+    );
+}
+
+#[test]
+fn test_empty_strings() {
+    let input = r#"[set_note(text="", title='', padding="   ", tags=["", "x"])]"#;
+
+    let expected = vec![create_function_call(
+        "set_note",
+        vec![
+            ("text", Value::String("".to_string())),
+            ("title", Value::String("".to_string())),
+            ("padding", Value::String("   ".to_string())),
+            (
+                "tags",
+                Value::List(vec![
+                    Value::String("".to_string()),
+                    Value::String("x".to_string()),
+                ]),
+            ),
+        ],
+    )];
+
+    let result = parse_python_with_nom(input).unwrap();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_string_escapes() {
+    let input = r#"[echo(text="line\nnext \"quoted\" \\ done", other='it\'s')]"#;
+
+    let expected = vec![create_function_call(
+        "echo",
+        vec![
+            (
+                "text",
+                Value::String("line\nnext \"quoted\" \\ done".to_string()),
+            ),
+            ("other", Value::String("it's".to_string())),
+        ],
+    )];
+
+    let result = parse_python_with_nom(input).unwrap();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_parse_kwargs_only() {
+    let bare = parse_kwargs_only(r#"city="Tokyo", days=7"#).unwrap();
+    assert_eq!(bare.len(), 2);
+    assert_eq!(bare.get("city"), Some(&Value::String("Tokyo".to_string())));
+    assert_eq!(bare.get("days"), Some(&Value::Number(7.0)));
+
+    let wrapped = parse_kwargs_only(r#" (city="Tokyo") "#).unwrap();
+    assert_eq!(wrapped.len(), 1);
+    assert_eq!(
+        wrapped.get("city"),
+        Some(&Value::String("Tokyo".to_string()))
+    );
+
+    assert!(parse_kwargs_only("()").unwrap().is_empty());
+    assert!(parse_kwargs_only("").unwrap().is_empty());
+}
+
+#[test]
+fn test_parse_kwargs_only_rejects_invalid_input() {
+    assert!(parse_kwargs_only(r#"city="Tokyo" days=7"#).is_err());
+    assert!(parse_kwargs_only(r#"get_weather(city="Tokyo")"#).is_err());
+}
+
+#[test]
+fn test_strict_mode_rejects_trailing_garbage() {
+    let strict = ParserConfig::default().with_strict(true);
+    let lenient = ParserConfig::default();
+    let input = "[foo()] xyz";
+
+    let err = parse_python_with_nom_config(input, &strict).unwrap_err();
+    assert!(
+        err.to_string().contains("Trailing garbage at 8..11: xyz"),
+        "{}",
+        err
+    );
+
+    assert_eq!(
+        parse_python_with_nom_config(input, &lenient),
+        Ok(vec![create_function_call("foo", vec![])])
+    );
+}
+
+#[test]
+fn test_strict_mode_accepts_clean_input() {
+    let strict = ParserConfig::default().with_strict(true);
+
+    for input in [
+        "[foo()]",
+        "  [foo()]\n",
+        "<|python_start|>[foo()]<|python_end|>",
+        "<|python_start|>[foo()]<|python_end|>\n<|python_start|>[bar()]<|python_end|>",
+    ] {
+        assert!(
+            parse_python_with_nom_config(input, &strict).is_ok(),
+            "{:?}",
+            input
+        );
+    }
+
+    assert!(parse_python_with_nom_config("Sure: [foo()]", &strict).is_err());
+}
+
+fn stream_bytes(input: &str) -> (NomParserState, Vec<FunctionCall>) {
+    let mut state = NomParserState::new();
+    let mut calls = Vec::new();
+    let mut buf = [0u8; 4];
+    for c in input.chars() {
+        calls.extend(parse_incremental_delta(&mut state, c.encode_utf8(&mut buf)).unwrap());
+    }
+    (state, calls)
+}
+
+#[test]
+fn test_incremental_single_char_chunks_match_batch() {
+    let inputs = [
+        r#"[get_weather(city="SF", days=7), get_time(tz='PST')]"#,
+        r#"See [the docs](https://example.com) then [search(q="a [b] c", tags=["x", "y"])]"#,
+        "<|python_start|>[a(x={'k': [1, 2]}), b(y=None)]<|python_end|>\n<|python_start|>[c()]<|python_end|>",
+        r#"I found [several options]: [book(hotel="Ritz", nights=2)] — enjoy! [ping()]"#,
+        "[bad(x=1 y=2)] [good(z=3)]",
+        "[]",
+    ];
+
+    for input in inputs {
+        let (state, calls) = stream_bytes(input);
+        let batch = parse_python_with_nom(input).unwrap();
+
+        assert_eq!(calls, batch, "{:?}", input);
+        assert_eq!(state.get_parsed_functions(), batch, "{:?}", input);
+    }
+}
+
+#[test]
+fn test_incremental_drains_consumed_input() {
+    let mut state = NomParserState::new();
+
+    let calls =
+        parse_incremental_delta(&mut state, r#"Sure. [a(x="long value")] then [b("#).unwrap();
+
+    assert_eq!(calls.len(), 1);
+    assert_eq!(state.remainder, "[b(");
+    assert_eq!(
+        state.current_function.as_ref().map(|f| f.name.as_str()),
+        Some("b")
+    );
+
+    parse_incremental_delta(&mut state, ")]").unwrap();
+    assert!(state.remainder.is_empty());
+    assert!(state.current_function.is_none());
+}
+
+#[test]
+fn test_incremental_streaming_is_linear() {
+    let calls: Vec<String> = (0..400)
+        .map(|i| format!(r#"search(query="item {i}", limit={i}, exact=True)"#))
+        .collect();
+    let input = format!("<|python_start|>[{}]<|python_end|>", calls.join(", "));
+
+    let (state, _) = stream_bytes(&input);
+
+    assert_eq!(state.parsed_functions.len(), 400);
+    // Each element is scanned once as it arrives, however many chunks it takes; rescanning
+    // the buffer would read it again with every byte
+    assert!(
+        bytes_scanned(&state) <= 2 * input.len(),
+        "scanned {} bytes",
+        bytes_scanned(&state)
+    );
+}
+
+#[test]
+fn test_incremental_long_call_is_linear() {
+    let len = 10 * 1024 * 1024;
+    let input = format!(
+        r#"<|python_start|>[write_file(path="a.txt", content="{}", append=False)]<|python_end|>"#,
+        "x".repeat(len)
+    );
+
+    let mut state = NomParserState::new();
+    state.set_emit_events(true);
+    for chunk in input.as_bytes().chunks(1024) {
+        parse_incremental(&mut state, std::str::from_utf8(chunk).unwrap()).unwrap();
+    }
+
+    assert_eq!(state.parsed_functions.len(), 1);
+    assert_eq!(
+        state.parsed_functions[0].kwargs["content"],
+        Value::String("x".repeat(len))
+    );
+    // The element and the kwarg being parsed are each scanned once as the chunks arrive,
+    // events or not, rather than again from their start with every chunk
+    assert!(
+        bytes_scanned(&state) <= 2 * input.len(),
+        "scanned {} bytes",
+        bytes_scanned(&state)
+    );
+}
+
+#[test]
+fn test_bare_function_call() {
+    let expected = vec![create_function_call(
+        "get_weather",
+        vec![("city", Value::String("NYC".to_string()))],
+    )];
+
+    assert_eq!(
+        parse_python_with_nom(r#"get_weather(city="NYC")"#),
+        Ok(expected.clone())
+    );
+    assert_eq!(
+        parse_python_with_nom("\n  get_weather(city=\"NYC\")\n"),
+        Ok(expected.clone())
+    );
+
+    let strict = ParserConfig::default().with_strict(true);
+    assert_eq!(
+        parse_python_with_nom_config(r#"get_weather(city="NYC")"#, &strict),
+        Ok(expected)
+    );
+}
+
+#[test]
+fn test_bare_call_does_not_shadow_lists() {
+    let input = r#"[get_weather(city="NYC"), get_time(tz="EST")]"#;
+
+    let result = parse_python_with_nom(input).unwrap();
+
+    assert_eq!(result.len(), 2);
+    assert!(parse_python_with_nom("just some prose (with parens)").is_err());
+}
+
+#[test]
+fn test_incremental_drops_prose() {
+    let mut state = NomParserState::new();
+
+    for _ in 0..1000 {
+        parse_incremental_delta(&mut state, "Nothing to call here, just prose. ").unwrap();
+    }
+    assert!(state.remainder.is_empty());
+
+    // A trailing `[name` might still become a call, so it is kept
+    parse_incremental_delta(&mut state, "More text [get_wea").unwrap();
+    assert_eq!(state.remainder, "[get_wea");
+
+    let calls = parse_incremental_delta(&mut state, "ther()]").unwrap();
+    assert_eq!(calls, vec![create_function_call("get_weather", vec![])]);
+}
+
+#[test]
+fn test_incremental_buffer_overflow() {
+    let mut state = NomParserState::with_max_buffer_size(64);
+
+    parse_incremental_delta(&mut state, &"prose ".repeat(100)).unwrap();
+    parse_incremental_delta(&mut state, r#"[write(text=""#).unwrap();
+
+    let err = parse_incremental_delta(&mut state, &"x".repeat(100)).unwrap_err();
+    assert!(err.to_string().contains("Buffer overflow"), "{}", err);
+    assert!(state.remainder.is_empty());
+
+    // The state is usable again after an overflow
+    let calls = parse_incremental_delta(&mut state, "[ping()]").unwrap();
+    assert_eq!(calls, vec![create_function_call("ping", vec![])]);
+
+    state.set_max_buffer_size(None);
+    parse_incremental_delta(&mut state, &format!("[write(text=\"{}", "x".repeat(100))).unwrap();
+}
+
+fn finish_after(chunks: &[&str]) -> (Vec<FunctionCall>, bool) {
+    let mut state = NomParserState::new();
+    for chunk in chunks {
+        parse_incremental_delta(&mut state, chunk).unwrap();
+    }
+    let calls = state.finish().unwrap();
+    (calls, state.truncated)
+}
+
+#[test]
+fn test_finish_truncated_in_string() {
+    let (calls, truncated) = finish_after(&["[get_weather(city=\"S", "F"]);
+
+    assert_eq!(
+        calls,
+        vec![create_function_call(
+            "get_weather",
+            vec![("city", Value::String("SF".to_string()))]
+        )]
+    );
+    assert!(truncated);
+}
+
+#[test]
+fn test_finish_truncated_after_comma() {
+    let (calls, truncated) = finish_after(&["[a(), get_weather(city=\"SF\", "]);
+    assert_eq!(
+        calls,
+        vec![
+            create_function_call("a", vec![]),
+            create_function_call(
+                "get_weather",
+                vec![("city", Value::String("SF".to_string()))]
+            ),
+        ]
+    );
+    assert!(truncated);
+
+    // A kwarg missing its value is dropped, keeping the closed ones
+    let (calls, _) = finish_after(&["[get_weather(city=\"SF\", days="]);
+    assert_eq!(
+        calls,
+        vec![create_function_call(
+            "get_weather",
+            vec![("city", Value::String("SF".to_string()))]
+        )]
+    );
+}
+
+#[test]
+fn test_finish_truncated_before_paren() {
+    let (calls, truncated) = finish_after(&["Calling [get_weather"]);
+
+    assert_eq!(calls, vec![create_function_call("get_weather", vec![])]);
+    assert!(truncated);
+}
+
+#[test]
+fn test_finish_unclosed_list() {
+    // Complete calls waiting for `]` are kept without being marked truncated
+    let (calls, truncated) = finish_after(&["[a(x=1), b(y=[1, 2])"]);
+    assert_eq!(calls.len(), 2);
+    assert!(!truncated);
+
+    let (calls, truncated) = finish_after(&["[a()] and prose"]);
+    assert_eq!(calls, vec![create_function_call("a", vec![])]);
+    assert!(!truncated);
+}
+
+#[test]
+fn test_allowed_names() {
+    let source = "[get_weather(), evil_call()]";
+    let allowed = vec!["get_weather".to_string()];
+
+    let calls = parse_python_with_nom_filtered(source, &allowed).unwrap();
+    assert_eq!(calls, vec![create_function_call("get_weather", vec![])]);
+
+    let strict = ParserConfig::default()
+        .with_allowed_names(allowed)
+        .with_strict(true);
+    let err = parse_python_with_nom_config(source, &strict).unwrap_err();
+    assert!(err.to_string().contains("evil_call"), "{}", err);
+    assert!(parse_python_with_nom_config("[get_weather()]", &strict).is_ok());
+}
+
+#[test]
+fn test_incremental_marker_split_across_chunks() {
+    let mut state = NomParserState::new();
+
+    parse_incremental_delta(&mut state, "<|python_st").unwrap();
+    assert!(!state.is_inside_python_block());
+    parse_incremental_delta(&mut state, "art|>[a()]<|python").unwrap();
+    assert!(state.is_inside_python_block());
+    parse_incremental_delta(&mut state, "_end|>").unwrap();
+    assert!(!state.is_inside_python_block());
+    assert!(!state.has_pending_input());
+    assert!(!state.is_inside_call());
+}
+
+#[test]
+fn test_raw_call_text() {
+    let source = r#"Checking. [get_weather(city="NYC"),  get_time( tz = "EST" )] Done."#;
+
+    let calls = parse_python_with_nom_raw(source, &ParserConfig::default()).unwrap();
+    let raw: Vec<&str> = calls.iter().map(|(_, raw)| *raw).collect();
+
+    assert_eq!(
+        raw,
+        [r#"get_weather(city="NYC")"#, r#"get_time( tz = "EST" )"#]
+    );
+    assert_eq!(calls[0].0.name, "get_weather");
+    assert_eq!(calls[1].0.get_str("tz"), Some("EST"));
+}
+
+#[test]
+fn test_raw_strings() {
+    let calls = parse_python_with_nom(r#"[func(regex=r"\w+@\w+\.\w+", path=R'C:\temp', q=r"\"")]"#)
+        .unwrap();
+
+    assert_eq!(calls[0].get_str("regex"), Some(r"\w+@\w+\.\w+"));
+    assert_eq!(calls[0].get_str("path"), Some(r"C:\temp"));
+    // As in Python, a backslash still keeps the quote from closing the string
+    assert_eq!(calls[0].get_str("q"), Some(r#"\""#));
+}
+
+#[test]
+fn test_raw_string_streaming() {
+    let source = r#"[func(pattern=r"\d+\]\.\d+"), other()]"#;
+    let mut state = NomParserState::new();
+
+    for c in source.chars() {
+        parse_incremental_delta(&mut state, &c.to_string()).unwrap();
+    }
+
+    assert_eq!(
+        state.parsed_functions,
+        parse_python_with_nom(source).unwrap()
+    );
+    assert_eq!(
+        state.parsed_functions[0].get_str("pattern"),
+        Some(r"\d+\]\.\d+")
+    );
+}
+
+#[test]
+fn test_f_strings() {
+    let source = r#"[greet(msg=f"Hello {name}!\n", alt=F'{a} and {{b}}', path=rf"C:\{dir}")]"#;
+
+    let calls = parse_python_with_nom(source).unwrap();
+
+    // Read as plain strings, with the braces kept for the caller to fill in
+    assert_eq!(calls[0].get_str("msg"), Some("Hello {name}!\n"));
+    assert_eq!(calls[0].get_str("alt"), Some("{a} and {{b}}"));
+    assert_eq!(calls[0].get_str("path"), Some(r"C:\{dir}"));
+    assert!(parse_python_with_nom(r#"[greet(msg=bf"hi")]"#).is_err());
+    assert!(parse_python_with_nom(r#"[greet(msg=ff"hi")]"#).is_err());
+
+    let mut state = NomParserState::new();
+    for c in source.chars() {
+        parse_incremental_delta(&mut state, &c.to_string()).unwrap();
+    }
+    assert_eq!(state.parsed_functions, calls);
+}
+
+fn arguments_of(events: &[ToolCallEvent], call_index: usize) -> String {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            ToolCallEvent::ArgumentsDelta {
+                index,
+                json_fragment,
+            } if *index == call_index => Some(json_fragment.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_streaming_events() {
+    let source = r#"[get_weather(city="SF", days=3), ping()]"#;
+    let mut state = NomParserState::new();
+    state.set_emit_events(true);
+
+    let mut events = Vec::new();
+    for c in source.chars() {
+        parse_incremental_delta(&mut state, &c.to_string()).unwrap();
+        events.extend(state.drain_events());
+    }
+
+    assert_eq!(
+        events,
+        vec![
+            ToolCallEvent::CallStarted {
+                index: 0,
+                name: "get_weather".to_string()
+            },
+            ToolCallEvent::ArgumentsDelta {
+                index: 0,
+                json_fragment: r#"{"city": "SF""#.to_string()
+            },
+            ToolCallEvent::ArgumentsDelta {
+                index: 0,
+                json_fragment: r#", "days": 3"#.to_string()
+            },
+            ToolCallEvent::ArgumentsDelta {
+                index: 0,
+                json_fragment: "}".to_string()
+            },
+            ToolCallEvent::CallFinished { index: 0 },
+            ToolCallEvent::CallStarted {
+                index: 1,
+                name: "ping".to_string()
+            },
+            ToolCallEvent::ArgumentsDelta {
+                index: 1,
+                json_fragment: "{}".to_string()
+            },
+            ToolCallEvent::CallFinished { index: 1 },
+        ]
+    );
+}
+
+#[test]
+fn test_streaming_events_arrive_early() {
+    let mut state = NomParserState::new();
+    state.set_emit_events(true);
+
+    parse_incremental_delta(&mut state, "[search(query=").unwrap();
+    assert!(matches!(
+        state.drain_events()[..],
+        [ToolCallEvent::CallStarted { index: 0, .. }]
+    ));
+
+    // A kwarg is sent once the next `,` terminates it, long before the call closes
+    parse_incremental_delta(&mut state, r#""a, b", tags=["x", "y"], "#).unwrap();
+    assert_eq!(
+        arguments_of(&state.drain_events(), 0),
+        r#"{"query": "a, b", "tags": ["x", "y"]"#
+    );
+    assert!(state.parsed_functions.is_empty());
+
+    // Without events enabled nothing is recorded
+    let mut quiet = NomParserState::new();
+    parse_incremental_delta(&mut quiet, "[a(x=1)]").unwrap();
+    assert!(quiet.drain_events().is_empty());
+}
+
+#[test]
+fn test_streaming_events_finish_truncated() {
+    let mut state = NomParserState::new();
+    state.set_emit_events(true);
+
+    parse_incremental_delta(&mut state, r#"[get_weather(city="SF", days="#).unwrap();
+    state.finish().unwrap();
+    let events = state.drain_events();
+
+    assert_eq!(arguments_of(&events, 0), r#"{"city": "SF"}"#);
+    assert_eq!(
+        events.last(),
+        Some(&ToolCallEvent::CallFinished { index: 0 })
+    );
+}
+
+#[test]
+fn test_incremental_current_partial() {
+    let mut state = NomParserState::new();
+    let partial_after = |state: &mut NomParserState, chunk: &str| {
+        parse_incremental_delta(state, chunk).unwrap();
+        state.current_partial().map(|partial| FunctionCall {
+            name: partial.name.clone(),
+            kwargs: partial.kwargs.clone(),
+            span: None,
+        })
+    };
+
+    assert_eq!(partial_after(&mut state, "Sure: [get_wea"), None);
+    assert_eq!(
+        partial_after(&mut state, "ther(city=\"S"),
+        Some(create_function_call("get_weather", vec![]))
+    );
+    assert_eq!(
+        partial_after(&mut state, "F\", days=3"),
+        Some(create_function_call(
+            "get_weather",
+            vec![("city", Value::String("SF".to_string()))]
+        ))
+    );
+    assert_eq!(
+        partial_after(&mut state, ", units=[\"c\""),
+        Some(create_function_call(
+            "get_weather",
+            vec![
+                ("city", Value::String("SF".to_string())),
+                ("days", Value::Number(3.0)),
+            ]
+        ))
+    );
+    assert_eq!(
+        partial_after(&mut state, "])"),
+        Some(create_function_call(
+            "get_weather",
+            vec![
+                ("city", Value::String("SF".to_string())),
+                ("days", Value::Number(3.0)),
+                ("units", Value::List(vec![Value::String("c".to_string())])),
+            ]
+        ))
+    );
+    assert_eq!(partial_after(&mut state, ", pi"), None);
+    assert_eq!(
+        partial_after(&mut state, "ng("),
+        Some(create_function_call("ping", vec![]))
+    );
+    assert_eq!(partial_after(&mut state, ")]"), None);
+    assert_eq!(state.parsed_functions.len(), 2);
+}
+
+#[test]
+fn test_incremental_checkpoint_after_every_chunk() {
+    let cases: [&[&str]; 6] = [
+        &["[test_function(", "arg1=\"value1\", ", "arg2=42)]"],
+        &["[func1(arg=\"val1\"), func2(", "arg=42)]"],
+        &["[ping()]", " then ", "[ping(", ")]"],
+        &[
+            "I'll check: [foo(",
+            r#"note="a ) in text", tags=["x""#,
+            "])]",
+        ],
+        &[
+            "<|python_st",
+            "art|>[a(x={'k': [1, ",
+            "2]}), b(y=No",
+            "ne)]<|python_end|>",
+        ],
+        &["[get_weather(city=\"SF\", days=3), get_ti", "me(tz='PS"],
+    ];
+
+    for chunks in cases {
+        let mut uninterrupted = NomParserState::new();
+        uninterrupted.set_emit_events(true);
+        let mut resumed = uninterrupted.clone();
+
+        for chunk in chunks {
+            let expected = parse_incremental_delta(&mut uninterrupted, chunk);
+            resumed = NomParserState::from_bytes(&resumed.to_bytes()).unwrap();
+            assert_eq!(parse_incremental_delta(&mut resumed, chunk), expected);
+            assert_eq!(resumed.drain_events(), uninterrupted.drain_events());
+        }
+
+        let expected = uninterrupted.finish().unwrap();
+        resumed = NomParserState::from_bytes(&resumed.to_bytes()).unwrap();
+        assert_eq!(resumed.finish().unwrap(), expected, "{:?}", chunks);
+        assert_eq!(resumed.truncated, uninterrupted.truncated);
+    }
+
+    assert!(NomParserState::from_bytes(b"not a state").is_err());
+}
+
+#[test]
+fn test_set_dict_and_empty_braces() {
+    let calls = parse_python_with_nom(
+        r#"[f(tags={"a", "b"}, labels={"a:b",}, mapping={"a": 1, "b": 2,}, empty={ })]"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        calls,
+        vec![create_function_call(
+            "f",
+            vec![
+                (
+                    "tags",
+                    Value::Set(vec![
+                        Value::String("a".to_string()),
+                        Value::String("b".to_string())
+                    ])
+                ),
+                ("labels", Value::Set(vec![Value::String("a:b".to_string())])),
+                (
+                    "mapping",
+                    Value::List(vec![
+                        Value::String("a".to_string()),
+                        Value::Number(1.0),
+                        Value::String("b".to_string()),
+                        Value::Number(2.0),
+                    ])
+                ),
+                ("empty", Value::List(vec![])),
+            ]
+        )]
+    );
+
+    // A trailing comma needs an element before it
+    assert!(parse_python_with_nom("[f(x={,})]").is_err());
+}
+
+#[test]
+fn test_incremental_parse_events() {
+    let chunks = [
+        "Checking: [get_wea",
+        r#"ther(city="SF", da"#,
+        "ys=3), ping()",
+        "]",
+    ];
+    let mut state = NomParserState::new();
+
+    let events: Vec<Vec<ParseEvent>> = chunks
+        .iter()
+        .map(|chunk| parse_incremental_events(&mut state, chunk).unwrap())
+        .collect();
+
+    assert_eq!(
+        events,
+        vec![
+            vec![],
+            vec![
+                ParseEvent::FunctionStarted {
+                    name: "get_weather".to_string()
+                },
+                ParseEvent::ArgumentParsed {
+                    name: "get_weather".to_string(),
+                    key: "city".to_string(),
+                    value: Value::String("SF".to_string()),
+                },
+            ],
+            vec![
+                ParseEvent::ArgumentParsed {
+                    name: "get_weather".to_string(),
+                    key: "days".to_string(),
+                    value: Value::Number(3.0),
+                },
+                ParseEvent::FunctionCompleted { index: 0 },
+                ParseEvent::FunctionStarted {
+                    name: "ping".to_string()
+                },
+            ],
+            // A call completes once the `,` or `]` after it arrives
+            vec![ParseEvent::FunctionCompleted { index: 1 }],
+        ]
+    );
+    assert_eq!(state.parsed_functions.len(), 2);
+}
+
+// Records each hook as a line, to check their order
+#[derive(Default)]
+struct Recorder(Vec<String>);
+
+impl ParserEvents for Recorder {
+    fn on_call_start(&mut self, name: &str) {
+        self.0.push(format!("start {}", name));
+    }
+
+    fn on_kwarg(&mut self, name: &str, key: &str, value: &Value) {
+        self.0.push(format!("kwarg {}.{}={}", name, key, value));
+    }
+
+    fn on_call_complete(&mut self, index: usize, function_call: &FunctionCall) {
+        self.0.push(format!("complete {} {}", index, function_call));
+    }
+
+    fn on_block_end(&mut self) {
+        self.0.push("block end".to_string());
+    }
+}
+
+#[test]
+fn test_incremental_event_hooks_order() {
+    let source = concat!(
+        r#"<|python_start|>[get_weather(city="SF", days=3), ping()]<|python_end|>"#,
+        " and then ",
+        r#"<|python_start|>[search(q="a, b")]<|python_end|>"#,
+    );
+    let expected = [
+        "start get_weather",
+        r#"kwarg get_weather.city="SF""#,
+        "kwarg get_weather.days=3",
+        r#"complete 0 get_weather(city="SF", days=3)"#,
+        "start ping",
+        "complete 1 ping()",
+        "block end",
+        "start search",
+        r#"kwarg search.q="a, b""#,
+        r#"complete 2 search(q="a, b")"#,
+        "block end",
+    ];
+
+    // Every split reports each item exactly once and in source order
+    for chunk_len in [1, 3, 7, source.len()] {
+        let mut state = NomParserState::new();
+        let mut recorder = Recorder::default();
+        let chars: Vec<char> = source.chars().collect();
+        for chunk in chars.chunks(chunk_len) {
+            let chunk: String = chunk.iter().collect();
+            parse_incremental_with(&mut state, &chunk, &mut recorder).unwrap();
+        }
+
+        assert_eq!(recorder.0, expected, "chunks of {}", chunk_len);
+        assert_eq!(state.parsed_functions.len(), 3);
+    }
+}
+
+#[test]
+fn test_each_region_parsed_once() {
+    let names = |source: &str| -> Vec<String> {
+        parse_python_with_nom(source)
+            .unwrap()
+            .into_iter()
+            .map(|call| call.name)
+            .collect()
+    };
+
+    assert_eq!(
+        names("<|python_start|>[a(), b()]<|python_end|> [c()]"),
+        ["a", "b", "c"]
+    );
+    assert_eq!(
+        names("<|python_start|>[a()]<|python_end|><|python_start|>[b()]<|python_end|> then [c()]"),
+        ["a", "b", "c"]
+    );
+
+    // A call nested in the arguments of a call that fails to parse is not a call of its own
+    let source = "[a(x=[b(y=1)])] [c()]";
+    assert_eq!(names(source), ["c"]);
+    let (_, streamed) = stream_bytes(source);
+    assert_eq!(streamed, parse_python_with_nom(source).unwrap());
+}
+
+#[test]
+fn test_borrowed_parse_points_into_source() {
+    let source = r#"Sure! [search(query="rust", unit=celsius, note="a\"b", data=b"xy", n=2, q=1)]"#;
+    let function_calls = parse_python_borrowed(source).unwrap();
+
+    let within_source = |slice: &str| source.as_bytes().as_ptr_range().contains(&slice.as_ptr());
+    let call = &function_calls[0];
+    assert_eq!(call.name, "search");
+    assert!(within_source(call.name));
+    for (key, _) in &call.kwargs {
+        assert!(within_source(key), "{}", key);
+    }
+
+    match call.get("query") {
+        Some(ValueRef::String(Cow::Borrowed(query))) => assert!(within_source(query)),
+        other => panic!("expected a borrowed string, got {:?}", other),
+    }
+    match call.get("unit") {
+        Some(ValueRef::Identifier(unit)) => assert!(within_source(unit)),
+        other => panic!("expected an identifier, got {:?}", other),
+    }
+    assert!(matches!(
+        call.get("data"),
+        Some(ValueRef::Bytes(Cow::Borrowed(b"xy")))
+    ));
+    // Escapes have to be resolved into a new string
+    assert_eq!(
+        call.get("note"),
+        Some(&ValueRef::String(Cow::Owned("a\"b".to_string())))
+    );
+
+    let owned: Vec<FunctionCall> = function_calls.iter().map(|call| call.to_owned()).collect();
+    assert_eq!(owned, parse_python_with_nom(source).unwrap());
+}
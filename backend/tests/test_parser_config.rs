@@ -0,0 +1,290 @@
+use backend::nom_parser::NomParserState;
+use backend::{
+    DuplicateKwarg, FunctionCall, MAX_NESTING_DEPTH_LIMIT, ParseError, ParserConfig, Value,
+    parse_incremental_delta, parse_python_with_config, parse_python_with_nom_config,
+};
+
+type Engine = fn(&str, &ParserConfig) -> Result<Vec<FunctionCall>, ParseError>;
+
+const ENGINES: [(&str, Engine); 2] = [
+    ("nom", parse_python_with_nom_config),
+    ("logos", parse_python_with_config),
+];
+
+fn names(function_calls: &[FunctionCall]) -> Vec<&str> {
+    function_calls.iter().map(|f| f.name.as_str()).collect()
+}
+
+#[test]
+fn test_strict_fails_instead_of_recovering() {
+    let strict = ParserConfig::default().with_strict(true);
+
+    for (engine, parse) in ENGINES {
+        let lenient = parse("Sure! [foo()]", &ParserConfig::default()).unwrap();
+        assert_eq!(names(&lenient), vec!["foo"], "{}", engine);
+        assert!(parse("Sure! [foo()]", &strict).is_err(), "{}", engine);
+        assert_eq!(names(&parse("[foo()]", &strict).unwrap()), vec!["foo"]);
+    }
+
+    // The logos engine skips what its lexer can't read, unless strict
+    let source = "[foo(x=1 @)]";
+    assert_eq!(
+        parse_python_with_config(source, &ParserConfig::default()).unwrap()[0].kwargs["x"],
+        Value::Number(1.0)
+    );
+    assert_eq!(
+        parse_python_with_config(source, &strict).unwrap_err(),
+        ParseError::Recovered {
+            message: "unrecognised input '@'".to_string(),
+            span: 9..10,
+        }
+    );
+}
+
+#[test]
+fn test_surrounding_text() {
+    let source = "* [foo()] Anything else?";
+    let calls_only = ParserConfig::default().with_allow_surrounding_text(false);
+
+    for (engine, parse) in ENGINES {
+        let lenient = parse(source, &ParserConfig::default()).unwrap();
+        assert_eq!(names(&lenient), vec!["foo"], "{}", engine);
+
+        assert_eq!(
+            parse(source, &calls_only).unwrap_err(),
+            ParseError::UnexpectedToken {
+                found: "'*'".to_string(),
+                expected: "a function call or list of calls".to_string(),
+                span: 0..1,
+            },
+            "{}",
+            engine
+        );
+        let blocks = "  <|python_start|>[foo()]<|python_end|>\n";
+        assert_eq!(names(&parse(blocks, &calls_only).unwrap()), vec!["foo"]);
+    }
+}
+
+#[test]
+fn test_markers() {
+    let source = "<tool>[foo()]</tool> That's all.";
+    let config = ParserConfig::new("<tool>", "</tool>").with_allow_surrounding_text(false);
+
+    for (engine, parse) in ENGINES {
+        let function_calls = parse(&source[..20], &config).unwrap();
+        assert_eq!(names(&function_calls), vec!["foo"], "{}", engine);
+        assert!(parse(source, &config).is_err(), "{}", engine);
+    }
+}
+
+#[test]
+fn test_max_calls() {
+    let source = "[a(), b(), c()]";
+    let config = ParserConfig::default().with_max_calls(2);
+
+    for (engine, parse) in ENGINES {
+        assert_eq!(
+            names(&parse(source, &ParserConfig::default()).unwrap()),
+            vec!["a", "b", "c"]
+        );
+        assert_eq!(
+            names(&parse(source, &config).unwrap()),
+            vec!["a", "b"],
+            "{}",
+            engine
+        );
+        assert_eq!(
+            parse(source, &config.clone().with_strict(true)).unwrap_err(),
+            ParseError::TooManyCalls { count: 3, limit: 2 },
+            "{}",
+            engine
+        );
+    }
+}
+
+#[test]
+fn test_on_duplicate_kwarg() {
+    let source = "[f(x=1, y=2, x=3)]";
+
+    for (engine, parse) in ENGINES {
+        let value_of_x = |policy| {
+            let config = ParserConfig::default().with_on_duplicate_kwarg(policy);
+            parse(source, &config).map(|function_calls| function_calls[0].kwargs["x"].clone())
+        };
+
+        assert_eq!(
+            value_of_x(DuplicateKwarg::KeepLast),
+            Ok(Value::Number(3.0)),
+            "{}",
+            engine
+        );
+        assert_eq!(
+            value_of_x(DuplicateKwarg::KeepFirst),
+            Ok(Value::Number(1.0)),
+            "{}",
+            engine
+        );
+        assert_eq!(
+            value_of_x(DuplicateKwarg::Error),
+            Err(ParseError::DuplicateKwarg {
+                name: "x".to_string(),
+                span: 13..14,
+            }),
+            "{}",
+            engine
+        );
+    }
+}
+
+#[test]
+fn test_recovery() {
+    let source = r#"get_weather(city="NYC")"#;
+    let no_recovery = ParserConfig::default().with_recovery(false);
+
+    let recovered = parse_python_with_nom_config(source, &ParserConfig::default()).unwrap();
+    assert_eq!(names(&recovered), vec!["get_weather"]);
+    assert_eq!(
+        parse_python_with_nom_config(source, &no_recovery),
+        Err(ParseError::NoToolCallsFound)
+    );
+    // A function list is found by the scan itself
+    let listed = parse_python_with_nom_config(&format!("[{}]", source), &no_recovery).unwrap();
+    assert_eq!(listed, recovered);
+}
+
+#[test]
+fn test_streaming_with_config() {
+    let config = ParserConfig::new("<tool>", "</tool>")
+        .with_allowed_names(vec!["a".to_string(), "b".to_string(), "c".to_string()])
+        .with_max_calls(2)
+        .with_on_duplicate_kwarg(DuplicateKwarg::KeepFirst);
+    let mut state = NomParserState::with_config(config);
+
+    let mut function_calls = Vec::new();
+    for chunk in [
+        "<tool>[a(x=1, x=2), skip()",
+        ", b()]</tool>",
+        "<tool>[c()]</tool>",
+    ] {
+        function_calls.extend(parse_incremental_delta(&mut state, chunk).unwrap());
+    }
+
+    assert_eq!(names(&function_calls), vec!["a", "b"]);
+    assert_eq!(function_calls[0].kwargs["x"], Value::Number(1.0));
+    // The config survives a snapshot
+    let restored = NomParserState::from_bytes(&state.to_bytes()).unwrap();
+    assert_eq!(restored.config, state.config);
+}
+
+#[test]
+fn test_streaming_truncated_call_with_custom_markers() {
+    let config = ParserConfig::new("<tool>", "</tool>");
+    let source = "<tool>[a(x=1</tool> then <tool>[b(y=2)]</tool>";
+    let expected = parse_python_with_nom_config(source, &config).unwrap();
+    assert_eq!(names(&expected), vec!["b"]);
+
+    // The end marker ends the cut-off call, however the stream is split
+    for chunk_len in [1, 5, source.len()] {
+        let mut state = NomParserState::with_config(config.clone());
+        let mut function_calls = Vec::new();
+        for chunk in source.as_bytes().chunks(chunk_len) {
+            let chunk = std::str::from_utf8(chunk).unwrap();
+            function_calls.extend(parse_incremental_delta(&mut state, chunk).unwrap());
+        }
+        assert_eq!(function_calls, expected, "chunk_len {}", chunk_len);
+        assert_eq!(state.finish().unwrap(), expected, "chunk_len {}", chunk_len);
+    }
+}
+
+#[test]
+fn test_max_nesting_depth() {
+    let nested = |depth: usize| format!("[f(x={}1{})]", "[".repeat(depth), "]".repeat(depth));
+
+    for (engine, parse) in ENGINES {
+        let config = ParserConfig::default();
+        assert!(parse(&nested(64), &config).is_ok(), "{}", engine);
+        assert_eq!(
+            parse(&nested(65), &config).unwrap_err(),
+            ParseError::RecursionLimit {
+                limit: 64,
+                span: 70..71,
+            },
+            "{}",
+            engine
+        );
+        // Far too deep to parse recursively without the limit
+        assert!(matches!(
+            parse(&nested(100_000), &config),
+            Err(ParseError::RecursionLimit { .. })
+        ));
+
+        let shallow = config.with_max_nesting_depth(2);
+        assert!(parse(&nested(2), &shallow).is_ok(), "{}", engine);
+        assert!(parse(&nested(3), &shallow).is_err(), "{}", engine);
+    }
+
+    let mut state = NomParserState::new();
+    assert!(matches!(
+        parse_incremental_delta(&mut state, &nested(65)),
+        Err(ParseError::RecursionLimit { limit: 64, .. })
+    ));
+}
+
+#[test]
+fn test_max_nesting_depth_is_clamped() {
+    let nested = |open: &str, close: &str| {
+        format!("[f(x={}1{})]", open.repeat(100_000), close.repeat(100_000))
+    };
+    let inputs = [
+        nested("[", "]"),
+        nested("{'k': ", "}"),
+        // A nested call's kwarg values count as well as the call
+        nested("[g(a=", ")]"),
+    ];
+    let unlimited = ParserConfig::default().with_max_nesting_depth(usize::MAX);
+
+    // Debug builds need more stack than the default test thread's for the deepest parse
+    let parse_all = move || {
+        for (engine, parse) in ENGINES {
+            for input in &inputs {
+                match parse(input, &unlimited) {
+                    Err(ParseError::RecursionLimit { limit, .. }) => {
+                        assert_eq!(limit, MAX_NESTING_DEPTH_LIMIT, "{}", engine)
+                    }
+                    // The nom engine has no nested calls, so fails before nesting deeply
+                    result => assert!(
+                        engine == "nom" && input.starts_with("[f(x=[g("),
+                        "{:?}",
+                        result
+                    ),
+                }
+            }
+        }
+    };
+    std::thread::Builder::new()
+        .stack_size(8 << 20)
+        .spawn(parse_all)
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn test_import_statements_before_calls() {
+    let source = "import json\nfrom typing import Optional\n\n[func(x=1), send(from=\"a\")]";
+    let strict = ParserConfig::default().with_strict(true);
+
+    for (engine, parse) in ENGINES {
+        for config in [ParserConfig::default(), strict.clone()] {
+            let function_calls = parse(source, &config).unwrap();
+            assert_eq!(names(&function_calls), vec!["func", "send"], "{}", engine);
+            assert_eq!(function_calls[0].span, Some(42..51), "{}", engine);
+        }
+        // Other text before the calls is still not allowed
+        assert!(
+            parse("Let me import it:\n[func()]", &strict).is_err(),
+            "{}",
+            engine
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use backend::{parse_python_with_nom, parse_python_with_nom_with_stats, parse_python_with_stats};
+
+fn call_list(n: usize) -> String {
+    let calls: Vec<String> = (0..n)
+        .map(|i| format!(r#"search(query="item {i}", limit={i}, tags=["a", "b"])"#))
+        .collect();
+    format!("<|python_start|>[{}]<|python_end|>", calls.join(", "))
+}
+
+#[test]
+fn test_stats_for_both_engines() {
+    let source = call_list(200);
+
+    let (nom_calls, nom_stats) = parse_python_with_nom_with_stats(&source).unwrap();
+    let (logos_calls, logos_stats) = parse_python_with_stats(&source).unwrap();
+
+    assert_eq!(nom_calls, logos_calls);
+    assert_eq!(nom_stats.engine, "nom");
+    assert_eq!(logos_stats.engine, "logos");
+    for stats in [&nom_stats, &logos_stats] {
+        assert_eq!(stats.function_count, 200);
+        assert!(stats.token_count > 200);
+        assert!(stats.parse_duration_us > 0);
+    }
+    assert_eq!(nom_stats.token_count, logos_stats.token_count);
+}
+
+#[test]
+fn test_stats_match_plain_parse() {
+    let source = r#"[get_weather(location="Tokyo", days=7)]"#;
+
+    let (calls, stats) = parse_python_with_nom_with_stats(source).unwrap();
+
+    assert_eq!(calls, parse_python_with_nom(source).unwrap());
+    assert_eq!(stats.function_count, 1);
+    // [ get_weather ( location = "Tokyo" , days = 7 ) ]
+    assert_eq!(stats.token_count, 12);
+}
@@ -0,0 +1,69 @@
+use backend::{FunctionCall, Value, parse_python_stream};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Write};
+
+const LOG: &str = r#"Checking the weather first.
+<|python_start|>[get_weather(city="SF",
+    days=3), get_time(
+    zone="PST")]<|python_end|>
+Now searching.
+[search(query="multi
+line"), search(query="rust")]
+"#;
+
+#[test]
+fn test_stream_yields_each_call_once_in_order() {
+    let streamed: Vec<FunctionCall> = parse_python_stream(Cursor::new(LOG))
+        .collect::<Result<_, _>>()
+        .unwrap();
+
+    let names: Vec<&str> = streamed.iter().map(|call| call.name.as_str()).collect();
+    assert_eq!(names, ["get_weather", "get_time", "search", "search"]);
+    assert_eq!(streamed[0].kwargs["days"], Value::Number(3.0));
+    assert_eq!(
+        streamed[2].kwargs["query"],
+        Value::String("multi\nline".to_string())
+    );
+    assert_eq!(
+        streamed,
+        backend::nom_parser::parse_python_with_surrounding_text(LOG).unwrap()
+    );
+}
+
+#[test]
+fn test_stream_read_error() {
+    let mut input = b"[ping()]\n".to_vec();
+    input.extend_from_slice(b"\xff\xfe\n[pong()]\n");
+
+    let results: Vec<_> = parse_python_stream(Cursor::new(input.clone())).collect();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].as_ref().unwrap().name, "ping");
+    let message = results[1].as_ref().unwrap_err().to_string();
+    assert!(message.starts_with("Read error at byte 9"), "{}", message);
+
+    // Without fusing, the lines after the error are still parsed
+    let results: Vec<_> = parse_python_stream(Cursor::new(input))
+        .fuse_on_error(false)
+        .collect();
+    assert_eq!(results.len(), 3);
+    assert!(results[1].is_err());
+    assert_eq!(results[2].as_ref().unwrap().name, "pong");
+}
+
+#[test]
+fn test_stream_from_file() {
+    let path = std::env::temp_dir().join(format!("tool_calls_{}.txt", std::process::id()));
+    File::create(&path)
+        .unwrap()
+        .write_all(LOG.as_bytes())
+        .unwrap();
+
+    let reader = BufReader::new(File::open(&path).unwrap());
+    let streamed: Vec<FunctionCall> = parse_python_stream(reader).map(Result::unwrap).collect();
+    std::fs::remove_file(&path).unwrap();
+
+    let from_memory: Vec<FunctionCall> = parse_python_stream(Cursor::new(LOG))
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(streamed, from_memory);
+}
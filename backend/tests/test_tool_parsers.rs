@@ -1,25 +1,23 @@
 use backend::{FunctionCall, parse_python_with_nom, Value, NomParserState, parse_incremental};
-use std::collections::HashMap;
+use backend::count_tool_calls;
+use backend::{IncrementalBackend, LogosParserState, ParseEvent, parse_python};
+use backend::assert_function_calls_eq;
+use backend::testing::MORE_TYPES_FUNCTION_OUTPUT;
+use backend::{parse_python_with_diagnostics, parse_python_with_nom_with_diagnostics};
+use backend::{ParseError, ParserConfig, parse_python_with_config, parse_python_with_nom_config};
 
 fn create_function_call(name: &str, args: Vec<(&str, Value)>) -> FunctionCall {
-    let mut kwargs = HashMap::new();
-    for (k, v) in args {
-        kwargs.insert(k.to_string(), v);
-    }
-    FunctionCall {
-        name: name.to_string(),
-        kwargs,
-    }
+    args.into_iter()
+        .fold(FunctionCall::new(name), |call, (k, v)| call.with_kwarg(k, v))
 }
 
 // Test constants based on the Python test cases
 const SIMPLE_FUNCTION_OUTPUT: &str = "get_weather(city=\"San Francisco\", metric=\"celsius\")";
-const MORE_TYPES_FUNCTION_OUTPUT: &str = "register_user(name=\"John Doe\", age=37, address={'city': 'San Francisco', 'state': 'CA'}, role=None, passed_test=True, aliases=['John', 'Johnny'])";
 const PARAMETERLESS_FUNCTION_OUTPUT: &str = "get_weather()";
 const EMPTY_DICT_FUNCTION_OUTPUT: &str = "do_something_cool(additional_data={})";
 const EMPTY_LIST_FUNCTION_OUTPUT: &str = "do_something_cool(steps=[])";
 // Simplify the escaped string test case
-const ESCAPED_STRING_FUNCTION_OUTPUT: &str = "get_weather(city=\"Martha Vineyard\", metric=\"cool units\")";
+const ESCAPED_STRING_FUNCTION_OUTPUT: &str = r#"get_weather(city="Martha Vineyard", metric="cool units", cache_dir="C:\\Users\\foo\\weather")"#;
 
 // Helper to get the simple function call for tests
 fn get_simple_function_call() -> FunctionCall {
@@ -54,7 +52,7 @@ fn get_more_types_function_call() -> FunctionCall {
             ("name", Value::String("John Doe".to_string())),
             ("age", Value::Number(37.0)),
             ("address", Value::List(address_entries)),
-            ("role", Value::Empty),
+            ("role", Value::Null),
             ("passed_test", Value::Bool(true)),
             ("aliases", aliases),
         ],
@@ -89,6 +87,7 @@ fn get_escaped_string_function_call() -> FunctionCall {
         vec![
             ("city", Value::String("Martha Vineyard".to_string())),
             ("metric", Value::String("cool units".to_string())),
+            ("cache_dir", Value::String(r"C:\Users\foo\weather".to_string())),
         ],
     )
 }
@@ -96,11 +95,31 @@ fn get_escaped_string_function_call() -> FunctionCall {
 #[test]
 fn test_no_tool_call() {
     let model_output = "How can I help you today?";
-    // The parser will return an error for non-matching input, which is expected behavior
+    // Prose without any call is an error, unless no calls are allowed
     let result = parse_python_with_nom(model_output);
-    
-    // Expect an error since this isn't a valid function call syntax
-    assert!(result.is_err());
+    assert_eq!(result, Err(ParseError::NoToolCallsFound));
+
+    let config = ParserConfig::default().with_allow_no_calls(true);
+    assert_eq!(parse_python_with_nom_config(model_output, &config), Ok(vec![]));
+}
+
+#[test]
+fn test_empty_input() {
+    for model_output in ["", "   \n "] {
+        assert_eq!(parse_python_with_nom(model_output), Ok(vec![]));
+        assert_eq!(parse_python(model_output), Ok(vec![]));
+        let strict = ParserConfig::default().with_strict(true);
+        assert_eq!(parse_python_with_nom_config(model_output, &strict), Ok(vec![]));
+        assert_eq!(parse_python_with_config(model_output, &strict), Ok(vec![]));
+    }
+}
+
+// Well-formed output leaves neither engine anything to recover from or skip
+fn assert_no_diagnostics(model_output: &str) {
+    let (_, diagnostics) = parse_python_with_nom_with_diagnostics(model_output).unwrap();
+    assert_eq!(diagnostics, vec![]);
+    let (_, diagnostics) = parse_python_with_diagnostics(model_output).unwrap();
+    assert_eq!(diagnostics, vec![]);
 }
 
 // Test cases for non-streaming parsing
@@ -110,7 +129,8 @@ fn test_simple_nonstreaming() {
     let expected = vec![get_simple_function_call()];
     
     let result = parse_python_with_nom(&model_output).unwrap();
-    assert_eq!(result, expected);
+    assert_function_calls_eq!(result, expected);
+    assert_no_diagnostics(&model_output);
 }
 
 #[test]
@@ -119,7 +139,8 @@ fn test_more_types_nonstreaming() {
     let expected = vec![get_more_types_function_call()];
     
     let result = parse_python_with_nom(&model_output).unwrap();
-    assert_eq!(result, expected);
+    assert_function_calls_eq!(result, expected);
+    assert_no_diagnostics(&model_output);
 }
 
 #[test]
@@ -128,7 +149,8 @@ fn test_parameterless_nonstreaming() {
     let expected = vec![get_parameterless_function_call()];
     
     let result = parse_python_with_nom(&model_output).unwrap();
-    assert_eq!(result, expected);
+    assert_function_calls_eq!(result, expected);
+    assert_no_diagnostics(&model_output);
 }
 
 #[test]
@@ -137,7 +159,8 @@ fn test_empty_dict_nonstreaming() {
     let expected = vec![get_empty_dict_function_call()];
     
     let result = parse_python_with_nom(&model_output).unwrap();
-    assert_eq!(result, expected);
+    assert_function_calls_eq!(result, expected);
+    assert_no_diagnostics(&model_output);
 }
 
 #[test]
@@ -146,7 +169,8 @@ fn test_empty_list_nonstreaming() {
     let expected = vec![get_empty_list_function_call()];
     
     let result = parse_python_with_nom(&model_output).unwrap();
-    assert_eq!(result, expected);
+    assert_function_calls_eq!(result, expected);
+    assert_no_diagnostics(&model_output);
 }
 
 #[test]
@@ -155,7 +179,8 @@ fn test_escaped_string_nonstreaming() {
     let expected = vec![get_escaped_string_function_call()];
     
     let result = parse_python_with_nom(&model_output).unwrap();
-    assert_eq!(result, expected);
+    assert_function_calls_eq!(result, expected);
+    assert_no_diagnostics(&model_output);
 }
 
 #[test]
@@ -168,7 +193,8 @@ fn test_parallel_calls_nonstreaming() {
     ];
     
     let result = parse_python_with_nom(&model_output).unwrap();
-    assert_eq!(result, expected);
+    assert_function_calls_eq!(result, expected);
+    assert_no_diagnostics(&model_output);
 }
 
 // Test cases for streaming parsing
@@ -180,7 +206,7 @@ fn test_simple_streaming() {
     let result = parse_incremental(&mut state, &model_output).unwrap();
     let expected = vec![get_simple_function_call()];
     
-    assert_eq!(result, expected);
+    assert_function_calls_eq!(result, expected);
 }
 
 #[test]
@@ -191,7 +217,7 @@ fn test_more_types_streaming() {
     let result = parse_incremental(&mut state, &model_output).unwrap();
     let expected = vec![get_more_types_function_call()];
     
-    assert_eq!(result, expected);
+    assert_function_calls_eq!(result, expected);
 }
 
 #[test]
@@ -202,7 +228,7 @@ fn test_parameterless_streaming() {
     let result = parse_incremental(&mut state, &model_output).unwrap();
     let expected = vec![get_parameterless_function_call()];
     
-    assert_eq!(result, expected);
+    assert_function_calls_eq!(result, expected);
 }
 
 #[test]
@@ -213,7 +239,7 @@ fn test_empty_dict_streaming() {
     let result = parse_incremental(&mut state, &model_output).unwrap();
     let expected = vec![get_empty_dict_function_call()];
     
-    assert_eq!(result, expected);
+    assert_function_calls_eq!(result, expected);
 }
 
 #[test]
@@ -224,7 +250,7 @@ fn test_empty_list_streaming() {
     let result = parse_incremental(&mut state, &model_output).unwrap();
     let expected = vec![get_empty_list_function_call()];
     
-    assert_eq!(result, expected);
+    assert_function_calls_eq!(result, expected);
 }
 
 #[test]
@@ -235,7 +261,7 @@ fn test_escaped_string_streaming() {
     let result = parse_incremental(&mut state, &model_output).unwrap();
     let expected = vec![get_escaped_string_function_call()];
     
-    assert_eq!(result, expected);
+    assert_function_calls_eq!(result, expected);
 }
 
 #[test]
@@ -249,7 +275,7 @@ fn test_parallel_calls_streaming() {
         get_more_types_function_call(),
     ];
     
-    assert_eq!(result, expected);
+    assert_function_calls_eq!(result, expected);
 }
 
 #[test]
@@ -272,5 +298,105 @@ fn test_streaming_tool_call_with_large_steps() {
         get_empty_list_function_call(),
     ];
     
-    assert_eq!(result, expected);
-}
\ No newline at end of file
+    assert_function_calls_eq!(result, expected);
+}
+
+// Tokens that are easy to resume wrongly when a chunk ends inside them: escaped quotes,
+// keywords, a fraction, an exponent and a signed number
+const SPLIT_TOKENS_FUNCTION_OUTPUT: &str = r#"f(a="Martha\'s \"Vineyard\"", b=True, c=3.5, d=-1e3, e=None, g=False)"#;
+
+// Every fixture, each split into two chunks at every character boundary, streams to the
+// same calls as the non-streaming parse
+#[test]
+fn test_streaming_every_split_matches_nonstreaming() {
+    let fixtures = [
+        SIMPLE_FUNCTION_OUTPUT,
+        MORE_TYPES_FUNCTION_OUTPUT,
+        PARAMETERLESS_FUNCTION_OUTPUT,
+        EMPTY_DICT_FUNCTION_OUTPUT,
+        EMPTY_LIST_FUNCTION_OUTPUT,
+        ESCAPED_STRING_FUNCTION_OUTPUT,
+        SPLIT_TOKENS_FUNCTION_OUTPUT,
+    ];
+    let mut model_outputs: Vec<String> =
+        fixtures.iter().map(|fixture| format!("[{}]", fixture)).collect();
+    model_outputs.push(format!(
+        "Sure. <|python_start|>[{}, {}]<|python_end|>",
+        SIMPLE_FUNCTION_OUTPUT, SPLIT_TOKENS_FUNCTION_OUTPUT
+    ));
+
+    for model_output in &model_outputs {
+        let expected = parse_python_with_nom(model_output).unwrap();
+        assert_function_calls_eq!(parse_python(model_output).unwrap(), expected);
+
+        let splits = model_output.char_indices().map(|(i, _)| i).skip(1);
+        for split in splits {
+            let chunks = [&model_output[..split], &model_output[split..]];
+
+            let mut state = NomParserState::new();
+            parse_incremental(&mut state, chunks[0]).unwrap();
+            let result = parse_incremental(&mut state, chunks[1]).unwrap();
+            assert_eq!(result, expected, "nom, chunks: {:?}", chunks);
+
+            // Each argument is reported once, with its final value
+            let mut nom = NomParserState::new();
+            let mut logos = LogosParserState::new();
+            for backend in [&mut nom as &mut dyn IncrementalBackend, &mut logos] {
+                let mut arguments = Vec::new();
+                for chunk in chunks {
+                    for event in backend.feed_events(chunk).unwrap() {
+                        if let ParseEvent::ArgumentParsed { name, key, value } = event {
+                            arguments.push((name, key, value));
+                        }
+                    }
+                }
+                assert_eq!(backend.parsed(), expected, "chunks: {:?}", chunks);
+
+                let mut expected_arguments: Vec<(String, String, Value)> = expected
+                    .iter()
+                    .flat_map(|call| {
+                        call.kwargs
+                            .iter()
+                            .map(|(key, value)| (call.name.clone(), key.clone(), value.clone()))
+                    })
+                    .collect();
+                let by_key = |a: &(String, String, Value), b: &(String, String, Value)| {
+                    (&a.0, &a.1).cmp(&(&b.0, &b.1))
+                };
+                arguments.sort_by(by_key);
+                expected_arguments.sort_by(by_key);
+                assert_eq!(arguments, expected_arguments, "chunks: {:?}", chunks);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_count_tool_calls_matches_parse() {
+    let fixtures = [
+        SIMPLE_FUNCTION_OUTPUT,
+        MORE_TYPES_FUNCTION_OUTPUT,
+        PARAMETERLESS_FUNCTION_OUTPUT,
+        EMPTY_DICT_FUNCTION_OUTPUT,
+        EMPTY_LIST_FUNCTION_OUTPUT,
+        ESCAPED_STRING_FUNCTION_OUTPUT,
+        SPLIT_TOKENS_FUNCTION_OUTPUT,
+    ];
+    let mut model_outputs: Vec<String> =
+        fixtures.iter().map(|fixture| fixture.to_string()).collect();
+    model_outputs.extend(fixtures.iter().map(|fixture| format!("[{}]", fixture)));
+    model_outputs.push(format!("[{}]", fixtures.join(", ")));
+    model_outputs.push(format!(
+        "Sure. <|python_start|>[{}, {}]<|python_end|> then [{}]",
+        SIMPLE_FUNCTION_OUTPUT, MORE_TYPES_FUNCTION_OUTPUT, PARAMETERLESS_FUNCTION_OUTPUT
+    ));
+
+    for model_output in &model_outputs {
+        assert_eq!(
+            count_tool_calls(model_output),
+            parse_python_with_nom(model_output).unwrap().len(),
+            "{}",
+            model_output
+        );
+    }
+}
@@ -0,0 +1,41 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use backend::parse_python_with_nom;
+
+// Collects everything a subscriber writes, for inspecting the trace output
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_nom_parser_traces_calls_and_values() {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::TRACE)
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .finish();
+
+    let long_value = "x".repeat(200);
+    let source = format!(r#"[get_weather(city="SF", note="{}")]"#, long_value);
+    tracing::subscriber::with_default(subscriber, || parse_python_with_nom(&source).unwrap());
+
+    let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    assert!(output.contains("parse_function_call"));
+    assert!(output.contains("Parsed function call: get_weather"));
+    assert!(output.contains("parse_string"));
+    // Inputs are cut to their first 50 characters
+    assert!(!output.contains(&long_value[..51]));
+}
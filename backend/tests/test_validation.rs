@@ -0,0 +1,117 @@
+use backend::validation::{ValidationError, validate_call};
+use backend::{FunctionCall, Value, parse_python_with_nom};
+use serde_json::json;
+
+fn weather_tool() -> serde_json::Value {
+    json!({
+        "type": "function",
+        "function": {
+            "name": "get_weather",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "city": {"type": "string"},
+                    "days": {"type": "integer"},
+                    "threshold": {"type": "number"},
+                    "metric": {"type": ["boolean", "null"]},
+                    "tags": {"type": "array"},
+                    "filters": {"type": "object"}
+                },
+                "required": ["city", "days"]
+            }
+        }
+    })
+}
+
+fn parse_one(source: &str) -> FunctionCall {
+    parse_python_with_nom(source).unwrap().remove(0)
+}
+
+#[test]
+fn test_valid_call() {
+    let call = parse_one(
+        r#"[get_weather(city="SF", days=3, threshold=0.5, metric=None, tags=["a"], filters={"unit": "C"})]"#,
+    );
+
+    assert_eq!(validate_call(&call, &weather_tool()), Ok(()));
+}
+
+#[test]
+fn test_validation_errors() {
+    let call = parse_one(r#"[get_weather(days=2.5, metric="yes", units="C")]"#);
+
+    assert_eq!(
+        validate_call(&call, &weather_tool()),
+        Err(vec![
+            ValidationError::MissingRequired {
+                field: "city".to_string()
+            },
+            ValidationError::TypeMismatch {
+                field: "days".to_string(),
+                expected: "integer",
+                got: "number",
+            },
+            ValidationError::TypeMismatch {
+                field: "metric".to_string(),
+                expected: "boolean",
+                got: "string",
+            },
+            ValidationError::UnknownField {
+                field: "units".to_string()
+            },
+        ])
+    );
+}
+
+#[test]
+fn test_integer_accepted_as_number() {
+    let call = FunctionCall::new("get_weather")
+        .with_kwarg("city", Value::String("SF".to_string()))
+        .with_kwarg("days", Value::Number(3.0))
+        .with_kwarg("threshold", Value::Number(2.0));
+
+    assert_eq!(validate_call(&call, &weather_tool()), Ok(()));
+}
+
+#[test]
+fn test_list_is_not_an_object() {
+    let call = parse_one(r#"[get_weather(city="SF", days=1, filters=[1, 2])]"#);
+
+    assert_eq!(
+        validate_call(&call, &weather_tool()),
+        Err(vec![ValidationError::TypeMismatch {
+            field: "filters".to_string(),
+            expected: "object",
+            got: "array",
+        }])
+    );
+}
+
+#[test]
+fn test_additional_properties() {
+    let schema = json!({
+        "name": "log",
+        "parameters": {
+            "properties": {"message": {"type": "string"}},
+            "additionalProperties": true
+        }
+    });
+    let call = parse_one(r#"[log(message="hi", level="debug")]"#);
+
+    assert_eq!(validate_call(&call, &schema), Ok(()));
+}
+
+#[test]
+fn test_error_messages() {
+    let call = parse_one(r#"[get_weather(days="3")]"#);
+    let errors = validate_call(&call, &weather_tool()).unwrap_err();
+
+    let messages: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "missing required field 'city'",
+            "field 'days' should be integer, got string",
+        ]
+    );
+}
@@ -0,0 +1,153 @@
+"""Generate the type stub for the compiled module from the signatures pyo3 gives it.
+
+Run after building the extension, e.g. `uv run maturin develop && uv run python
+generate_stubs.py`. With --check, exit non-zero instead if the stub is out of date.
+"""
+
+import sys
+from pathlib import Path
+
+from llama_tool_parser_native import llama_tool_parser_native as native
+
+STUB_PATH = Path(__file__).parent / "llama_tool_parser_native" / "llama_tool_parser_native.pyi"
+
+HEADER = """\
+# Generated by generate_stubs.py from the compiled module's signatures; do not edit.
+from collections.abc import Callable, Iterable, Iterator
+from typing import Any
+"""
+
+# pyo3's signatures carry no types, so each parameter's type is looked up by its name, which
+# means a parameter has the same type wherever it appears
+CALL = "dict[str, Any]"
+PARAMETER_TYPES = {
+    "source": "str",
+    "chunk": "str",
+    "chunks": "Iterable[str]",
+    "engine": "str | None",
+    "max_buffer_size": "int | None",
+    "events": "bool",
+    "on_call_start": "Callable[[str], object] | None",
+    "on_kwarg": "Callable[[str, str, Any], object] | None",
+    "on_call_complete": f"Callable[[{CALL}], object] | None",
+    "on_block_end": "Callable[[], object] | None",
+    "callback": f"Callable[[{CALL}], object] | None",
+    "delta": "bool",
+    "start_marker": "str | None",
+    "end_marker": "str | None",
+    "strict": "bool",
+    "allowed_names": "list[str] | None",
+    "collect_stats": "bool",
+    "allow_surrounding_text": "bool",
+    "max_calls": "int | None",
+    "on_duplicate_kwarg": "str | None",
+    "recovery": "bool",
+    "allow_no_calls": "bool",
+    "parsed": CALL,
+    "schema": "dict[str, Any]",
+    "mapping": "dict[str, str]",
+    "allowed_keys": "set[str]",
+}
+
+# Return types by function or method name; `__iter__` returns its own class
+RETURN_TYPES = {
+    "count_tools": "int",
+    "filter_kwargs": CALL,
+    "parse_kwargs": "dict[str, Any]",
+    # With collect_stats=True, a (calls, stats) tuple
+    "parse_tools": f"list[{CALL}] | tuple[list[{CALL}], dict[str, Any]]",
+    # A call that failed to parse is a ValueError in its place
+    "parse_tools_all": f"list[{CALL} | ValueError]",
+    "parse_tools_with_diagnostics": f"tuple[list[{CALL}], list[dict[str, Any]]]",
+    "rename_kwargs": CALL,
+    "validate_tool_call": "list[str]",
+    "__next__": CALL,
+    "parse_chunk": f"list[{CALL}]",
+    "parse_chunk_tracked": f"list[{CALL}]",
+    "get_tracked_functions": f"list[{CALL}]",
+    "parse_chunk_cumulative": f"list[{CALL}]",
+    "drain_events": "list[dict[str, Any]]",
+    "parse_chunk_events": "Iterator[dict[str, Any]]",
+    "parse_chunk_argument_events": "list[dict[str, Any]]",
+    "finish": f"list[{CALL}]",
+    "flush": f"list[{CALL}]",
+    "is_complete": "bool",
+    "is_truncated": "bool",
+    "is_inside_python_block": "bool",
+    "is_inside_call": "bool",
+    "current_call_name": "str | None",
+    "has_pending_input": "bool",
+    "current_partial": f"{CALL} | None",
+    "current_partial_function": f"{CALL} | None",
+    "reset": "None",
+    "get_parsed_functions": f"list[{CALL}]",
+    "pending": f"list[{CALL}]",
+    "drain": f"list[{CALL}]",
+    "feed_iter": "FeedIter",
+}
+
+
+def _parameter(parameter: str) -> str:
+    name, equals, default = parameter.partition("=")
+    if name in ("self", "/", "*"):
+        return name
+    annotated = f"{name}: {PARAMETER_TYPES[name]}"
+    return f"{annotated} = {default}" if equals else annotated
+
+
+def _parameters(text_signature: str) -> str:
+    # pyo3 names the receiver `$self`, which is not valid Python
+    parameters = text_signature.strip("()").replace("$self", "self").split(", ")
+    return ", ".join(_parameter(parameter) for parameter in parameters if parameter)
+
+
+def _function(name: str, obj, indent: str = "", returns: str | None = None) -> str:
+    returns = returns or RETURN_TYPES[name]
+    return f"{indent}def {name}({_parameters(obj.__text_signature__)}) -> {returns}: ...\n"
+
+
+def _class(name: str, cls: type) -> str:
+    lines = [f"class {name}:\n"]
+    # The class's own signature is that of its constructor, which an iterator has none of
+    if cls.__text_signature__ is not None:
+        parameters = ", ".join(filter(None, ["self", _parameters(cls.__text_signature__)]))
+        lines.append(f"    def __init__({parameters}) -> None: ...\n")
+    for attr, member in vars(cls).items():
+        if attr.startswith("__") and attr not in ("__iter__", "__next__"):
+            continue
+        if not callable(member):
+            # A #[getter], which has no signature of its own
+            lines.append(f"    @property\n    def {attr}(self) -> {RETURN_TYPES[attr]}: ...\n")
+            continue
+        returns = name if attr == "__iter__" else None
+        lines.append(_function(attr, member, "    ", returns))
+    return "".join(lines)
+
+
+def render() -> str:
+    parts = [HEADER]
+    for name in sorted(dir(native)):
+        obj = getattr(native, name)
+        if name.startswith("_"):
+            continue
+        if isinstance(obj, type):
+            parts.append("\n\n" + _class(name, obj))
+        elif getattr(obj, "__text_signature__", None) is not None:
+            parts.append("\n\n" + _function(name, obj))
+    return "".join(parts)
+
+
+def main() -> int:
+    stub = render()
+    if "--check" in sys.argv[1:]:
+        if not STUB_PATH.exists() or STUB_PATH.read_text() != stub:
+            print(f"{STUB_PATH} is out of date, run generate_stubs.py", file=sys.stderr)
+            return 1
+        return 0
+    STUB_PATH.write_text(stub)
+    (STUB_PATH.parent / "py.typed").touch()
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())
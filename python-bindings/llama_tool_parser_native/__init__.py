@@ -1,6 +1,13 @@
-from .llama_tool_parser_native import parse_tools, IncrementalParser
+from .llama_tool_parser_native import parse_tools, parse_tools_all, parse_tools_with_diagnostics, parse_kwargs, count_tools, validate_tool_call, rename_kwargs, filter_kwargs, IncrementalParser
 
 __all__ = [
     "parse_tools",
+    "parse_tools_all",
+    "parse_tools_with_diagnostics",
+    "parse_kwargs",
+    "count_tools",
+    "validate_tool_call",
+    "rename_kwargs",
+    "filter_kwargs",
     "IncrementalParser",
 ]
@@ -0,0 +1,61 @@
+# Generated by generate_stubs.py from the compiled module's signatures; do not edit.
+from collections.abc import Callable, Iterable, Iterator
+from typing import Any
+
+
+class FeedIter:
+    def __iter__(self, /) -> FeedIter: ...
+    def __next__(self, /) -> dict[str, Any]: ...
+
+
+class IncrementalParser:
+    def __init__(self, engine: str | None = None, max_buffer_size: int | None = None, events: bool = False, on_call_start: Callable[[str], object] | None = None, on_kwarg: Callable[[str, str, Any], object] | None = None, on_call_complete: Callable[[dict[str, Any]], object] | None = None, on_block_end: Callable[[], object] | None = None, callback: Callable[[dict[str, Any]], object] | None = None, delta: bool = True, start_marker: str | None = None, end_marker: str | None = None, allowed_names: list[str] | None = None, max_calls: int | None = None, on_duplicate_kwarg: str | None = None) -> None: ...
+    def __iter__(self, /) -> IncrementalParser: ...
+    def __next__(self, /) -> dict[str, Any]: ...
+    def parse_chunk(self, chunk: str) -> list[dict[str, Any]]: ...
+    def parse_chunk_tracked(self, chunk: str) -> list[dict[str, Any]]: ...
+    def get_tracked_functions(self) -> list[dict[str, Any]]: ...
+    def parse_chunk_cumulative(self, chunk: str) -> list[dict[str, Any]]: ...
+    def drain_events(self) -> list[dict[str, Any]]: ...
+    def parse_chunk_events(self, chunk: str) -> Iterator[dict[str, Any]]: ...
+    def parse_chunk_argument_events(self, chunk: str) -> list[dict[str, Any]]: ...
+    def finish(self) -> list[dict[str, Any]]: ...
+    def flush(self) -> list[dict[str, Any]]: ...
+    def is_complete(self) -> bool: ...
+    def is_truncated(self) -> bool: ...
+    def is_inside_python_block(self) -> bool: ...
+    def is_inside_call(self) -> bool: ...
+    def current_call_name(self) -> str | None: ...
+    def has_pending_input(self) -> bool: ...
+    def current_partial(self) -> dict[str, Any] | None: ...
+    def reset(self) -> None: ...
+    def get_parsed_functions(self) -> list[dict[str, Any]]: ...
+    def pending(self) -> list[dict[str, Any]]: ...
+    def drain(self) -> list[dict[str, Any]]: ...
+    def feed_iter(self, chunks: Iterable[str]) -> FeedIter: ...
+    @property
+    def current_partial_function(self) -> dict[str, Any] | None: ...
+
+
+def count_tools(source: str) -> int: ...
+
+
+def filter_kwargs(parsed: dict[str, Any], allowed_keys: set[str]) -> dict[str, Any]: ...
+
+
+def parse_kwargs(source: str) -> dict[str, Any]: ...
+
+
+def parse_tools(source: str, engine: str | None = None, start_marker: str | None = None, end_marker: str | None = None, strict: bool = False, allowed_names: list[str] | None = None, collect_stats: bool = False, allow_surrounding_text: bool = True, max_calls: int | None = None, on_duplicate_kwarg: str | None = None, recovery: bool = True, allow_no_calls: bool = False) -> list[dict[str, Any]] | tuple[list[dict[str, Any]], dict[str, Any]]: ...
+
+
+def parse_tools_all(source: str) -> list[dict[str, Any] | ValueError]: ...
+
+
+def parse_tools_with_diagnostics(source: str, engine: str | None = None) -> tuple[list[dict[str, Any]], list[dict[str, Any]]]: ...
+
+
+def rename_kwargs(parsed: dict[str, Any], mapping: dict[str, str]) -> dict[str, Any]: ...
+
+
+def validate_tool_call(parsed: dict[str, Any], schema: dict[str, Any]) -> list[str]: ...
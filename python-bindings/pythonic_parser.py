@@ -128,12 +128,11 @@ class NativePythonicToolParser(ToolParser):
 
         # parse new chunk
         try:
-            all_parsed_tools = self._incremental_parser.parse_chunk(delta_text)
-            
-            # check if we have new complete tools
-            if len(all_parsed_tools) > self._last_parsed_count:
-                new_tools = all_parsed_tools[self._last_parsed_count:]
-                self._last_parsed_count = len(all_parsed_tools)
+            # parse_chunk only returns the tools completed by this chunk
+            new_tools = self._incremental_parser.parse_chunk(delta_text)
+
+            if new_tools:
+                self._last_parsed_count += len(new_tools)
                 
                 # return delta message with new tool calls
                 return DeltaMessage(
@@ -1,30 +1,76 @@
-use backend::parse_python;
-use backend::parse_python_with_nom;
-use backend::nom_parser::{NomParserState, parse_incremental};
+use backend::{DuplicateKwarg, ParseStats, ParserConfig};
+use backend::{count_function_calls, describe_error, parse_kwargs_only, parse_python_all};
+use backend::{parse_python_with_config, parse_python_with_diagnostics};
+use backend::{parse_python_with_nom_config, parse_python_with_nom_with_diagnostics};
+use backend::nom_parser::NomParserState;
+use backend::validation::validate_call;
+use backend::{FunctionCall, IncrementalBackend, LogosParserState, ParserEvents, Value};
 use pyo3::prelude::*;
-use pyo3::types::PyAny;
-use pythonize::pythonize;
+use pyo3::exceptions::PyDeprecationWarning;
+use pyo3::types::{PyAny, PyIterator, PyTuple};
+use pythonize::{depythonize, pythonize};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
 
+// Every keyword argument of the Python function is a parameter here
+#[allow(clippy::too_many_arguments)]
 #[pyfunction(name = "parse_tools")]
+#[pyo3(signature = (
+    source,
+    engine=None,
+    start_marker=None,
+    end_marker=None,
+    strict=false,
+    allowed_names=None,
+    collect_stats=false,
+    allow_surrounding_text=true,
+    max_calls=None,
+    on_duplicate_kwarg=None,
+    recovery=true,
+    allow_no_calls=false,
+))]
 pub fn wrapped_parse_python(
     py: Python<'_>,
     source: String,
-    engine: String,
+    engine: Option<String>,
+    start_marker: Option<String>,
+    end_marker: Option<String>,
+    strict: bool,
+    allowed_names: Option<Vec<String>>,
+    collect_stats: bool,
+    allow_surrounding_text: bool,
+    max_calls: Option<usize>,
+    on_duplicate_kwarg: Option<String>,
+    recovery: bool,
+    allow_no_calls: bool,
 ) -> PyResult<Bound<'_, PyAny>> {
-    let function_calls = match engine.as_str() {
-        "nom" => match parse_python_with_nom(&source) {
+    let config =
+        parser_config(start_marker, end_marker, allowed_names, max_calls, on_duplicate_kwarg)?
+            .with_strict(strict)
+            .with_collect_stats(collect_stats)
+            .with_allow_surrounding_text(allow_surrounding_text)
+            .with_recovery(recovery)
+            .with_allow_no_calls(allow_no_calls);
+
+    let engine = engine.as_deref().unwrap_or("nom");
+
+    let function_calls = match engine {
+        "nom" => match ParseStats::collect("nom", &source, &config, || {
+            parse_python_with_nom_config(&source, &config)
+        }) {
             Ok(function_calls) => Ok(function_calls),
-            Err(err) => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
-                "Parse error: {:?}",
-                err
-            ))),
+            Err(err) => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                describe_error(&source, &err),
+            )),
         },
-        "logos" => match parse_python(&source) {
+        "logos" => match ParseStats::collect("logos", &source, &config, || {
+            parse_python_with_config(&source, &config)
+        }) {
             Ok(function_calls) => Ok(function_calls),
-            Err(err) => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
-                "Parse error: {:?}",
-                err
-            ))),
+            Err(err) => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                describe_error(&source, &err),
+            )),
         },
         _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
             "Unsupported engine: {}",
@@ -32,64 +78,789 @@ pub fn wrapped_parse_python(
         ))),
     };
 
-    if let Ok(function_calls) = function_calls {
-        Ok(pythonize(py, &function_calls)
-            .expect("Failed to pythonize")
-            .to_owned())
-    } else {
-        Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
-            "Parse error: {:?}",
-            function_calls
-        )))
+    let (function_calls, stats) = function_calls?;
+    let results = to_py(py, &function_calls)?;
+    match stats {
+        // With collect_stats the result is a (calls, stats) tuple
+        Some(stats) => {
+            let stats = to_py(py, &stats)?;
+            Ok(PyTuple::new(py, [results, stats])?.into_any())
+        }
+        None => Ok(results.to_owned()),
+    }
+}
+
+// Build the config from the keyword arguments `parse_tools` and `IncrementalParser` share.
+// `on_duplicate_kwarg` is "last", the default, "first" or "error".
+fn parser_config(
+    start_marker: Option<String>,
+    end_marker: Option<String>,
+    allowed_names: Option<Vec<String>>,
+    max_calls: Option<usize>,
+    on_duplicate_kwarg: Option<String>,
+) -> PyResult<ParserConfig> {
+    let default = ParserConfig::default();
+    let mut config = ParserConfig::new(
+        start_marker.unwrap_or(default.start_marker),
+        end_marker.unwrap_or(default.end_marker),
+    );
+    if let Some(allowed_names) = allowed_names {
+        config = config.with_allowed_names(allowed_names);
+    }
+    if let Some(max_calls) = max_calls {
+        config = config.with_max_calls(max_calls);
+    }
+    let on_duplicate_kwarg = match on_duplicate_kwarg.as_deref().unwrap_or("last") {
+        "last" => DuplicateKwarg::KeepLast,
+        "first" => DuplicateKwarg::KeepFirst,
+        "error" => DuplicateKwarg::Error,
+        other => {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unsupported on_duplicate_kwarg: {}",
+                other
+            )))
+        }
+    };
+    Ok(config.with_on_duplicate_kwarg(on_duplicate_kwarg))
+}
+
+#[pyfunction(name = "parse_tools_all")]
+#[pyo3(signature = (source))]
+pub fn wrapped_parse_python_all(py: Python<'_>, source: String) -> PyResult<Vec<PyObject>> {
+    parse_python_all(&source)
+        .into_iter()
+        .map(|result| match result {
+            Ok(function_call) => Ok(to_py(py, &function_call)?.unbind()),
+            // Errors are returned in place rather than raised
+            Err(err) => Ok(
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(describe_error(&source, &err))
+                    .into_value(py)
+                    .into_any(),
+            ),
+        })
+        .collect()
+}
+
+// Parse with the logos engine, or the nom engine, returning a (calls, diagnostics) tuple.
+// Each diagnostic is a dict with a "message", a "span" of byte offsets and the "snippet" of
+// source it spans, for input the parser recovered from or skipped.
+#[pyfunction(name = "parse_tools_with_diagnostics")]
+#[pyo3(signature = (source, engine=None))]
+pub fn wrapped_parse_python_with_diagnostics(
+    py: Python<'_>,
+    source: String,
+    engine: Option<String>,
+) -> PyResult<Bound<'_, PyAny>> {
+    let parsed = match engine.as_deref().unwrap_or("logos") {
+        "logos" => parse_python_with_diagnostics(&source),
+        "nom" => parse_python_with_nom_with_diagnostics(&source),
+        engine => {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unsupported engine: {}",
+                engine
+            )));
+        }
+    };
+    match parsed {
+        Ok((function_calls, diagnostics)) => {
+            let results = to_py(py, &function_calls)?;
+            let diagnostics = to_py(py, &diagnostics)?;
+            Ok(PyTuple::new(py, [results, diagnostics])?.into_any())
+        }
+        Err(err) => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            describe_error(&source, &err),
+        )),
+    }
+}
+
+#[pyfunction(name = "count_tools")]
+#[pyo3(signature = (source))]
+pub fn wrapped_count_function_calls(source: String) -> usize {
+    count_function_calls(&source)
+}
+
+#[pyfunction(name = "parse_kwargs")]
+#[pyo3(signature = (source))]
+pub fn wrapped_parse_kwargs(py: Python<'_>, source: String) -> PyResult<Bound<'_, PyAny>> {
+    match parse_kwargs_only(&source) {
+        Ok(kwargs) => to_py(py, &kwargs),
+        Err(err) => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            describe_error(&source, &err),
+        )),
+    }
+}
+
+// Check a call as returned by `parse_tools` against its tool's JSON Schema, returning
+// one message per problem; an empty list means the call is valid
+#[pyfunction(name = "validate_tool_call")]
+#[pyo3(signature = (parsed, schema))]
+pub fn wrapped_validate_tool_call(
+    parsed: Bound<'_, PyAny>,
+    schema: Bound<'_, PyAny>,
+) -> PyResult<Vec<String>> {
+    let call = depythonize_call(&parsed)?;
+    let schema: serde_json::Value = depythonize(&schema).map_err(|err| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid schema: {}", err))
+    })?;
+    Ok(match validate_call(&call, &schema) {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors.iter().map(|error| error.to_string()).collect(),
+    })
+}
+
+// Return a copy of a call as returned by `parse_tools` with its kwargs renamed by
+// `mapping`, leaving keys it doesn't mention unchanged
+#[pyfunction(name = "rename_kwargs")]
+#[pyo3(signature = (parsed, mapping))]
+pub fn wrapped_rename_kwargs<'py>(
+    parsed: Bound<'py, PyAny>,
+    mapping: HashMap<String, String>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let call = depythonize_call(&parsed)?;
+    let mapping = mapping
+        .iter()
+        .map(|(from, to)| (from.as_str(), to.as_str()))
+        .collect();
+    to_py(parsed.py(), &call.rename_kwargs(&mapping))
+}
+
+// Return a copy of a call as returned by `parse_tools` keeping only the kwargs whose keys
+// are in `allowed_keys`
+#[pyfunction(name = "filter_kwargs")]
+#[pyo3(signature = (parsed, allowed_keys))]
+pub fn wrapped_filter_kwargs<'py>(
+    parsed: Bound<'py, PyAny>,
+    allowed_keys: HashSet<String>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let mut call = depythonize_call(&parsed)?;
+    call.kwargs.retain(|key, _| allowed_keys.contains(key));
+    to_py(parsed.py(), &call)
+}
+
+fn depythonize_call(parsed: &Bound<'_, PyAny>) -> PyResult<FunctionCall> {
+    depythonize(parsed).map_err(|err| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid tool call: {}", err))
+    })
+}
+
+// Convert to Python objects, raising ValueError rather than panicking should `value` hold
+// something Python can't represent
+fn to_py<'py, T: Serialize + ?Sized>(py: Python<'py>, value: &T) -> PyResult<Bound<'py, PyAny>> {
+    pythonize(py, value).map_err(|err| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "Failed to convert to Python: {}",
+            err
+        ))
+    })
+}
+
+// Convert each item to a Python object, as `to_py` does
+fn to_py_list<T: Serialize>(py: Python<'_>, items: &[T]) -> PyResult<Vec<PyObject>> {
+    items
+        .iter()
+        .map(|item| Ok(to_py(py, item)?.unbind()))
+        .collect()
+}
+
+// Python callables to run as the stream reveals each part of a call
+#[derive(Default)]
+struct Hooks {
+    on_call_start: Option<PyObject>,
+    on_kwarg: Option<PyObject>,
+    on_call_complete: Option<PyObject>,
+    on_block_end: Option<PyObject>,
+}
+
+impl Hooks {
+    fn is_empty(&self) -> bool {
+        self.on_call_start.is_none()
+            && self.on_kwarg.is_none()
+            && self.on_call_complete.is_none()
+            && self.on_block_end.is_none()
+    }
+
+    // Make the recorded hook calls in order, stopping at the first exception a hook raises
+    fn run(&self, py: Python<'_>, calls: Vec<HookCall>) -> PyResult<()> {
+        for call in calls {
+            match call {
+                HookCall::CallStart(name) => {
+                    if let Some(hook) = &self.on_call_start {
+                        hook.call1(py, (name,))?;
+                    }
+                }
+                HookCall::Kwarg(name, key, value) => {
+                    if let Some(hook) = &self.on_kwarg {
+                        hook.call1(py, (name, key, to_py(py, &value)?))?;
+                    }
+                }
+                HookCall::CallComplete(function_call) => {
+                    if let Some(hook) = &self.on_call_complete {
+                        hook.call1(py, (to_py(py, &function_call)?,))?;
+                    }
+                }
+                HookCall::BlockEnd => {
+                    if let Some(hook) = &self.on_block_end {
+                        hook.call0(py)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// A hook call recorded while the chunk is parsed
+enum HookCall {
+    CallStart(String),
+    Kwarg(String, String, Value),
+    CallComplete(FunctionCall),
+    BlockEnd,
+}
+
+// Records a call for each hook that is set, to be made once the state is unlocked, so
+// that a hook can call back into the parser instead of waiting on the lock forever
+struct PyParserEvents<'a> {
+    hooks: &'a Hooks,
+    calls: Vec<HookCall>,
+}
+
+impl ParserEvents for PyParserEvents<'_> {
+    fn on_call_start(&mut self, name: &str) {
+        if self.hooks.on_call_start.is_some() {
+            self.calls.push(HookCall::CallStart(name.to_string()));
+        }
+    }
+
+    fn on_kwarg(&mut self, name: &str, key: &str, value: &Value) {
+        if self.hooks.on_kwarg.is_some() {
+            self.calls.push(HookCall::Kwarg(
+                name.to_string(),
+                key.to_string(),
+                value.clone(),
+            ));
+        }
+    }
+
+    fn on_call_complete(&mut self, _index: usize, function_call: &FunctionCall) {
+        if self.hooks.on_call_complete.is_some() {
+            self.calls.push(HookCall::CallComplete(function_call.clone()));
+        }
+    }
+
+    fn on_block_end(&mut self) {
+        if self.hooks.on_block_end.is_some() {
+            self.calls.push(HookCall::BlockEnd);
+        }
     }
 }
 
-#[pyclass(name = "IncrementalParser")]
+// The engine's state, and how many of its calls `drain` or iteration has returned
+struct Shared {
+    state: Box<dyn IncrementalBackend + Send>,
+    drained: usize,
+}
+
+// Safe to share between Python threads: the state is behind a lock, which is only waited
+// for with the GIL released. Chunks are parsed without the GIL, and converted to Python
+// objects once the lock is released again.
+#[pyclass(name = "IncrementalParser", frozen)]
 pub struct IncrementalParser {
-    state: NomParserState,
+    shared: Mutex<Shared>,
+    // "nom" or "logos", for `__repr__`
+    engine: String,
+    hooks: Hooks,
+    // Called with each call as it is returned, i.e. once its list has closed
+    callback: Option<PyObject>,
+    // Whether `parse_chunk` returns only the calls completed by the chunk
+    delta: bool,
 }
 
 #[pymethods]
 impl IncrementalParser {
     #[new]
-    fn new() -> Self {
-        Self {
-            state: NomParserState::new(),
-        }
-    }
-
-    fn parse_chunk(&mut self, chunk: String) -> PyResult<Vec<PyObject>> {
-        Python::with_gil(|py| {
-            match parse_incremental(&mut self.state, &chunk) {
-                Ok(function_calls) => Ok(pythonize(py, &function_calls)
-                    .expect("Failed to pythonize")
-                    .extract()
-                    .expect("Failed to extract")),
-                Err(err) => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
-                    "Parse error: {:?}",
-                    err
-                ))),
+    // The on_* callables are called from `parse_chunk` for each part of a call the chunk
+    // revealed, in source order, once the chunk is parsed: on_call_start(name),
+    // on_kwarg(name, key, value), on_call_complete(call) and on_block_end().
+    // `callback(call)` is instead called once for every call that `parse_chunk` or `finish`
+    // returns, so never for a call in an abandoned list.
+    // delta=False makes `parse_chunk` return every call so far; it is deprecated in favour
+    // of `pending()`.
+    #[pyo3(signature = (
+        engine=None,
+        max_buffer_size=None,
+        events=false,
+        on_call_start=None,
+        on_kwarg=None,
+        on_call_complete=None,
+        on_block_end=None,
+        callback=None,
+        delta=true,
+        start_marker=None,
+        end_marker=None,
+        allowed_names=None,
+        max_calls=None,
+        on_duplicate_kwarg=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        py: Python<'_>,
+        engine: Option<String>,
+        max_buffer_size: Option<usize>,
+        events: bool,
+        on_call_start: Option<PyObject>,
+        on_kwarg: Option<PyObject>,
+        on_call_complete: Option<PyObject>,
+        on_block_end: Option<PyObject>,
+        callback: Option<PyObject>,
+        delta: bool,
+        start_marker: Option<String>,
+        end_marker: Option<String>,
+        allowed_names: Option<Vec<String>>,
+        max_calls: Option<usize>,
+        on_duplicate_kwarg: Option<String>,
+    ) -> PyResult<Self> {
+        if !delta {
+            PyErr::warn(
+                py,
+                &py.get_type::<PyDeprecationWarning>(),
+                c"delta=False is deprecated, use pending() for every call parsed so far",
+                1,
+            )?;
+        }
+        let engine = engine.as_deref().unwrap_or("nom");
+        if max_buffer_size.is_some() && engine != "nom" {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "max_buffer_size is not supported by engine: {}",
+                engine
+            )));
+        }
+        let config =
+            parser_config(start_marker, end_marker, allowed_names, max_calls, on_duplicate_kwarg)?;
+        if config != ParserConfig::default() && engine != "nom" {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Parser options are not supported by engine: {}",
+                engine
+            )));
+        }
+
+        let state: Box<dyn IncrementalBackend + Send> =
+            match engine {
+                "nom" => {
+                    let mut state = NomParserState::with_config(config);
+                    state.set_max_buffer_size(max_buffer_size);
+                    state.set_emit_events(events);
+                    Box::new(state)
+                }
+                "logos" => {
+                    let mut state = LogosParserState::new();
+                    state.set_emit_events(events);
+                    Box::new(state)
+                }
+                other => {
+                    return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "Unsupported engine: {}",
+                        other
+                    )))
+                }
+            };
+        let hooks = Hooks {
+            on_call_start,
+            on_kwarg,
+            on_call_complete,
+            on_block_end,
+        };
+        Ok(Self {
+            shared: Mutex::new(Shared { state, drained: 0 }),
+            engine: engine.to_string(),
+            hooks,
+            callback,
+            delta,
+        })
+    }
+
+    // Returns only the calls completed by this chunk, or with delta=False every call so
+    // far
+    #[pyo3(signature = (chunk))]
+    fn parse_chunk(&self, py: Python<'_>, chunk: String) -> PyResult<Vec<PyObject>> {
+        let delta = self.delta;
+        let (function_calls, all) =
+            self.feed(py, &chunk, |state, _| (!delta).then(|| state.parsed().to_vec()))?;
+        to_py_list(py, all.as_deref().unwrap_or(&function_calls))
+    }
+
+    // Like `parse_chunk`, but each call is a dict that also has an "index", the order in
+    // which the stream completed it, and an "id" derived from it. Neither changes for the
+    // life of the parser, so later deltas for a call can be matched to it.
+    #[pyo3(signature = (chunk))]
+    fn parse_chunk_tracked(&self, py: Python<'_>, chunk: String) -> PyResult<Vec<PyObject>> {
+        let (_, tracked) = self.feed(py, &chunk, |state, reported| {
+            state.tracked_functions().split_off(reported)
+        })?;
+        to_py_list(py, &tracked)
+    }
+
+    // Every call parsed so far, with its "index" and "id" as in `parse_chunk_tracked`
+    #[pyo3(signature = ())]
+    fn get_tracked_functions(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        let tracked = self.with_state(py, |shared| shared.state.tracked_functions())?;
+        to_py_list(py, &tracked)
+    }
+
+    // Returns every call parsed so far, as `parse_chunk` did before it returned deltas
+    #[pyo3(signature = (chunk))]
+    fn parse_chunk_cumulative(&self, py: Python<'_>, chunk: String) -> PyResult<Vec<PyObject>> {
+        let (_, all) = self.feed(py, &chunk, |state, _| state.parsed().to_vec())?;
+        to_py_list(py, &all)
+    }
+
+    // Events recorded since the last call, as dicts with a "type" of "CallStarted",
+    // "ArgumentsDelta" or "CallFinished". Requires the parser to be created with events=True.
+    #[pyo3(signature = ())]
+    fn drain_events(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        let events = self.with_state(py, |shared| shared.state.drain_events())?;
+        to_py_list(py, &events)
+    }
+
+    // Feed a chunk and iterate over the events it produced
+    #[pyo3(signature = (chunk))]
+    fn parse_chunk_events<'py>(
+        &self,
+        py: Python<'py>,
+        chunk: String,
+    ) -> PyResult<Bound<'py, PyIterator>> {
+        let (_, events) = self.feed(py, &chunk, |state, _| state.drain_events())?;
+        to_py(py, &events)?.try_iter()
+    }
+
+    // Feed a chunk and return the parsed-value events it produced, as dicts with a "type" of
+    // "FunctionStarted", "ArgumentParsed" or "FunctionCompleted". Unlike `parse_chunk_events`,
+    // arguments arrive as values rather than JSON fragments, and no setup is needed.
+    #[pyo3(signature = (chunk))]
+    fn parse_chunk_argument_events(
+        &self,
+        py: Python<'_>,
+        chunk: String,
+    ) -> PyResult<Vec<PyObject>> {
+        let (events, function_calls) = self.with_state(py, |shared| {
+            let reported = shared.state.parsed().len();
+            let events = shared.state.feed_events(&chunk);
+            (events, shared.state.parsed()[reported..].to_vec())
+        })?;
+        match events {
+            Ok(events) => {
+                self.run_callback(py, &function_calls)?;
+                to_py_list(py, &events)
             }
+            Err(err) => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Parse error: {}",
+                err
+            ))),
+        }
+    }
+
+    #[pyo3(signature = ())]
+    fn finish(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        let (function_calls, _) = self.end_stream(py)?;
+        to_py_list(py, &function_calls)
+    }
+
+    // End the stream like `finish`, but return only the calls still buffered, e.g. one cut
+    // off mid-arguments, completed as if its `)` and `]` had arrived
+    #[pyo3(signature = ())]
+    fn flush(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        let (function_calls, reported) = self.end_stream(py)?;
+        to_py_list(py, &function_calls[reported..])
+    }
+
+    #[pyo3(signature = ())]
+    fn is_complete(&self, py: Python<'_>) -> PyResult<bool> {
+        self.with_state(py, |shared| shared.state.is_complete())
+    }
+
+    // Whether `finish` had to complete a call cut off by the end of the stream
+    #[pyo3(signature = ())]
+    fn is_truncated(&self, py: Python<'_>) -> PyResult<bool> {
+        self.with_state(py, |shared| shared.state.is_truncated())
+    }
+
+    #[pyo3(signature = ())]
+    fn is_inside_python_block(&self, py: Python<'_>) -> PyResult<bool> {
+        self.with_state(py, |shared| shared.state.is_inside_python_block())
+    }
+
+    #[pyo3(signature = ())]
+    fn is_inside_call(&self, py: Python<'_>) -> PyResult<bool> {
+        self.with_state(py, |shared| shared.state.current_call_name().is_some())
+    }
+
+    #[pyo3(signature = ())]
+    fn current_call_name(&self, py: Python<'_>) -> PyResult<Option<String>> {
+        self.with_state(py, |shared| {
+            shared.state.current_call_name().map(str::to_string)
         })
     }
 
-    fn reset(&mut self) {
-        self.state.reset();
+    #[pyo3(signature = ())]
+    fn has_pending_input(&self, py: Python<'_>) -> PyResult<bool> {
+        self.with_state(py, |shared| shared.state.has_pending_input())
+    }
+
+    // The call whose arguments are still arriving, as a dict with the name and the kwargs
+    // completed so far. Always None for the logos engine, which only sees whole calls.
+    #[pyo3(signature = ())]
+    fn current_partial(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let function_call = self.with_state(py, |shared| {
+            shared.state.current_partial().map(|partial| FunctionCall {
+                name: partial.name.clone(),
+                kwargs: partial.kwargs.clone(),
+                span: None,
+            })
+        })?;
+        function_call
+            .map(|function_call| Ok(to_py(py, &function_call)?.unbind()))
+            .transpose()
+    }
+
+    // `current_partial()` as a property: None, or a dict with the "name" and "kwargs" of the
+    // call being built
+    #[getter]
+    fn current_partial_function(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        self.current_partial(py)
+    }
+
+    #[pyo3(signature = ())]
+    fn reset(&self, py: Python<'_>) -> PyResult<()> {
+        self.with_state(py, |shared| {
+            shared.state.reset();
+            shared.drained = 0;
+        })
+    }
+
+    #[pyo3(signature = ())]
+    fn get_parsed_functions(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        let function_calls = self.with_state(py, |shared| shared.state.parsed().to_vec())?;
+        to_py_list(py, &function_calls)
+    }
+
+    // Every call parsed so far, however it was returned
+    #[pyo3(signature = ())]
+    fn pending(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        self.get_parsed_functions(py)
+    }
+
+    // The calls parsed since the last `drain`, or since they were last iterated over
+    #[pyo3(signature = ())]
+    fn drain(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        let undrained = self.with_state(py, |shared| {
+            let undrained = shared.state.parsed()[shared.drained..].to_vec();
+            shared.drained += undrained.len();
+            undrained
+        })?;
+        to_py_list(py, &undrained)
+    }
+
+    // e.g. IncrementalParser(engine='nom', parsed=2, in_block=True, pending_input=False)
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        let (parsed, in_block, pending_input) = self.with_state(py, |shared| {
+            let state = &shared.state;
+            (state.parsed().len(), state.is_inside_python_block(), state.has_pending_input())
+        })?;
+        Ok(format!(
+            "IncrementalParser(engine='{}', parsed={}, in_block={}, pending_input={})",
+            self.engine,
+            parsed,
+            if in_block { "True" } else { "False" },
+            if pending_input { "True" } else { "False" },
+        ))
     }
 
-    fn get_parsed_functions(&self) -> PyResult<Vec<PyObject>> {
-        Python::with_gil(|py| {
-            Ok(pythonize(py, &self.state.get_parsed_functions())
-                .expect("Failed to pythonize")
-                .extract()
-                .expect("Failed to extract"))
+    // e.g. "nom parser with 2 calls parsed, in the middle of a call to get_weather"
+    fn __str__(&self, py: Python<'_>) -> PyResult<String> {
+        let (parsed, progress) = self.with_state(py, |shared| {
+            let state = &shared.state;
+            let progress = if let Some(name) = state.current_call_name() {
+                format!(", in the middle of a call to {}", name)
+            } else if state.is_inside_python_block() {
+                ", inside a python block".to_string()
+            } else if state.has_pending_input() {
+                ", waiting for more input".to_string()
+            } else {
+                String::new()
+            };
+            (state.parsed().len(), progress)
+        })?;
+        let plural = if parsed == 1 { "" } else { "s" };
+        Ok(format!("{} parser with {} call{} parsed{}", self.engine, parsed, plural, progress))
+    }
+
+    // Iterating over the parser yields the calls `drain` would return, one at a time
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        self.next_undrained(py)
+    }
+
+    // Feed each chunk of an iterable in turn, yielding the calls as they complete. The
+    // stream is finished once the chunks run out, so a call cut off at the end is yielded
+    // too.
+    #[pyo3(signature = (chunks))]
+    fn feed_iter(slf: Py<Self>, chunks: Bound<'_, PyAny>) -> PyResult<FeedIter> {
+        Ok(FeedIter {
+            parser: slf,
+            chunks: chunks.try_iter()?.unbind(),
+            finished: false,
+        })
+    }
+}
+
+impl IncrementalParser {
+    // Run `f` on the locked state with the GIL released. Waiting for the lock without the
+    // GIL means a thread holding the lock can always take the GIL to run a hook.
+    fn with_state<R: Send>(
+        &self,
+        py: Python<'_>,
+        f: impl FnOnce(&mut Shared) -> R + Send,
+    ) -> PyResult<R> {
+        py.allow_threads(|| match self.shared.lock() {
+            Ok(mut shared) => Ok(f(&mut shared)),
+            Err(_) => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+                "IncrementalParser state was poisoned by a panic",
+            )),
         })
     }
+
+    // Feed a chunk, running the hooks once the state is unlocked, and return the calls it
+    // completed along with what `after` takes from the state before it is unlocked. `after`
+    // is also passed how many calls had been parsed before the chunk.
+    fn feed<R: Send>(
+        &self,
+        py: Python<'_>,
+        chunk: &str,
+        after: impl FnOnce(&mut dyn IncrementalBackend, usize) -> R + Send,
+    ) -> PyResult<(Vec<FunctionCall>, R)> {
+        let (result, hook_calls) = self.with_state(py, |shared| {
+            let reported = shared.state.parsed().len();
+            let mut events = PyParserEvents {
+                hooks: &self.hooks,
+                calls: Vec::new(),
+            };
+            let result = if self.hooks.is_empty() {
+                shared.state.feed(chunk)
+            } else {
+                shared.state.feed_with(chunk, &mut events)
+            };
+            let result = result.map(|function_calls| {
+                let extra = after(shared.state.as_mut(), reported);
+                (function_calls, extra)
+            });
+            (result, events.calls)
+        })?;
+        self.hooks.run(py, hook_calls)?;
+        match result {
+            Ok((function_calls, extra)) => {
+                self.run_callback(py, &function_calls)?;
+                Ok((function_calls, extra))
+            }
+            Err(err) => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Parse error: {}",
+                err
+            ))),
+        }
+    }
+
+    // Finish the stream, returning every call along with how many had been parsed before,
+    // and pass the calls it completed to `callback`
+    fn end_stream(&self, py: Python<'_>) -> PyResult<(Vec<FunctionCall>, usize)> {
+        let (result, reported) = self.with_state(py, |shared| {
+            let reported = shared.state.parsed().len();
+            (shared.state.finish(), reported)
+        })?;
+        match result {
+            Ok(function_calls) => {
+                self.run_callback(py, &function_calls[reported..])?;
+                Ok((function_calls, reported))
+            }
+            Err(err) => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Parse error: {}",
+                err
+            ))),
+        }
+    }
+
+    fn next_undrained(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let function_call = self.with_state(py, |shared| {
+            let function_call = shared.state.parsed().get(shared.drained).cloned();
+            shared.drained += function_call.is_some() as usize;
+            function_call
+        })?;
+        function_call
+            .map(|function_call| Ok(to_py(py, &function_call)?.unbind()))
+            .transpose()
+    }
+
+    // Pass each newly returned call to `callback`, raising the first exception it raises.
+    // The calls are already recorded as parsed, so they are not passed again.
+    fn run_callback(&self, py: Python<'_>, function_calls: &[FunctionCall]) -> PyResult<()> {
+        let Some(callback) = &self.callback else {
+            return Ok(());
+        };
+        for function_call in function_calls {
+            let function_call = to_py(py, function_call)?;
+            callback.call1(py, (function_call,))?;
+        }
+        Ok(())
+    }
+}
+
+// Iterator returned by `IncrementalParser.feed_iter`
+#[pyclass(name = "FeedIter")]
+pub struct FeedIter {
+    parser: Py<IncrementalParser>,
+    chunks: Py<PyIterator>,
+    finished: bool,
+}
+
+#[pymethods]
+impl FeedIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let parser = self.parser.get();
+        loop {
+            if let Some(function_call) = parser.next_undrained(py)? {
+                return Ok(Some(function_call));
+            }
+            if self.finished {
+                return Ok(None);
+            }
+            match self.chunks.bind(py).clone().next() {
+                Some(chunk) => {
+                    parser.parse_chunk(py, chunk?.extract()?)?;
+                }
+                None => {
+                    self.finished = true;
+                    parser.finish(py)?;
+                }
+            }
+        }
+    }
 }
 
 #[pymodule]
 fn llama_tool_parser_native(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(wrapped_parse_python, m)?)?;
+    m.add_function(wrap_pyfunction!(wrapped_parse_python_all, m)?)?;
+    m.add_function(wrap_pyfunction!(wrapped_parse_python_with_diagnostics, m)?)?;
+    m.add_function(wrap_pyfunction!(wrapped_parse_kwargs, m)?)?;
+    m.add_function(wrap_pyfunction!(wrapped_count_function_calls, m)?)?;
+    m.add_function(wrap_pyfunction!(wrapped_validate_tool_call, m)?)?;
+    m.add_function(wrap_pyfunction!(wrapped_rename_kwargs, m)?)?;
+    m.add_function(wrap_pyfunction!(wrapped_filter_kwargs, m)?)?;
     m.add_class::<IncrementalParser>()?;
+    m.add_class::<FeedIter>()?;
     Ok(())
 }
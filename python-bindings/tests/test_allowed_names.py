@@ -0,0 +1,25 @@
+import pytest
+from llama_tool_parser_native import parse_tools
+
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_allowed_names_drops_other_calls(engine):
+    tools = parse_tools("[get_weather(), evil_call()]", engine=engine, allowed_names=["get_weather"])
+
+    assert [tool["name"] for tool in tools] == ["get_weather"]
+
+
+def test_allowed_names_strict_rejects_other_calls():
+    with pytest.raises(ValueError):
+        parse_tools(
+            "[get_weather(), evil_call()]",
+            engine="nom",
+            strict=True,
+            allowed_names=["get_weather"],
+        )
+
+
+def test_no_allowlist_keeps_every_call():
+    tools = parse_tools("[get_weather(), evil_call()]", engine="nom")
+
+    assert [tool["name"] for tool in tools] == ["get_weather", "evil_call"]
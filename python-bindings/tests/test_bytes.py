@@ -0,0 +1,11 @@
+import pytest
+from llama_tool_parser_native import parse_tools
+
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_bytes_literal_becomes_bytes(engine):
+    """Bytes literals come back as Python bytes, with their escapes resolved."""
+    tools = parse_tools(r'[upload(data=b"\x00\x01\x02", name=b"a.bin")]', engine=engine)
+
+    assert tools[0]["kwargs"]["data"] == {"Bytes": b"\x00\x01\x02"}
+    assert tools[0]["kwargs"]["name"] == {"Bytes": b"a.bin"}
@@ -0,0 +1,7 @@
+from llama_tool_parser_native import count_tools
+
+
+def test_count_tools():
+    assert count_tools("[f(x=1), g(y=2)]") == 2
+    assert count_tools('<|python_start|>[calc(expr="f(1)")]<|python_end|>') == 1
+    assert count_tools("no tools here") == 0
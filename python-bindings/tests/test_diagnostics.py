@@ -0,0 +1,43 @@
+import pytest
+
+from llama_tool_parser_native import parse_tools, parse_tools_with_diagnostics
+
+
+def test_diagnostics_for_recovered_input():
+    code = '[get_weather(city="SF", days=), ping(x=[1, =])]'
+
+    tools, diagnostics = parse_tools_with_diagnostics(code)
+
+    assert tools == parse_tools(code, "logos")
+    assert [tool["name"] for tool in tools] == ["get_weather", "ping"]
+    assert diagnostics == [
+        {
+            "message": "empty argument value for 'days'",
+            "span": {"start": 29, "end": 30},
+            "snippet": ")",
+        },
+        {
+            "message": "skipped unexpected token '='",
+            "span": {"start": 43, "end": 44},
+            "snippet": "=",
+        },
+    ]
+
+
+def test_skipped_candidates_are_diagnosed_by_nom():
+    code = 'Try [foo(x=1 y)] or [bar(z="a")]'
+
+    tools, diagnostics = parse_tools_with_diagnostics(code, engine="nom")
+
+    assert [tool["name"] for tool in tools] == ["bar"]
+    assert [(d["span"], d["snippet"]) for d in diagnostics] == [
+        ({"start": 4, "end": 14}, "[foo(x=1 y"),
+    ]
+
+
+@pytest.mark.parametrize("engine", ["logos", "nom"])
+def test_no_diagnostics_for_well_formed_input(engine):
+    tools, diagnostics = parse_tools_with_diagnostics('Sure! [get_weather(city="SF")]', engine)
+
+    assert [tool["name"] for tool in tools] == ["get_weather"]
+    assert diagnostics == []
@@ -0,0 +1,12 @@
+import pytest
+from llama_tool_parser_native import parse_tools
+
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_ellipsis_literals(engine):
+    """`...` is kept as its own unit value, like `None`, rather than failing the call."""
+    tools = parse_tools("[foo(x=..., shape=(..., 3))]", engine=engine)
+    kwargs = tools[0]["kwargs"]
+
+    assert kwargs["x"] == "Ellipsis"
+    assert kwargs["shape"] == {"Tuple": ["Ellipsis", {"Number": 3.0}]}
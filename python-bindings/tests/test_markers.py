@@ -0,0 +1,28 @@
+from llama_tool_parser_native import parse_tools
+import pytest
+
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_custom_markers(engine):
+    """Tool call blocks can be delimited by model-specific markers."""
+    code = 'Sure.<|tool_calls_begin|>[get_weather(city="SF")]<|tool_calls_end|> Done.'
+
+    tools = parse_tools(
+        code,
+        engine=engine,
+        start_marker="<|tool_calls_begin|>",
+        end_marker="<|tool_calls_end|>",
+    )
+
+    assert len(tools) == 1
+    assert tools[0]["name"] == "get_weather"
+    assert tools[0]["kwargs"]["city"] == {"String": "SF"}
+
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_default_markers_unchanged(engine):
+    code = '<|python_start|>[get_weather(city="SF")]<|python_end|>'
+
+    tools = parse_tools(code, engine=engine)
+
+    assert [tool["name"] for tool in tools] == ["get_weather"]
@@ -0,0 +1,19 @@
+from llama_tool_parser_native import parse_tools_all
+
+
+def test_parse_all_keeps_good_calls_around_malformed_one():
+    """A malformed call in the middle is reported in place, not raised."""
+    code = '[get_weather(city="SF"), broken(city=), get_time(zone="UTC")]'
+
+    results = parse_tools_all(code)
+
+    assert len(results) == 3
+    assert results[0]["name"] == "get_weather"
+    assert isinstance(results[1], ValueError)
+    assert "broken(city=)" in str(results[1])
+    assert results[2]["name"] == "get_time"
+    assert results[2]["kwargs"]["zone"] == {"String": "UTC"}
+
+
+def test_parse_all_no_calls():
+    assert parse_tools_all("How can I help you today?") == []
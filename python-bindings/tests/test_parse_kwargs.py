@@ -0,0 +1,18 @@
+import pytest
+from llama_tool_parser_native import parse_kwargs
+
+
+def test_parse_bare_kwargs():
+    kwargs = parse_kwargs('city="Tokyo", days=7')
+
+    assert kwargs == {"city": {"String": "Tokyo"}, "days": {"Number": 7.0}}
+
+
+def test_parse_parenthesized_kwargs():
+    assert parse_kwargs('(city="Tokyo")') == {"city": {"String": "Tokyo"}}
+    assert parse_kwargs("()") == {}
+
+
+def test_parse_kwargs_invalid():
+    with pytest.raises(ValueError):
+        parse_kwargs('city="Tokyo" days=7')
@@ -0,0 +1,81 @@
+import pytest
+from llama_tool_parser_native import IncrementalParser, parse_tools
+
+
+def names(tools):
+    return [tool["name"] for tool in tools]
+
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_allow_surrounding_text(engine):
+    source = "* [foo()] Anything else?"
+
+    assert names(parse_tools(source, engine=engine)) == ["foo"]
+    with pytest.raises(ValueError, match=r"Unexpected '\*'"):
+        parse_tools(source, engine=engine, allow_surrounding_text=False)
+
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_max_calls(engine):
+    source = "[a(), b(), c()]"
+
+    assert names(parse_tools(source, engine=engine, max_calls=2)) == ["a", "b"]
+    with pytest.raises(ValueError, match=r"Too many calls: 3 \(limit 2\)"):
+        parse_tools(source, engine=engine, max_calls=2, strict=True)
+
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_on_duplicate_kwarg(engine):
+    source = "[f(x=1, x=2)]"
+
+    assert parse_tools(source, engine=engine)[0]["kwargs"]["x"] == {"Number": 2.0}
+    first = parse_tools(source, engine=engine, on_duplicate_kwarg="first")
+    assert first[0]["kwargs"]["x"] == {"Number": 1.0}
+    with pytest.raises(ValueError, match=r"Duplicate argument x"):
+        parse_tools(source, engine=engine, on_duplicate_kwarg="error")
+    with pytest.raises(ValueError, match=r"Unsupported on_duplicate_kwarg: sometimes"):
+        parse_tools(source, engine=engine, on_duplicate_kwarg="sometimes")
+
+
+def test_recovery():
+    source = 'get_weather(city="NYC")'
+
+    assert names(parse_tools(source)) == ["get_weather"]
+    with pytest.raises(ValueError, match=r"No tool calls found"):
+        parse_tools(source, recovery=False)
+
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+@pytest.mark.parametrize("source", ["", "   \n "])
+def test_empty_input_has_no_calls(engine, source):
+    assert parse_tools(source, engine=engine) == []
+    assert parse_tools(source, engine=engine, strict=True) == []
+
+
+def test_allow_no_calls():
+    source = "How can I help you today?"
+
+    with pytest.raises(ValueError, match=r"No tool calls found"):
+        parse_tools(source)
+    assert parse_tools(source, allow_no_calls=True) == []
+
+
+def test_incremental_parser_options():
+    parser = IncrementalParser(
+        start_marker="<tool>",
+        end_marker="</tool>",
+        allowed_names=["a", "b"],
+        max_calls=1,
+        on_duplicate_kwarg="first",
+    )
+
+    calls = parser.parse_chunk("<tool>[skip(), a(x=1, x=2)")
+    calls += parser.parse_chunk(", b()]</tool>")
+
+    assert names(calls) == ["a"]
+    assert calls[0]["kwargs"]["x"] == {"Number": 1.0}
+
+
+def test_incremental_parser_options_require_nom_engine():
+    with pytest.raises(ValueError, match=r"not supported by engine: logos"):
+        IncrementalParser(engine="logos", max_calls=1)
@@ -0,0 +1,13 @@
+import pytest
+from llama_tool_parser_native import parse_tools
+
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_set_literal_serializes_sorted(engine):
+    """Set literals come back as sorted lists, since sets have no order."""
+    tools = parse_tools('[tag_items(labels={"beta", "alpha"}, ids={3, 1, 2})]', engine=engine)
+
+    assert tools[0]["kwargs"]["labels"] == {"Set": [{"String": "alpha"}, {"String": "beta"}]}
+    assert tools[0]["kwargs"]["ids"] == {
+        "Set": [{"Number": 1.0}, {"Number": 2.0}, {"Number": 3.0}]
+    }
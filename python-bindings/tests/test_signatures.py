@@ -0,0 +1,50 @@
+import importlib.util
+from pathlib import Path
+
+import pytest
+
+import llama_tool_parser_native
+from llama_tool_parser_native import IncrementalParser
+
+BINDINGS = Path(__file__).parent.parent
+
+
+@pytest.mark.parametrize("name", llama_tool_parser_native.__all__)
+def test_exports_have_text_signatures(name):
+    assert getattr(llama_tool_parser_native, name).__text_signature__
+
+
+def test_incremental_parser_methods_have_text_signatures():
+    methods = [
+        name
+        for name, member in vars(IncrementalParser).items()
+        if not name.startswith("_") and callable(member)
+    ]
+
+    assert "parse_chunk" in methods
+    for name in methods:
+        assert getattr(IncrementalParser, name).__text_signature__, name
+
+
+def test_optional_arguments_are_visible():
+    signature = llama_tool_parser_native.parse_tools.__text_signature__
+
+    assert "engine=None" in signature
+    assert "strict=False" in signature
+
+
+def test_stub_is_typed():
+    stub = (BINDINGS / "llama_tool_parser_native" / "llama_tool_parser_native.pyi").read_text()
+
+    assert "-> Any" not in stub
+    assert "def parse_tools(source: str, engine: str | None = None," in stub
+    assert "def feed_iter(self, chunks: Iterable[str]) -> FeedIter: ..." in stub
+    assert "class FeedIter:" in stub
+
+
+def test_stub_is_up_to_date():
+    spec = importlib.util.spec_from_file_location("generate_stubs", BINDINGS / "generate_stubs.py")
+    generate_stubs = importlib.util.module_from_spec(spec)
+    spec.loader.exec_module(generate_stubs)
+
+    assert generate_stubs.STUB_PATH.read_text() == generate_stubs.render()
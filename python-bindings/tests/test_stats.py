@@ -0,0 +1,21 @@
+import pytest
+from llama_tool_parser_native import parse_tools
+
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_collect_stats_returns_tuple(engine):
+    tools, stats = parse_tools(
+        '[get_weather(location="Tokyo"), get_time()]', engine=engine, collect_stats=True
+    )
+
+    assert [tool["name"] for tool in tools] == ["get_weather", "get_time"]
+    assert stats["engine"] == engine
+    assert stats["function_count"] == 2
+    assert stats["token_count"] > 0
+    assert stats["parse_duration_us"] >= 0
+
+
+def test_stats_off_by_default():
+    tools = parse_tools("[get_time()]", engine="nom")
+
+    assert isinstance(tools, list)
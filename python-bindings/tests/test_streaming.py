@@ -1,11 +1,15 @@
 #!/usr/bin/env python3
-"""Test streaming functionality for the nom parser."""
+"""Test streaming functionality for both parser engines."""
 
+import json
+import pytest
+from unittest.mock import Mock
 from llama_tool_parser_native import IncrementalParser
 
-def test_incremental_parser():
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_incremental_parser(engine):
     """Test the incremental parser with chunks."""
-    parser = IncrementalParser()
+    parser = IncrementalParser(engine=engine)
     
     # test partial parsing with chunks
     chunk1 = "<|python_start|>["
@@ -49,6 +53,408 @@ def test_incremental_parser():
     assert final_functions[1]["kwargs"]["filename"]["String"] == "test.txt"
     assert final_functions[1]["kwargs"]["content"]["String"] == "hello"
 
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_is_complete(engine):
+    """is_complete() reports whether the buffered input is balanced."""
+    parser = IncrementalParser(engine=engine)
+    assert parser.is_complete()
+
+    parser.parse_chunk("[foo(")
+    assert not parser.is_complete()
+
+    parser.parse_chunk('bar="it\'s ) fine"')
+    assert not parser.is_complete()
+
+    parser.parse_chunk(")]")
+    assert parser.is_complete()
+
+def test_incremental_engines_match():
+    """Both engines report the same calls after every chunk."""
+    chunks = [
+        "<|python_start|>[",
+        'search(query="test", limit=5',
+        "), write_file(",
+        'filename="test.txt", content="hello"',
+        ")]<|python_end|>",
+    ]
+    nom = IncrementalParser()
+    logos = IncrementalParser(engine="logos")
+
+    for chunk in chunks:
+        assert nom.parse_chunk(chunk) == logos.parse_chunk(chunk)
+
+    assert len(logos.get_parsed_functions()) == 2
+    assert nom.finish() == logos.finish()
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_parse_chunk_returns_deltas(engine):
+    """parse_chunk reports each call once, even when an identical call repeats."""
+    parser = IncrementalParser(engine=engine)
+
+    assert [t["name"] for t in parser.parse_chunk("[ping()]")] == ["ping"]
+    assert parser.parse_chunk(" and again [ping(") == []
+    assert [t["name"] for t in parser.parse_chunk(")]")] == ["ping"]
+    assert len(parser.get_parsed_functions()) == 2
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_repeated_identical_calls_in_one_list(engine):
+    """Identical calls in one list are both reported."""
+    parser = IncrementalParser(engine=engine)
+
+    calls = []
+    for chunk in ["[roll_dice(sides=6), ", "roll_dice(sides=6)", "]"]:
+        calls += parser.parse_chunk(chunk)
+
+    assert [call["name"] for call in calls] == ["roll_dice", "roll_dice"]
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_parse_chunk_cumulative(engine):
+    """parse_chunk_cumulative keeps the old behavior of returning every call so far."""
+    parser = IncrementalParser(engine=engine)
+
+    assert len(parser.parse_chunk_cumulative("[a()]")) == 1
+    assert [t["name"] for t in parser.parse_chunk_cumulative(" [b()]")] == ["a", "b"]
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_delta_false_is_cumulative_and_deprecated(engine):
+    with pytest.warns(DeprecationWarning, match="delta=False"):
+        parser = IncrementalParser(engine, delta=False)
+
+    assert [t["name"] for t in parser.parse_chunk("[a()]")] == ["a"]
+    assert [t["name"] for t in parser.parse_chunk(" [a()]")] == ["a", "a"]
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_pending_and_drain(engine):
+    """drain() returns each call once, pending() every call so far."""
+    parser = IncrementalParser(engine)
+    chunks = ["[ping(), pi", "ng()] and [pi", "ng(", ")]"]
+
+    drained = []
+    for chunk in chunks:
+        parser.parse_chunk(chunk)
+        drained.append([t["name"] for t in parser.drain()])
+
+    assert drained == [[], ["ping", "ping"], [], ["ping"]]
+    assert [t["name"] for t in parser.pending()] == ["ping", "ping", "ping"]
+    assert parser.drain() == []
+
+    parser.reset()
+    assert parser.parse_chunk("[pong()]") == parser.drain()
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_iterating_the_parser(engine):
+    parser = IncrementalParser(engine)
+
+    parser.parse_chunk("[a(), b()] [a(")
+    assert [t["name"] for t in parser] == ["a", "b"]
+    parser.parse_chunk(")]")
+    assert [t["name"] for t in parser] == ["a"]
+    assert list(parser) == []
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_feed_iter(engine):
+    """feed_iter yields each call once as its list closes, then any truncated call."""
+    chunks = ["Sure. [get_weather(", 'city="SF"), get_weather(', 'city="SF")] ', "[ping(", ")] [late("]
+    consumed = []
+
+    def stream():
+        for chunk in chunks:
+            consumed.append(chunk)
+            yield chunk
+
+    parser = IncrementalParser(engine)
+    calls = []
+    for call in parser.feed_iter(stream()):
+        calls.append((call, len(consumed)))
+
+    # Each call is yielded as soon as the chunk completing it has been fed
+    assert [(call["name"], fed) for call, fed in calls] == [
+        ("get_weather", 3),
+        ("get_weather", 3),
+        ("ping", 5),
+        ("late", 5),
+    ]
+    # The same call twice, told apart by where in the stream each was made
+    first, second = calls[0][0], calls[1][0]
+    assert first["kwargs"] == second["kwargs"]
+    assert first["span"] == {"start": 7, "end": 29}
+    assert second["span"] == {"start": 31, "end": 53}
+    assert parser.is_truncated()
+    assert parser.pending() == [call for call, _ in calls]
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+@pytest.mark.parametrize("with_hooks", [False, True])
+def test_shared_between_threads(engine, with_hooks):
+    """Threads feeding one parser get every call exactly once, hooks or not."""
+    import threading
+
+    threads, calls_per_thread = 8, 200
+    started = []
+    parser = IncrementalParser(
+        engine, on_call_start=started.append if with_hooks else None
+    )
+    returned = [[] for _ in range(threads)]
+
+    def feed(thread):
+        for i in range(calls_per_thread):
+            # Each chunk is a whole list, so interleaving chunks can't split a call
+            for call in parser.parse_chunk(f"[call(thread={thread}, i={i})] "):
+                returned[thread].append(call)
+
+    workers = [threading.Thread(target=feed, args=(t,)) for t in range(threads)]
+    for worker in workers:
+        worker.start()
+    for worker in workers:
+        worker.join()
+
+    seen = [
+        (call["kwargs"]["thread"]["Number"], call["kwargs"]["i"]["Number"])
+        for calls in returned
+        for call in calls
+    ]
+    expected = {(t, i) for t in range(threads) for i in range(calls_per_thread)}
+    assert len(seen) == len(expected)
+    assert set(seen) == expected
+    assert len(parser.pending()) == len(expected)
+    assert len(parser.drain()) == len(expected)
+    if with_hooks:
+        assert len(started) == len(expected)
+
+def test_incremental_unknown_engine():
+    with pytest.raises(ValueError):
+        IncrementalParser(engine="regex")
+
+def test_max_buffer_size():
+    parser = IncrementalParser(max_buffer_size=64)
+
+    parser.parse_chunk("prose " * 100)
+    parser.parse_chunk('[write(text="')
+    with pytest.raises(ValueError):
+        parser.parse_chunk("x" * 100)
+
+    # The oversized input is discarded and parsing carries on
+    assert [tool["name"] for tool in parser.parse_chunk("[ping()]")] == ["ping"]
+
+def test_max_buffer_size_requires_nom_engine():
+    with pytest.raises(ValueError):
+        IncrementalParser("logos", max_buffer_size=64)
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_finish_completes_truncated_call(engine):
+    parser = IncrementalParser(engine=engine)
+
+    assert parser.parse_chunk('[get_weather(city="SF", days=') == []
+    tools = parser.finish()
+
+    assert [tool["name"] for tool in tools] == ["get_weather"]
+    assert tools[0]["kwargs"]["city"] == {"String": "SF"}
+    assert parser.is_truncated()
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_flush_returns_only_the_truncated_call(engine):
+    parser = IncrementalParser(engine=engine)
+
+    assert [t["name"] for t in parser.parse_chunk("[ping()] ")] == ["ping"]
+    assert parser.parse_chunk('[func(city="Tokyo"') == []
+    tools = parser.flush()
+
+    assert [tool["name"] for tool in tools] == ["func"]
+    assert tools[0]["kwargs"] == {"city": {"String": "Tokyo"}}
+    assert [tool["name"] for tool in parser.get_parsed_functions()] == ["ping", "func"]
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_status_after_each_chunk(engine):
+    parser = IncrementalParser(engine)
+    # (chunk, inside block, current call, pending input) after each chunk
+    steps = [
+        ("Sure. ", False, None, False),
+        ("<|python_start|>[get_weather(", True, "get_weather", True),
+        ('city="SF"', True, "get_weather", True),
+        (")]", True, None, False),
+        ("<|python_end|>", False, None, False),
+    ]
+
+    for chunk, inside_block, call, pending in steps:
+        parser.parse_chunk(chunk)
+        assert parser.is_inside_python_block() == inside_block, chunk
+        assert parser.current_call_name() == call, chunk
+        assert parser.is_inside_call() == (call is not None), chunk
+        assert parser.has_pending_input() == pending, chunk
+        assert parser.is_complete() == (not pending), chunk
+
+def test_parse_chunk_events():
+    parser = IncrementalParser(events=True)
+    chunks = ["[get_weather(", 'city="SF", ', "days=3)", ", ping()]"]
+
+    events = []
+    for chunk in chunks:
+        events.extend(parser.parse_chunk_events(chunk))
+
+    assert events[0] == {"type": "CallStarted", "index": 0, "name": "get_weather"}
+    for index, name in [(0, "get_weather"), (1, "ping")]:
+        arguments = "".join(
+            event["json_fragment"]
+            for event in events
+            if event["type"] == "ArgumentsDelta" and event["index"] == index
+        )
+        assert json.loads(arguments) == ({"city": "SF", "days": 3} if index == 0 else {})
+        assert {"type": "CallFinished", "index": index} in events
+
+def test_events_disabled_by_default():
+    parser = IncrementalParser()
+    parser.parse_chunk("[ping()]")
+
+    assert parser.drain_events() == []
+
+def test_current_partial():
+    parser = IncrementalParser()
+    # (chunk, expected partial) after each chunk
+    steps = [
+        ("[get_weather(", {"name": "get_weather", "kwargs": {}}),
+        ('city="SF", da', {"name": "get_weather", "kwargs": {"city": {"String": "SF"}}}),
+        (
+            "ys=3",
+            {"name": "get_weather", "kwargs": {"city": {"String": "SF"}}},
+        ),
+        (
+            ")",
+            {
+                "name": "get_weather",
+                "kwargs": {"city": {"String": "SF"}, "days": {"Number": 3.0}},
+            },
+        ),
+        ("]", None),
+    ]
+
+    for chunk, partial in steps:
+        parser.parse_chunk(chunk)
+        assert parser.current_partial() == partial, chunk
+
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_current_partial_function_property(engine):
+    parser = IncrementalParser(engine)
+    assert parser.current_partial_function is None
+
+    parser.parse_chunk('[func(city="Tokyo",')
+
+    if engine == "nom":
+        expected = {"name": "func", "kwargs": {"city": {"String": "Tokyo"}}}
+    else:
+        # The logos engine only sees whole calls
+        expected = None
+    assert parser.current_partial_function == expected
+    assert parser.current_partial_function == parser.current_partial()
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_parse_chunk_argument_events(engine):
+    parser = IncrementalParser(engine)
+    chunks = ["[get_weather(", 'city="SF", ', "days=3), ", "ping()]"]
+
+    events = []
+    for chunk in chunks:
+        events.extend(parser.parse_chunk_argument_events(chunk))
+
+    assert [event["type"] for event in events] == [
+        "FunctionStarted",
+        "ArgumentParsed",
+        "ArgumentParsed",
+        "FunctionCompleted",
+        "FunctionStarted",
+        "FunctionCompleted",
+    ]
+    assert events[1] == {
+        "type": "ArgumentParsed",
+        "name": "get_weather",
+        "key": "city",
+        "value": {"String": "SF"},
+    }
+    assert events[3] == {"type": "FunctionCompleted", "index": 0}
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_event_callbacks(engine):
+    seen = []
+    parser = IncrementalParser(
+        engine,
+        on_call_start=lambda name: seen.append(("start", name)),
+        on_kwarg=lambda name, key, value: seen.append(("kwarg", name, key, value)),
+        on_call_complete=lambda call: seen.append(("complete", call["name"])),
+        on_block_end=lambda: seen.append(("block end",)),
+    )
+    chunks = ["<|python_start|>[get_weather(", 'city="SF"), ping()]', "<|python_end|>"]
+
+    for chunk in chunks:
+        parser.parse_chunk(chunk)
+
+    assert seen == [
+        ("start", "get_weather"),
+        ("kwarg", "get_weather", "city", {"String": "SF"}),
+        ("complete", "get_weather"),
+        ("start", "ping"),
+        ("complete", "ping"),
+        ("block end",),
+    ]
+
+def test_event_callbacks_can_call_back_into_the_parser():
+    seen = []
+    parser = IncrementalParser(
+        on_kwarg=lambda name, key, value: seen.append(
+            (key, parser.current_partial(), len(parser.get_parsed_functions()))
+        ),
+    )
+
+    assert [call["name"] for call in parser.parse_chunk("[f(a=1, b=2)]")] == ["f"]
+    # The hooks run once the chunk has been parsed
+    assert seen == [("a", None, 1), ("b", None, 1)]
+
+def test_event_callback_errors_are_raised():
+    def fail(name):
+        raise RuntimeError("hook failed for " + name)
+
+    parser = IncrementalParser(on_call_start=fail)
+
+    with pytest.raises(RuntimeError, match="hook failed for ping"):
+        parser.parse_chunk("[ping()]")
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_function_complete_callback(engine):
+    callback = Mock()
+    parser = IncrementalParser(engine, callback=callback)
+
+    for chunk in ["Sure. [get_weather(", 'city="SF"), pi', "ng()] then ", "[late("]:
+        parser.parse_chunk(chunk)
+    assert [c.args[0]["name"] for c in callback.call_args_list] == ["get_weather", "ping"]
+
+    # Calls completed by `finish` are passed too, and each call only once
+    parser.finish()
+    assert [c.args[0]["name"] for c in callback.call_args_list] == ["get_weather", "ping", "late"]
+    assert callback.call_args_list[0].args[0]["kwargs"] == {"city": {"String": "SF"}}
+
+def test_function_complete_callback_errors_are_raised():
+    callback = Mock(side_effect=RuntimeError("callback failed"))
+    parser = IncrementalParser(callback=callback)
+
+    with pytest.raises(RuntimeError, match="callback failed"):
+        parser.parse_chunk("[ping(), pong()]")
+    callback.assert_called_once()
+    assert [call["name"] for call in parser.get_parsed_functions()] == ["ping", "pong"]
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_parse_chunk_tracked(engine):
+    parser = IncrementalParser(engine)
+
+    assert parser.parse_chunk_tracked("[get_weather(") == []
+    first = parser.parse_chunk_tracked('city="SF"), ping()] then ')
+    second = parser.parse_chunk_tracked("[search()]")
+
+    assert [(c["index"], c["id"], c["name"]) for c in first + second] == [
+        (0, "call_0", "get_weather"),
+        (1, "call_1", "ping"),
+        (2, "call_2", "search"),
+    ]
+    assert first[0]["kwargs"] == {"city": {"String": "SF"}}
+    assert parser.get_tracked_functions() == first + second
+
 def test_streaming_parser():
     """Test streaming with the pythonic parser."""
     from pythonic_parser import NativePythonicToolParser
@@ -132,6 +538,25 @@ def test_streaming_parser():
     assert '"filename": "test.txt"' in tool_calls[1].function.arguments
     assert '"content": "hello"' in tool_calls[1].function.arguments
 
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_repr_and_str(engine):
+    """repr() shows the parser's state at a glance, and str() describes it in words."""
+    parser = IncrementalParser(engine=engine)
+    assert repr(parser) == (
+        f"IncrementalParser(engine='{engine}', parsed=0, in_block=False, pending_input=False)"
+    )
+    assert str(parser) == f"{engine} parser with 0 calls parsed"
+
+    parser.parse_chunk('<|python_start|>[ping()]<|python_end|> <|python_start|>[get_weather(city="SF"')
+    assert repr(parser) == (
+        f"IncrementalParser(engine='{engine}', parsed=1, in_block=True, pending_input=True)"
+    )
+    assert str(parser) == f"{engine} parser with 1 call parsed, in the middle of a call to get_weather"
+
+    parser.parse_chunk(")]")
+    assert str(parser) == f"{engine} parser with 2 calls parsed, inside a python block"
+
+
 if __name__ == "__main__":
     test_incremental_parser()
     test_streaming_parser()
\ No newline at end of file
@@ -0,0 +1,25 @@
+import pytest
+from llama_tool_parser_native import parse_tools
+
+
+def test_strict_rejects_trailing_garbage():
+    with pytest.raises(ValueError, match=r"^Parse error at 1:9: Trailing garbage at 8\.\.11: xyz$"):
+        parse_tools("[foo()] xyz", engine="nom", strict=True)
+
+
+def test_lenient_ignores_trailing_garbage():
+    tools = parse_tools("[foo()] xyz", engine="nom")
+
+    assert [tool["name"] for tool in tools] == ["foo"]
+
+
+def test_strict_accepts_clean_input():
+    tools = parse_tools("<|python_start|>[foo()]<|python_end|>", engine="nom", strict=True)
+
+    assert [tool["name"] for tool in tools] == ["foo"]
+
+
+def test_strict_logos_rejects_unreadable_input():
+    assert [tool["name"] for tool in parse_tools("[foo(x=1 @)]", engine="logos")] == ["foo"]
+    with pytest.raises(ValueError, match=r"unrecognised input '@'"):
+        parse_tools("[foo(x=1 @)]", engine="logos", strict=True)
@@ -0,0 +1,36 @@
+import pytest
+
+from llama_tool_parser_native import filter_kwargs, parse_tools, rename_kwargs
+
+
+def test_rename_kwargs():
+    [call] = parse_tools('[get_weather(city="Tokyo", days=7)]')
+
+    renamed = rename_kwargs(call, {"city": "location", "unit": "units"})
+
+    assert renamed == {
+        "name": "get_weather",
+        "kwargs": {"location": {"String": "Tokyo"}, "days": {"Number": 7.0}},
+        "span": {"start": 1, "end": 34},
+    }
+    assert "city" in call["kwargs"]
+
+
+def test_filter_kwargs():
+    [call] = parse_tools('[get_weather(city="Tokyo", days=7, debug=True)]')
+
+    filtered = filter_kwargs(call, {"city", "days", "units"})
+
+    assert filtered["name"] == "get_weather"
+    assert set(filtered["kwargs"]) == {"city", "days"}
+
+
+@pytest.mark.parametrize("value", [{"Bytes": [256]}, {"NotAValue": 1}, {"Number": "seven"}])
+def test_unconvertible_value_raises(value):
+    """A value that can't be converted raises ValueError instead of crashing the interpreter."""
+    call = {"name": "f", "kwargs": {"x": value}}
+
+    with pytest.raises(ValueError):
+        rename_kwargs(call, {})
+    with pytest.raises(ValueError):
+        filter_kwargs(call, {"x"})
@@ -0,0 +1,14 @@
+import pytest
+from llama_tool_parser_native import parse_tools
+
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_tuple_literals(engine):
+    """Tuples are tagged apart from lists; `(x)` without a comma is just `x`."""
+    tools = parse_tools("[locate(coords=(40.7, -74.0), pair=(1,), empty=(), grouped=(5))]", engine=engine)
+    kwargs = tools[0]["kwargs"]
+
+    assert kwargs["coords"] == {"Tuple": [{"Number": 40.7}, {"Number": -74.0}]}
+    assert kwargs["pair"] == {"Tuple": [{"Number": 1.0}]}
+    assert kwargs["empty"] == {"Tuple": []}
+    assert kwargs["grouped"] == {"Number": 5.0}
@@ -0,0 +1,39 @@
+import pytest
+from llama_tool_parser_native import parse_tools, validate_tool_call
+
+WEATHER_TOOL = {
+    "type": "function",
+    "function": {
+        "name": "get_weather",
+        "parameters": {
+            "type": "object",
+            "properties": {
+                "city": {"type": "string"},
+                "days": {"type": "integer"},
+            },
+            "required": ["city"],
+        },
+    },
+}
+
+
+@pytest.mark.parametrize("engine", ["nom", "logos"])
+def test_valid_call(engine):
+    [call] = parse_tools('[get_weather(city="SF", days=3)]', engine=engine)
+
+    assert validate_tool_call(call, WEATHER_TOOL) == []
+
+
+def test_invalid_call():
+    [call] = parse_tools('[get_weather(days=1.5, units="C")]', engine="nom")
+
+    assert validate_tool_call(call, WEATHER_TOOL) == [
+        "missing required field 'city'",
+        "field 'days' should be integer, got number",
+        "unknown field 'units'",
+    ]
+
+
+def test_malformed_call_raises():
+    with pytest.raises(ValueError):
+        validate_tool_call({"kwargs": {}}, WEATHER_TOOL)
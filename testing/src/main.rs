@@ -1,6 +1,7 @@
 #[cfg(test)]
+#[allow(clippy::items_after_test_module)]
 mod tests {
-    use backend::{Value, parse_python};
+    use backend::{Value, describe_error, parse_python};
 
     #[test]
     fn test_basic() {
@@ -10,7 +11,7 @@ mod tests {
 
         let source = r#"[
     get_weather_forecast(location="Tokyo", days=7),search_hotels(location="Shinjuku", check_in_date="2024-05-20", check_out_date="2024-05-27", budget_max_per_night=50.0, guest_count=2),
-    get_attractions(location="Tokyo", count=3, category="all"),
+    get_attractions(location="Tokyo", categories=["food", "art"], count=3, category="all"),
     convert_currency(amount=1000, from_currency="USD", to_currency="JPY")
     ]"#;
 
@@ -25,7 +26,7 @@ mod tests {
                 assert_eq!(function_calls[3].name, "convert_currency");
                 assert_eq!(function_calls[0].kwargs.len(), 2);
                 assert_eq!(function_calls[1].kwargs.len(), 5);
-                assert_eq!(function_calls[2].kwargs.len(), 3);
+                assert_eq!(function_calls[2].kwargs.len(), 4);
                 assert_eq!(function_calls[3].kwargs.len(), 3);
                 assert_eq!(
                     function_calls[0].kwargs.get("location"),
@@ -39,14 +40,88 @@ mod tests {
                     function_calls[2].kwargs.get("location"),
                     Some(&Value::String("Tokyo".to_string()))
                 );
+                assert_eq!(
+                    function_calls[2].kwargs.get("categories"),
+                    Some(&Value::List(vec![
+                        Value::String("food".to_string()),
+                        Value::String("art".to_string()),
+                    ]))
+                );
+                assert_eq!(
+                    function_calls[2].kwargs.get("count"),
+                    Some(&Value::Number(3.0))
+                );
             }
-            Err((msg, span)) => {
-                let error_message =
-                    format!("Error at position {}-{}: {}", span.start, span.end, msg);
-                tracing::error!("{}", error_message);
+            Err(err) => {
+                tracing::error!("{}", describe_error(source, &err));
             }
         }
     }
+
+    #[test]
+    fn test_nested_list_kwargs() {
+        let source = r#"[plot(points=[[1, 2], [3, 4]], labels=[], title="grid"), run_benchmark(test_cases=[create_test(size=100)], iterations=5)]"#;
+
+        let function_calls = parse_python(source).unwrap();
+
+        assert_eq!(function_calls.len(), 2);
+        assert_eq!(
+            function_calls[0].kwargs.get("points"),
+            Some(&Value::List(vec![
+                Value::List(vec![Value::Number(1.0), Value::Number(2.0)]),
+                Value::List(vec![Value::Number(3.0), Value::Number(4.0)]),
+            ]))
+        );
+        assert_eq!(
+            function_calls[0].kwargs.get("labels"),
+            Some(&Value::List(vec![]))
+        );
+        assert_eq!(
+            function_calls[0].kwargs.get("title"),
+            Some(&Value::String("grid".to_string()))
+        );
+
+        assert_eq!(function_calls[1].name, "run_benchmark");
+        assert_eq!(
+            function_calls[1].kwargs.get("iterations"),
+            Some(&Value::Number(5.0))
+        );
+        match function_calls[1].kwargs.get("test_cases") {
+            Some(Value::List(items)) => match &items[..] {
+                [Value::FunctionCall(call)] => {
+                    assert_eq!(call.name, "create_test");
+                    assert_eq!(call.kwargs.get("size"), Some(&Value::Number(100.0)));
+                }
+                other => panic!("unexpected test_cases items: {:?}", other),
+            },
+            other => panic!("unexpected test_cases value: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_multiple_python_blocks() {
+        let source =
+            r#"<|python_start|>[func1()]<|python_end|><|python_start|>[func2(x=1)]<|python_end|>"#;
+
+        let function_calls = parse_python(source).unwrap();
+
+        let names: Vec<&str> = function_calls.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["func1", "func2"]);
+    }
+
+    #[test]
+    fn test_python_block_after_truncated_block() {
+        // The first block is cut off mid-call; the second block must still be found
+        let source =
+            r#"<|python_start|>[func1(x=1<|python_end|><|python_start|>[func2(y=2)]<|python_end|>"#;
+
+        let function_calls = parse_python(source).unwrap();
+
+        let names: Vec<&str> = function_calls.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["func1", "func2"]);
+        assert_eq!(function_calls[0].kwargs.get("x"), Some(&Value::Number(1.0)));
+        assert_eq!(function_calls[1].kwargs.get("y"), Some(&Value::Number(2.0)));
+    }
 }
 
 fn main() {
@@ -0,0 +1,112 @@
+use backend::nom_parser::NomParserState;
+use backend::{FunctionCall, IncrementalBackend, LogosParserState, describe_error, parse_python};
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+// Kwargs are a HashMap, which serde-wasm-bindgen would turn into a JS `Map` by default;
+// the JSON-compatible serializer produces plain objects instead.
+fn to_js<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, JsError> {
+    value
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|err| JsError::new(&format!("Failed to serialize: {}", err)))
+}
+
+/// Parse the tool calls in `source`, returning an array of `{ name, kwargs }` objects.
+#[wasm_bindgen]
+pub fn parse_tools(source: &str) -> Result<JsValue, JsError> {
+    match parse_python(source) {
+        Ok(function_calls) => to_js(&function_calls),
+        Err(err) => Err(JsError::new(&describe_error(source, &err))),
+    }
+}
+
+/// Like `parse_tools`, but returns the calls as a JSON string, e.g. to hand them to a
+/// worker or store them without going through JS objects.
+#[wasm_bindgen]
+pub fn parse_tools_wasm(source: &str) -> Result<String, JsError> {
+    match parse_python(source) {
+        Ok(function_calls) => serde_json::to_string(&function_calls)
+            .map_err(|err| JsError::new(&format!("Failed to serialize: {}", err))),
+        Err(err) => Err(JsError::new(&describe_error(source, &err))),
+    }
+}
+
+#[wasm_bindgen]
+pub struct IncrementalParser {
+    state: Box<dyn IncrementalBackend>,
+}
+
+#[wasm_bindgen]
+impl IncrementalParser {
+    /// Create a parser for the given engine, `"nom"` (the default) or `"logos"`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(engine: Option<String>) -> Result<IncrementalParser, JsError> {
+        let state: Box<dyn IncrementalBackend> = match engine.as_deref().unwrap_or("nom") {
+            "nom" => Box::new(NomParserState::new()),
+            "logos" => Box::new(LogosParserState::new()),
+            other => return Err(JsError::new(&format!("Unsupported engine: {}", other))),
+        };
+        Ok(Self { state })
+    }
+
+    /// Add a chunk and return only the calls it completed.
+    pub fn parse_chunk(&mut self, chunk: &str) -> Result<JsValue, JsError> {
+        match self.state.feed(chunk) {
+            Ok(function_calls) => to_js(&function_calls),
+            Err(err) => Err(JsError::new(&format!("Parse error: {}", err))),
+        }
+    }
+
+    pub fn finish(&mut self) -> Result<JsValue, JsError> {
+        match self.state.finish() {
+            Ok(function_calls) => to_js(&function_calls),
+            Err(err) => Err(JsError::new(&format!("Parse error: {}", err))),
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.state.is_complete()
+    }
+
+    /// Whether `finish` had to complete a call cut off by the end of the stream.
+    pub fn is_truncated(&self) -> bool {
+        self.state.is_truncated()
+    }
+
+    pub fn is_inside_python_block(&self) -> bool {
+        self.state.is_inside_python_block()
+    }
+
+    pub fn is_inside_call(&self) -> bool {
+        self.state.current_call_name().is_some()
+    }
+
+    pub fn current_call_name(&self) -> Option<String> {
+        self.state.current_call_name().map(str::to_string)
+    }
+
+    pub fn has_pending_input(&self) -> bool {
+        self.state.has_pending_input()
+    }
+
+    /// The call whose arguments are still arriving, as `{ name, kwargs }` with the kwargs
+    /// completed so far, or `undefined`. The logos engine only sees whole calls.
+    pub fn current_partial(&self) -> Result<JsValue, JsError> {
+        match self.state.current_partial() {
+            Some(partial) => to_js(&FunctionCall {
+                name: partial.name.clone(),
+                kwargs: partial.kwargs.clone(),
+                span: None,
+            }),
+            None => Ok(JsValue::UNDEFINED),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.state.reset();
+    }
+
+    pub fn get_parsed_functions(&self) -> Result<JsValue, JsError> {
+        to_js(self.state.parsed())
+    }
+}
@@ -0,0 +1,22 @@
+//! Runs the bindings on the host, checking they build and work without pyo3 or a browser
+#![cfg(not(target_arch = "wasm32"))]
+
+use llama_tool_parser_wasm::parse_tools_wasm;
+
+#[test]
+fn test_parse_tools_wasm() {
+    let json = parse_tools_wasm(r#"Sure. [get_weather(city="SF", days=3), ping()]"#).unwrap();
+
+    let calls: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        calls,
+        serde_json::json!([
+            {
+                "name": "get_weather",
+                "kwargs": {"city": {"String": "SF"}, "days": {"Number": 3.0}},
+                "span": {"start": 7, "end": 37}
+            },
+            {"name": "ping", "kwargs": {}, "span": {"start": 39, "end": 45}}
+        ])
+    );
+}
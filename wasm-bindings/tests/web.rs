@@ -0,0 +1,45 @@
+//! Browser tests, run with `wasm-pack test --headless --chrome wasm-bindings`
+#![cfg(target_arch = "wasm32")]
+
+use js_sys::{Array, Reflect};
+use llama_tool_parser_wasm::{IncrementalParser, parse_tools, parse_tools_wasm};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::*;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+fn get(target: &JsValue, key: &str) -> JsValue {
+    Reflect::get(target, &JsValue::from_str(key)).unwrap()
+}
+
+#[wasm_bindgen_test]
+fn test_parse_tools() {
+    let result = Array::from(&parse_tools("[func(x=1)]").unwrap());
+
+    assert_eq!(result.length(), 1);
+    assert_eq!(get(&result.get(0), "name"), "func");
+    let x = get(&get(&result.get(0), "kwargs"), "x");
+    assert_eq!(get(&x, "Number"), 1.0);
+}
+
+#[wasm_bindgen_test]
+fn test_parse_tools_wasm() {
+    assert_eq!(
+        parse_tools_wasm("[func(x=1)]").unwrap(),
+        r#"[{"name":"func","kwargs":{"x":{"Number":1.0}},"span":{"start":1,"end":10}}]"#
+    );
+}
+
+#[wasm_bindgen_test]
+fn test_incremental_parser() {
+    let mut parser = IncrementalParser::new(None).unwrap();
+
+    let partial = Array::from(&parser.parse_chunk("[func(").unwrap());
+    assert_eq!(partial.length(), 0);
+    assert!(!parser.is_complete());
+
+    let complete = Array::from(&parser.parse_chunk("x=1)]").unwrap());
+    assert_eq!(complete.length(), 1);
+    assert_eq!(get(&complete.get(0), "name"), "func");
+    assert!(parser.is_complete());
+}